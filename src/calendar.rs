@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, ensure};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike, Weekday};
+
+/// 国民の祝日の一覧 (`日付,名称`)。振替休日・国民の休日を含む
+const HOLIDAYS_CSV: &str = include_str!("holidays.csv");
+
+/// 祝日の一覧に含まれる最初の年
+pub const FIRST_YEAR: i32 = 2019;
+/// 祝日の一覧に含まれる最後の年
+pub const LAST_YEAR: i32 = 2027;
+
+/// 日付ごとの祝日の名称
+static HOLIDAYS: LazyLock<HashMap<NaiveDate, &'static str>> = LazyLock::new(|| {
+    HOLIDAYS_CSV
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (date, name) = line.split_once(',').expect("祝日の一覧の形式");
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").expect("祝日の一覧の日付");
+            (date, name)
+        })
+        .collect()
+});
+
+/// 指定した日が祝日のときは、その名称を返す
+pub fn holiday(date: NaiveDate) -> Option<&'static str> {
+    HOLIDAYS.get(&date).copied()
+}
+
+/// 曜日による絞り込み
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DayOfWeek {
+    /// 平日 (月〜金曜日)
+    Weekdays,
+    /// 土日
+    Weekends,
+}
+
+/// 祝日による絞り込み
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Holidays {
+    /// 祝日を除く
+    Exclude,
+    /// 祝日のみ
+    Only,
+}
+
+/// 取得対象の時間帯の絞り込み条件。日時は日本標準時
+///
+/// 条件を複数指定したときは、すべてを満たす時間帯を対象とする。
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SlotFilter {
+    /// 曜日。None のときは絞り込まない
+    pub day_of_week: Option<DayOfWeek>,
+    /// 時間帯 (開始の時, 終了の時)。終了の時を含まない。空のときは絞り込まない
+    pub hours: Vec<(u32, u32)>,
+    /// 祝日。None のときは絞り込まない
+    pub holidays: Option<Holidays>,
+}
+
+impl SlotFilter {
+    /// 指定した時間帯の開始日時が、条件を満たすかどうか
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        let weekend = matches!(time.weekday(), Weekday::Sat | Weekday::Sun);
+        let day_of_week = match self.day_of_week {
+            Some(DayOfWeek::Weekdays) => !weekend,
+            Some(DayOfWeek::Weekends) => weekend,
+            None => true,
+        };
+
+        let hour = time.hour();
+        let hours = self.hours.is_empty()
+            || self.hours.iter().any(|&(start, end)| {
+                if start < end {
+                    start <= hour && hour < end
+                } else {
+                    // 日付をまたぐ時間帯 (`22-2` など)
+                    start <= hour || hour < end
+                }
+            });
+
+        let holiday = holiday(time.date()).is_some();
+        let holidays = match self.holidays {
+            Some(Holidays::Exclude) => !holiday,
+            Some(Holidays::Only) => holiday,
+            None => true,
+        };
+
+        day_of_week && hours && holidays
+    }
+
+    /// 祝日による絞り込みを指定したときに、期間 (`first`〜`last` の時間帯) が祝日の一覧に含まれることを確認する
+    pub fn ensure_covered(&self, first: NaiveDateTime, last: NaiveDateTime) -> Result<()> {
+        if self.holidays.is_some() {
+            ensure!(
+                FIRST_YEAR <= first.year() && last.year() <= LAST_YEAR,
+                "祝日による絞り込みは {}〜{} 年の期間のみ指定できる",
+                FIRST_YEAR,
+                LAST_YEAR
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `7-10,17-20` のような時間帯の指定を解釈する
+///
+/// - `開始-終了` は、開始の時から終了の時の前まで (`7-10` は 7:00〜10:00)
+/// - `8` のように時のみを指定したときは、その1時間
+/// - 開始が終了より後のときは、日付をまたぐ時間帯とする (`22-2` は 22:00〜翌 2:00)
+pub fn parse_hours(input: &str) -> Result<Vec<(u32, u32)>> {
+    let parse_hour = |s: &str, max: u32| -> Result<u32> {
+        let hour: u32 = s
+            .trim()
+            .parse()
+            .with_context(|| format!("時間帯 {} の {} を時として解釈不能", input, s))?;
+        ensure!(hour <= max, "時間帯 {} の {} は 0〜{} で指定する必要がある", input, s, max);
+        Ok(hour)
+    };
+
+    let mut hours = vec![];
+    for range in input.split(',') {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hour(start, 23)?, parse_hour(end, 24)?),
+            None => {
+                let hour = parse_hour(range, 23)?;
+                (hour, hour + 1)
+            }
+        };
+        ensure!(start != end, "時間帯 {} の {} は空の範囲", input, range);
+        hours.push((start, end));
+    }
+
+    Ok(hours)
+}
+
+#[cfg(test)]
+mod calendar_tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M").unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y%m%d").unwrap()
+    }
+
+    #[test]
+    fn holidays() {
+        assert_eq!(holiday(date("20250101")), Some("元日"));
+        assert_eq!(holiday(date("20250113")), Some("成人の日"));
+        assert_eq!(holiday(date("20250224")), Some("振替休日"));
+        assert_eq!(holiday(date("20260922")), Some("国民の休日"));
+        assert_eq!(holiday(date("20210723")), Some("スポーツの日"));
+        assert_eq!(holiday(date("20250102")), None);
+        // 土日でも祝日でない日
+        assert_eq!(holiday(date("20250104")), None);
+
+        // すべての年の元日を含む
+        for year in FIRST_YEAR..=LAST_YEAR {
+            assert!(holiday(NaiveDate::from_ymd_opt(year, 1, 1).unwrap()).is_some());
+        }
+    }
+
+    #[test]
+    fn day_of_week() {
+        let filter = SlotFilter {
+            day_of_week: Some(DayOfWeek::Weekdays),
+            ..Default::default()
+        };
+        // 2025-01-03 (金)・2025-01-04 (土)・2025-01-06 (月)
+        assert!(filter.matches(time("202501031200")));
+        assert!(!filter.matches(time("202501041200")));
+        assert!(filter.matches(time("202501060000")));
+
+        let filter = SlotFilter {
+            day_of_week: Some(DayOfWeek::Weekends),
+            ..Default::default()
+        };
+        assert!(!filter.matches(time("202501031200")));
+        assert!(filter.matches(time("202501041200")));
+        assert!(filter.matches(time("202501052355")));
+    }
+
+    #[test]
+    fn hours() {
+        let filter = SlotFilter {
+            hours: parse_hours("7-10,17-20").unwrap(),
+            ..Default::default()
+        };
+        assert!(!filter.matches(time("202501060655")));
+        assert!(filter.matches(time("202501060700")));
+        assert!(filter.matches(time("202501060955")));
+        assert!(!filter.matches(time("202501061000")));
+        assert!(filter.matches(time("202501061700")));
+        assert!(!filter.matches(time("202501062000")));
+
+        let filter = SlotFilter {
+            hours: parse_hours("22-2").unwrap(),
+            ..Default::default()
+        };
+        assert!(filter.matches(time("202501062300")));
+        assert!(filter.matches(time("202501060100")));
+        assert!(!filter.matches(time("202501060200")));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_hours("7-10,17-20").unwrap(), vec![(7, 10), (17, 20)]);
+        assert_eq!(parse_hours("8").unwrap(), vec![(8, 9)]);
+        assert_eq!(parse_hours("23").unwrap(), vec![(23, 24)]);
+        assert_eq!(parse_hours(" 0 - 24 ").unwrap(), vec![(0, 24)]);
+        assert_eq!(parse_hours("22-2").unwrap(), vec![(22, 2)]);
+    }
+
+    #[test]
+    fn invalid_hours() {
+        for input in ["", "7-", "-10", "7-25", "24", "a-b", "7-7", "7-10,", "7–10"] {
+            assert!(parse_hours(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn holiday_filter() {
+        let filter = SlotFilter {
+            day_of_week: Some(DayOfWeek::Weekdays),
+            holidays: Some(Holidays::Exclude),
+            ..Default::default()
+        };
+        // 2025-01-13 (月) は成人の日
+        assert!(!filter.matches(time("202501130800")));
+        assert!(filter.matches(time("202501140800")));
+
+        let filter = SlotFilter {
+            holidays: Some(Holidays::Only),
+            ..Default::default()
+        };
+        assert!(filter.matches(time("202501130800")));
+        assert!(!filter.matches(time("202501140800")));
+        // 祝日でない日曜日
+        assert!(!filter.matches(time("202501120800")));
+    }
+
+    #[test]
+    fn covered() {
+        let filter = SlotFilter {
+            holidays: Some(Holidays::Exclude),
+            ..Default::default()
+        };
+        assert!(filter.ensure_covered(time("201901010000"), time("202712312355")).is_ok());
+        assert!(filter.ensure_covered(time("201812310000"), time("201901020000")).is_err());
+        assert!(filter.ensure_covered(time("202712310000"), time("202801010000")).is_err());
+        assert!(
+            SlotFilter::default()
+                .ensure_covered(time("201001010000"), time("201001020000"))
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use anyhow::{Result, ensure};
+
+use crate::endpoint::Endpoint;
+use crate::execution_option::ExecutionOption;
+use crate::fetch::{self, RetryPolicy};
+use crate::paging;
+use crate::rate_limit::RateLimiter;
+use crate::runner::{Quiet, Reporter};
+use crate::types::{CounterType, Interval, RoadType};
+use crate::url::{self, Filter, Target};
+
+/// 交通量データを取得するクライアント
+///
+/// 絞り込み条件・再試行・取得頻度の設定を保持し、同じクライアントで行う取得はすべて
+/// 1つのレートリミッターを共有する。複数のタスクから共有する場合は `Arc` で包んで使用する。
+pub struct Client {
+    http: reqwest::Client,
+    filter: Filter,
+    endpoint: Endpoint,
+    retry: RetryPolicy,
+    limiter: Arc<RateLimiter>,
+    page_size: Option<usize>,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("filter", &self.filter)
+            .field("endpoint", &self.endpoint)
+            .field("retry", &self.retry)
+            .field("limiter", &self.limiter)
+            .field("page_size", &self.page_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    /// デフォルトの設定(絞り込みなし、再試行3回、1秒あたり1回まで、10000件ごとのページ分割、通知なし)のクライアントを生成する
+    pub fn new() -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            filter: Filter::default(),
+            endpoint: Endpoint::default(),
+            retry: RetryPolicy::default(),
+            limiter: Arc::new(RateLimiter::new(1.0)),
+            page_size: Some(paging::DEFAULT_PAGE_SIZE),
+            reporter: Arc::new(Quiet),
+        }
+    }
+
+    /// 実行時のオプションの絞り込み条件・再試行・取得頻度の設定を使用するクライアントを生成する
+    pub fn from_option(option: &ExecutionOption) -> Self {
+        Client::new()
+            .with_filter(option.filter.clone())
+            .with_endpoint(option.endpoint.clone())
+            .with_retry_policy(option.retry.clone())
+            .with_rate_limiter(RateLimiter::new(option.rate))
+            .with_page_size(option.page_size)
+    }
+
+    /// 道路種別と時間コード以外の絞り込み条件を設定する
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// データの取得先の WFS を変更する
+    pub fn with_endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// 取得失敗時の再試行の設定を変更する
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// 取得頻度を制限するレートリミッターを変更する
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Arc::new(limiter);
+        self
+    }
+
+    /// 取得頻度を制限するレートリミッターを、他のクライアントと共有するものに変更する
+    ///
+    /// 設定の異なる複数のクライアントで、全体のリクエスト頻度を一定以下に抑える場合に使用する。
+    pub fn with_shared_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// 1回のリクエストで取得する地物の最大数を変更する。None のときはページ分割せず、1回のリクエストで取得する
+    ///
+    /// ページ分割したときも、すべてのページの地物を1つの FeatureCollection にまとめて返す。
+    pub fn with_page_size(mut self, page_size: Option<usize>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// 再試行などの進捗・エラーの通知先を変更する。このクライアントで取得する `run` などの処理も、同じ通知先を使用する
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// 設定済みの進捗・エラーの通知先
+    pub fn reporter(&self) -> &dyn Reporter {
+        self.reporter.as_ref()
+    }
+
+    /// 設定済みの絞り込み条件
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// 設定済みのデータの取得先
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// 指定した時間コード (YYYYMMDDHHMM)・取得間隔・観測機器・道路種別のデータの取得先 URL を生成する
+    pub fn url(&self, time: &str, interval: Interval, counter_type: CounterType, road_type: RoadType) -> Result<String> {
+        ensure!(
+            time.len() == 12 && time.chars().all(|c| c.is_ascii_digit()),
+            "時間コード {} は YYYYMMDDHHMM の12桁の数字で指定する必要がある",
+            time
+        );
+
+        Ok(url::create_url(
+            time,
+            &interval,
+            &road_type,
+            &counter_type,
+            &self.filter,
+            &self.endpoint,
+        ))
+    }
+
+    /// 指定した時間コード (YYYYMMDDHHMM)・取得間隔・観測機器・道路種別のデータを取得する
+    ///
+    /// レスポンスの内容は検証しないため、必要に応じて `response::classify` で分類する。
+    pub async fn fetch(&self, time: &str, interval: Interval, counter_type: CounterType, road_type: RoadType) -> Result<String> {
+        let url = self.url(time, interval, counter_type, road_type)?;
+        self.fetch_url(&url).await
+    }
+
+    /// `url::create_targets` で生成した取得対象のデータを取得する
+    pub async fn fetch_target(&self, target: &Target) -> Result<String> {
+        self.fetch_url(&target.url).await
+    }
+
+    /// 任意の URL のレスポンスを、再試行・取得頻度の設定に従って取得する。ページ分割は行わない
+    pub async fn get(&self, url: &str) -> Result<String> {
+        fetch::get_data_from_url(&self.http, url, &self.retry, &self.limiter, self.reporter()).await
+    }
+
+    async fn fetch_url(&self, url: &str) -> Result<String> {
+        match self.page_size {
+            Some(page_size) => paging::get_all_pages(&self.http, url, page_size, &self.retry, &self.limiter, self.reporter()).await,
+            None => self.get(url).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod client_tests {
+    use super::*;
+    use tokio::time::Duration;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn url() {
+        let client = Client::new().with_filter(Filter {
+            stations: vec!["3310840".into()],
+            ..Default::default()
+        });
+
+        let url = client
+            .url("202501020300", Interval::H1, CounterType::Permanent, RoadType::Highway)
+            .unwrap();
+
+        assert!(url.contains("typeNames=t_travospublic_measure_1h&"));
+        // 絞り込み条件が反映されていることを確認
+        assert!(url.contains("3310840"));
+    }
+
+    #[test]
+    fn invalid_time() {
+        let client = Client::new();
+
+        for time in ["20250102", "2025010203000", "20250102030a", ""] {
+            assert!(client.url(time, Interval::H1, CounterType::Permanent, RoadType::Both).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_target() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::new()
+            .with_rate_limiter(RateLimiter::unlimited())
+            .with_retry_policy(RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            });
+        let target = Target {
+            time: "202501020300".into(),
+            interval: Interval::H1,
+            road_type: RoadType::Both,
+            counter_type: CounterType::Permanent,
+            name: "H202501020300P".into(),
+            url: server.uri(),
+        };
+
+        assert_eq!(client.fetch_target(&target).await.unwrap(), "{}");
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+
+use crate::types::CompressFormat;
+
+/// gzip 形式のファイルの先頭のバイト列
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// zstd 形式のファイルの先頭のバイト列
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// 保存するファイルの圧縮の設定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compression {
+    /// 圧縮形式
+    pub format: CompressFormat,
+    /// 圧縮レベル
+    pub level: u32,
+}
+
+impl Compression {
+    /// 圧縮形式と圧縮レベルから生成する。圧縮レベルの省略時は、各形式の標準の値を使用する
+    ///
+    /// - gzip: 0〜9 (デフォルト: 6)
+    /// - zstd: 1〜22 (デフォルト: 3)
+    pub fn new(format: CompressFormat, level: Option<u32>) -> Result<Self> {
+        let (range, default) = match format {
+            CompressFormat::Gzip => (0..=9, 6),
+            CompressFormat::Zstd => (1..=22, 3),
+        };
+        let level = level.unwrap_or(default);
+        ensure!(
+            range.contains(&level),
+            "圧縮レベル {} は {}〜{} で指定する必要がある",
+            level,
+            range.start(),
+            range.end()
+        );
+
+        Ok(Compression { format, level })
+    }
+
+    /// 圧縮したファイルの拡張子
+    pub fn extension(&self) -> &'static str {
+        extension(self.format)
+    }
+
+    /// データを圧縮する
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let compressed = match self.format {
+            CompressFormat::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::new(self.level));
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            CompressFormat::Zstd => zstd::encode_all(data, self.level as i32)?,
+        };
+        Ok(compressed)
+    }
+}
+
+/// 圧縮形式ごとの拡張子
+fn extension(format: CompressFormat) -> &'static str {
+    match format {
+        CompressFormat::Gzip => "gz",
+        CompressFormat::Zstd => "zst",
+    }
+}
+
+/// パスの末尾に圧縮形式の拡張子を追加する。`H202501020300P.json` は `H202501020300P.json.gz` などになる
+pub fn with_extension(path: &Path, compression: Option<&Compression>) -> PathBuf {
+    match compression {
+        Some(compression) => append_extension(path, compression.extension()),
+        None => path.to_path_buf(),
+    }
+}
+
+/// 保存済みのファイルとして存在しうるパス。圧縮なし・gzip・zstd の順
+pub fn candidates(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_path_buf()];
+    paths.extend([CompressFormat::Gzip, CompressFormat::Zstd].map(|format| append_extension(path, extension(format))));
+    paths
+}
+
+/// `Path::with_extension` と異なり、既存の拡張子を残して追加する
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// ファイルの内容を文字列として読み込む
+///
+/// 圧縮形式はファイルの先頭のバイト列から判定し、圧縮されていないファイルはそのまま読み込む。
+pub fn read_to_string(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).with_context(|| format!("{} を読み込めない", path.display()))?;
+    decode(&bytes).with_context(|| format!("{} を展開できない", path.display()))
+}
+
+/// 圧縮されている可能性のあるデータを展開し、文字列として返す
+pub fn decode(bytes: &[u8]) -> Result<String> {
+    let mut content = String::new();
+    if bytes.starts_with(&GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(bytes).read_to_string(&mut content)?;
+    } else if bytes.starts_with(&ZSTD_MAGIC) {
+        zstd::Decoder::new(bytes)?.read_to_string(&mut content)?;
+    } else {
+        content = String::from_utf8(bytes.to_vec())?;
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod compress_tests {
+    use super::*;
+
+    const DATA: &str = r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{"常時観測点コード":3310840}}]}"#;
+
+    #[test]
+    fn roundtrip() {
+        for format in [CompressFormat::Gzip, CompressFormat::Zstd] {
+            let compression = Compression::new(format, None).unwrap();
+            let compressed = compression.compress(DATA.as_bytes()).unwrap();
+
+            assert_ne!(compressed, DATA.as_bytes());
+            assert_eq!(decode(&compressed).unwrap(), DATA);
+        }
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(decode(DATA.as_bytes()).unwrap(), DATA);
+        assert!(decode(&[0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn level() {
+        assert_eq!(Compression::new(CompressFormat::Gzip, None).unwrap().level, 6);
+        assert_eq!(Compression::new(CompressFormat::Zstd, None).unwrap().level, 3);
+        assert_eq!(Compression::new(CompressFormat::Zstd, Some(19)).unwrap().level, 19);
+
+        assert!(Compression::new(CompressFormat::Gzip, Some(10)).is_err());
+        assert!(Compression::new(CompressFormat::Zstd, Some(0)).is_err());
+        assert!(Compression::new(CompressFormat::Zstd, Some(23)).is_err());
+    }
+
+    #[test]
+    fn path() {
+        let compression = Compression::new(CompressFormat::Zstd, None).unwrap();
+        let path = Path::new("1h/H202501020300P.json");
+
+        assert_eq!(with_extension(path, None), path);
+        assert_eq!(with_extension(path, Some(&compression)), Path::new("1h/H202501020300P.json.zst"));
+        assert_eq!(
+            candidates(path),
+            vec![
+                PathBuf::from("1h/H202501020300P.json"),
+                PathBuf::from("1h/H202501020300P.json.gz"),
+                PathBuf::from("1h/H202501020300P.json.zst"),
+            ]
+        );
+    }
+}
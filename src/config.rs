@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer};
+
+use crate::types::{ConfigArgs, CounterType, Interval, RoadType};
+
+/// 設定ファイルのファイル名
+pub const FILE_NAME: &str = "traffic-dl.toml";
+/// XDG の設定ディレクトリ内の、このツールのディレクトリ名
+const APP_DIR: &str = "traffic-dl";
+
+/// 設定ファイルの内容
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// プロファイルの外に記述した、すべてのプロファイルに共通の設定
+    pub base: Settings,
+    /// `[profile.名前]` に記述した、名前付きのプロファイル
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+/// 設定ファイルで指定できる設定。未指定の項目は None
+///
+/// 未知のキーは記述の誤りとしてエラーにする。
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    /// 取得間隔 (`1h` / `5m`)
+    pub intervals: Option<Vec<Interval>>,
+    /// 観測機器 (`permanent` / `cctv`)
+    pub counters: Option<Vec<CounterType>>,
+    /// 道路種別 (`highway` / `normal`)
+    pub roads: Option<Vec<RoadType>>,
+    /// 常時観測点コード。数値・文字列のいずれでも記述できる
+    #[serde(deserialize_with = "station_codes")]
+    pub stations: Option<Vec<String>>,
+    /// 保存先のディレクトリ
+    pub out_dir: Option<String>,
+    /// 同時に取得するデータの最大数
+    pub concurrency: Option<usize>,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: Option<f64>,
+    /// データの取得先の WFS
+    pub endpoint: EndpointConfig,
+}
+
+/// `[endpoint]` の設定
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EndpointConfig {
+    /// エンドポイントの URL
+    pub url: Option<String>,
+    /// 座標参照系 (`srsName`)
+    pub srs_name: Option<String>,
+    /// 出力形式 (`outputFormat`)
+    pub output_format: Option<String>,
+    /// レイヤー名
+    pub layers: LayersConfig,
+}
+
+/// `[endpoint.layers]` の設定
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LayersConfig {
+    pub permanent_1h: Option<String>,
+    pub permanent_5m: Option<String>,
+    pub cctv_1h: Option<String>,
+    pub cctv_5m: Option<String>,
+}
+
+impl Settings {
+    /// `other` で指定されている項目で上書きする
+    fn merge(self, other: Settings) -> Settings {
+        Settings {
+            intervals: other.intervals.or(self.intervals),
+            counters: other.counters.or(self.counters),
+            roads: other.roads.or(self.roads),
+            stations: other.stations.or(self.stations),
+            out_dir: other.out_dir.or(self.out_dir),
+            concurrency: other.concurrency.or(self.concurrency),
+            rate: other.rate.or(self.rate),
+            endpoint: EndpointConfig {
+                url: other.endpoint.url.or(self.endpoint.url),
+                srs_name: other.endpoint.srs_name.or(self.endpoint.srs_name),
+                output_format: other.endpoint.output_format.or(self.endpoint.output_format),
+                layers: LayersConfig {
+                    permanent_1h: other.endpoint.layers.permanent_1h.or(self.endpoint.layers.permanent_1h),
+                    permanent_5m: other.endpoint.layers.permanent_5m.or(self.endpoint.layers.permanent_5m),
+                    cctv_1h: other.endpoint.layers.cctv_1h.or(self.endpoint.layers.cctv_1h),
+                    cctv_5m: other.endpoint.layers.cctv_5m.or(self.endpoint.layers.cctv_5m),
+                },
+            },
+        }
+    }
+}
+
+impl Config {
+    /// 指定したプロファイルの設定を、共通の設定に重ねて返す。None のときは共通の設定のみ
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings> {
+        let Some(name) = profile else {
+            return Ok(self.base.clone());
+        };
+        let Some(settings) = self.profiles.get(name) else {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            bail!("プロファイル {} が存在しない (定義済み: {})", name, names.join(", "));
+        };
+        Ok(self.base.clone().merge(settings.clone()))
+    }
+}
+
+/// 実行時のオプションに従って設定ファイルを読み込み、プロファイルを適用した設定を返す
+///
+/// - `--no-config` を指定したときは、設定ファイルを読み込まずにすべて未設定とする
+/// - `--config` を指定したときは、そのファイルを読み込む。存在しないときはエラー
+/// - 指定しないときは、`search_paths` の順に探して最初に見つかったファイルを読み込む。見つからないときはすべて未設定とする
+pub fn load(args: &ConfigArgs) -> Result<Settings> {
+    let path = match &args.config {
+        _ if args.no_config => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => search_paths(
+            std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            std::env::var_os("HOME").map(PathBuf::from),
+        )
+        .into_iter()
+        .find(|path| path.is_file()),
+    };
+
+    match path {
+        Some(path) => read(&path)?.settings(args.profile.as_deref()),
+        None if args.profile.is_some() && args.no_config => bail!("--no-config を指定したときは --profile を指定できない"),
+        None if args.profile.is_some() => bail!("--profile を指定したが、設定ファイル {} が見つからない", FILE_NAME),
+        None => Ok(Settings::default()),
+    }
+}
+
+/// `--config` を指定しないときに、設定ファイルを探すパス。優先する順
+///
+/// 1. カレントディレクトリの `traffic-dl.toml`
+/// 2. `$XDG_CONFIG_HOME/traffic-dl/traffic-dl.toml`。`XDG_CONFIG_HOME` が未設定のときは `$HOME/.config` を使用する
+pub fn search_paths(xdg_config_home: Option<PathBuf>, home: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(FILE_NAME)];
+
+    // XDG Base Directory の仕様に従い、絶対パスでない値は無視する
+    let config_home = xdg_config_home
+        .filter(|path| path.is_absolute())
+        .or_else(|| home.map(|home| home.join(".config")));
+    if let Some(dir) = config_home {
+        paths.push(dir.join(APP_DIR).join(FILE_NAME));
+    }
+
+    paths
+}
+
+/// 指定したパスの設定ファイルを読み込む
+fn read(path: &Path) -> Result<Config> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("設定ファイル {} を読み込めない", path.display()))?;
+    parse(&text).with_context(|| format!("設定ファイル {} を解釈不能", path.display()))
+}
+
+/// 設定ファイルの内容を解釈する
+pub fn parse(text: &str) -> Result<Config> {
+    let mut table: toml::Table = toml::from_str(text)?;
+
+    let profiles = match table.remove("profile") {
+        Some(profiles) => profiles.try_into().context("[profile] の記述が不正")?,
+        None => BTreeMap::new(),
+    };
+    let base = toml::Value::Table(table).try_into()?;
+
+    Ok(Config { base, profiles })
+}
+
+/// 常時観測点コードのリストを、数値・文字列のいずれの記述でも文字列として読み込む
+pub(crate) fn station_codes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Code {
+        Number(u64),
+        Text(String),
+    }
+
+    let codes = Vec::<Code>::deserialize(deserializer)?;
+    let codes = codes
+        .into_iter()
+        .map(|code| match code {
+            Code::Number(n) => n.to_string(),
+            Code::Text(s) => s,
+        })
+        .collect();
+    Ok(Some(codes))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        intervals = ["1h"]
+        out-dir = "/var/traffic"
+        rate = 0.5
+
+        [endpoint]
+        url = "http://localhost:8080/geoserver"
+
+        [profile.tokyo-highway]
+        roads = ["highway"]
+        stations = [3310840, "3310850"]
+        rate = 2.0
+
+        [profile.tokyo-highway.endpoint]
+        srs-name = "EPSG:6668"
+
+        [profile.all-5m]
+        intervals = ["5m"]
+        counters = ["permanent", "cctv"]
+    "#;
+
+    #[test]
+    fn base() {
+        let config = parse(CONFIG).unwrap();
+        let settings = config.settings(None).unwrap();
+
+        assert_eq!(settings.intervals, Some(vec![Interval::H1]));
+        assert_eq!(settings.out_dir.as_deref(), Some("/var/traffic"));
+        assert_eq!(settings.rate, Some(0.5));
+        assert_eq!(settings.roads, None);
+        assert_eq!(settings.endpoint.url.as_deref(), Some("http://localhost:8080/geoserver"));
+        assert_eq!(config.profiles.len(), 2);
+    }
+
+    #[test]
+    fn profile() {
+        let config = parse(CONFIG).unwrap();
+
+        // プロファイルの設定が共通の設定より優先され、未指定の項目は共通の設定を使用する
+        let settings = config.settings(Some("tokyo-highway")).unwrap();
+        assert_eq!(settings.roads, Some(vec![RoadType::Highway]));
+        assert_eq!(settings.stations, Some(vec!["3310840".into(), "3310850".into()]));
+        assert_eq!(settings.rate, Some(2.0));
+        assert_eq!(settings.intervals, Some(vec![Interval::H1]));
+        assert_eq!(settings.out_dir.as_deref(), Some("/var/traffic"));
+        assert_eq!(settings.endpoint.url.as_deref(), Some("http://localhost:8080/geoserver"));
+        assert_eq!(settings.endpoint.srs_name.as_deref(), Some("EPSG:6668"));
+
+        let settings = config.settings(Some("all-5m")).unwrap();
+        assert_eq!(settings.intervals, Some(vec![Interval::M5]));
+        assert_eq!(settings.counters, Some(vec![CounterType::Permanent, CounterType::Cctv]));
+
+        let error = config.settings(Some("osaka")).unwrap_err();
+        assert!(error.to_string().contains("all-5m, tokyo-highway"));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse("[endpoint]\nurl = \"http://localhost\"\nsrs = \"EPSG:6668\"").is_err());
+        assert!(parse("[endpont]").is_err());
+        assert!(parse("intervals = [\"1d\"]").is_err());
+        assert!(parse("[profile.a]\nconcurrency = \"2\"").is_err());
+        assert!(parse("stations = [true]").is_err());
+    }
+
+    #[test]
+    fn search_order() {
+        let paths = search_paths(Some("/etc/xdg".into()), Some("/home/user".into()));
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("traffic-dl.toml"),
+                PathBuf::from("/etc/xdg/traffic-dl/traffic-dl.toml")
+            ]
+        );
+
+        // XDG_CONFIG_HOME が未設定、または相対パスのときは ~/.config を使用する
+        for xdg in [None, Some("relative".into())] {
+            let paths = search_paths(xdg, Some("/home/user".into()));
+            assert_eq!(paths[1], PathBuf::from("/home/user/.config/traffic-dl/traffic-dl.toml"));
+        }
+
+        assert_eq!(search_paths(None, None), vec![PathBuf::from("traffic-dl.toml")]);
+    }
+
+    #[test]
+    fn load_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("custom.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let args = ConfigArgs {
+            config: Some(path.to_string_lossy().into()),
+            profile: Some("tokyo-highway".into()),
+            no_config: false,
+        };
+        assert_eq!(load(&args).unwrap().roads, Some(vec![RoadType::Highway]));
+
+        // 指定したファイルが存在しないときはエラー
+        let args = ConfigArgs {
+            config: Some(dir.path().join("missing.toml").to_string_lossy().into()),
+            profile: None,
+            no_config: false,
+        };
+        assert!(load(&args).is_err());
+    }
+
+    #[test]
+    fn no_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("custom.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+
+        // --config を指定していても読み込まない
+        let mut args = ConfigArgs {
+            config: Some(path.to_string_lossy().into()),
+            profile: None,
+            no_config: true,
+        };
+        assert_eq!(load(&args).unwrap(), Settings::default());
+
+        args.profile = Some("tokyo-highway".into());
+        assert!(load(&args).is_err());
+    }
+}
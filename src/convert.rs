@@ -0,0 +1,615 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::de::DeserializeOwned;
+
+use crate::compress;
+use crate::model::{CctvProperties, Feature, FeatureCollection, PermanentProperties};
+use crate::response::{self, ResponseKind};
+use crate::runner::Reporter;
+use crate::types::{ConvertArgs, CounterType, CsvEncoding};
+
+/// `convert` サブコマンドの実行時のオプション
+#[derive(Debug, Clone)]
+pub struct ConvertOption {
+    /// 変換するファイル。ディレクトリはファイルの一覧に展開済み
+    pub files: Vec<PathBuf>,
+    /// 出力先の CSV ファイル。None のときは標準出力
+    pub output: Option<String>,
+    /// 観測機器。None のときはファイル名から判定する
+    pub counter: Option<CounterType>,
+    /// 出力する列。空のときはすべての列
+    pub columns: Vec<String>,
+    pub encoding: CsvEncoding,
+}
+
+impl ConvertOption {
+    /// コマンドライン引数から変換時のオプションを生成する
+    pub fn from_args(args: &ConvertArgs) -> Result<Self> {
+        let mut files = vec![];
+        for input in &args.inputs {
+            let path = Path::new(input);
+            if path.is_dir() {
+                collect_json_files(path, &mut files).with_context(|| format!("ディレクトリ {} を読み込めない", input))?;
+            } else {
+                ensure!(path.is_file(), "変換するファイル {} が存在しない", input);
+                files.push(path.to_path_buf());
+            }
+        }
+
+        Ok(ConvertOption {
+            files,
+            output: args.output.clone(),
+            counter: args.counter,
+            columns: args
+                .columns
+                .iter()
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect(),
+            encoding: args.encoding.unwrap_or(CsvEncoding::Utf8),
+        })
+    }
+}
+
+/// 変換結果の集計
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConvertSummary {
+    /// 変換したファイルの数
+    pub files: usize,
+    /// 出力した行の数
+    pub rows: usize,
+    /// 観測機器が異なるため、変換しなかったファイルの数
+    pub skipped: usize,
+    /// サーバー例外・不正なデータのため、変換できなかったファイルの数
+    pub failures: usize,
+}
+
+/// CSV の列
+pub struct Column<P> {
+    /// `--columns` で指定する名前
+    pub key: &'static str,
+    /// ヘッダー行に出力する名前
+    pub header: &'static str,
+    value: fn(&Feature<P>) -> String,
+}
+
+impl<P> Column<P> {
+    const fn new(key: &'static str, header: &'static str, value: fn(&Feature<P>) -> String) -> Self {
+        Column { key, header, value }
+    }
+}
+
+/// CSV に変換可能な観測機器ごとの属性
+pub trait Record: DeserializeOwned + 'static {
+    /// 出力可能な列。`--columns` を省略したときは、この順にすべての列を出力する
+    const COLUMNS: &'static [Column<Self>];
+}
+
+impl Record for PermanentProperties {
+    const COLUMNS: &'static [Column<Self>] = &[
+        Column::new("station", "常時観測点コード", |f| f.properties.station_code.clone()),
+        Column::new("time", "時間コード", |f| f.properties.time_code.clone()),
+        Column::new("road", "道路種別", |f| text(&f.properties.road_type)),
+        Column::new("lon", "経度", lon),
+        Column::new("lat", "緯度", lat),
+        Column::new("up_small", "上り・小型交通量", |f| count(f.properties.up_small)),
+        Column::new("up_large", "上り・大型交通量", |f| count(f.properties.up_large)),
+        Column::new("up_unknown", "上り・車種判別不能交通量", |f| {
+            count(f.properties.up_unknown)
+        }),
+        Column::new("up_power_failure", "上り・停電", |f| flag(f.properties.up_power_failure)),
+        Column::new("up_loop_error", "上り・ループ異常", |f| flag(f.properties.up_loop_error)),
+        Column::new("up_ultrasonic_error", "上り・超音波異常", |f| {
+            flag(f.properties.up_ultrasonic_error)
+        }),
+        Column::new("up_missing", "上り・欠測", |f| flag(f.properties.up_missing)),
+        Column::new("down_small", "下り・小型交通量", |f| count(f.properties.down_small)),
+        Column::new("down_large", "下り・大型交通量", |f| count(f.properties.down_large)),
+        Column::new("down_unknown", "下り・車種判別不能交通量", |f| {
+            count(f.properties.down_unknown)
+        }),
+        Column::new("down_power_failure", "下り・停電", |f| flag(f.properties.down_power_failure)),
+        Column::new("down_loop_error", "下り・ループ異常", |f| {
+            flag(f.properties.down_loop_error)
+        }),
+        Column::new("down_ultrasonic_error", "下り・超音波異常", |f| {
+            flag(f.properties.down_ultrasonic_error)
+        }),
+        Column::new("down_missing", "下り・欠測", |f| flag(f.properties.down_missing)),
+    ];
+}
+
+impl Record for CctvProperties {
+    const COLUMNS: &'static [Column<Self>] = &[
+        Column::new("station", "常時観測点コード", |f| f.properties.station_code.clone()),
+        Column::new("time", "時間コード", |f| f.properties.time_code.clone()),
+        Column::new("road", "道路種別", |f| text(&f.properties.road_type)),
+        Column::new("lon", "経度", lon),
+        Column::new("lat", "緯度", lat),
+        Column::new("up_small", "上り・小型交通量", |f| count(f.properties.up_small)),
+        Column::new("up_large", "上り・大型交通量", |f| count(f.properties.up_large)),
+        Column::new("up_unknown", "上り・車種判別不能交通量", |f| {
+            count(f.properties.up_unknown)
+        }),
+        Column::new("up_missing", "上り・欠測", |f| flag(f.properties.up_missing)),
+        Column::new("down_small", "下り・小型交通量", |f| count(f.properties.down_small)),
+        Column::new("down_large", "下り・大型交通量", |f| count(f.properties.down_large)),
+        Column::new("down_unknown", "下り・車種判別不能交通量", |f| {
+            count(f.properties.down_unknown)
+        }),
+        Column::new("down_missing", "下り・欠測", |f| flag(f.properties.down_missing)),
+    ];
+}
+
+fn text(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+fn count(value: Option<u32>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// 品質フラグは `1` / `0` で出力する
+fn flag(value: Option<bool>) -> String {
+    value.map(|v| if v { "1" } else { "0" }.to_string()).unwrap_or_default()
+}
+
+fn lon<P>(feature: &Feature<P>) -> String {
+    feature
+        .geometry
+        .as_ref()
+        .and_then(|g| g.lon_lat())
+        .map(|(lon, _)| lon.to_string())
+        .unwrap_or_default()
+}
+
+fn lat<P>(feature: &Feature<P>) -> String {
+    feature
+        .geometry
+        .as_ref()
+        .and_then(|g| g.lon_lat())
+        .map(|(_, lat)| lat.to_string())
+        .unwrap_or_default()
+}
+
+/// 保存済みのファイルを CSV に変換して出力する
+///
+/// 観測機器ごとに列が異なるため、常設トラカンと CCTVトラカンのファイルを1つの CSV にまとめることはできない。
+/// 観測機器はファイル名から判定し、判定できないときは地物の属性から判定する。
+/// `counter` を指定したときは、判定した観測機器が異なるファイルを省略する。
+/// 変換できなかったファイルは `reporter` へ通知して集計する。
+pub fn convert(option: &ConvertOption, output: &mut dyn Write, reporter: &dyn Reporter) -> Result<ConvertSummary> {
+    let mut summary = ConvertSummary::default();
+    let mut files = vec![];
+    let mut counter = option.counter;
+
+    for path in &option.files {
+        let detected = match counter_type_of(path) {
+            Some(detected) => Some(detected),
+            None => counter_type_of_content(&compress::read_to_string(path)?),
+        };
+
+        match (detected, option.counter) {
+            (Some(detected), Some(specified)) if detected != specified => summary.skipped += 1,
+            (Some(detected), None) => match counter {
+                Some(current) if current != detected => {
+                    bail!("常設トラカンと CCTVトラカンのファイルが混在している。--counter で変換する観測機器を指定する必要がある")
+                }
+                _ => {
+                    counter = Some(detected);
+                    files.push(path);
+                }
+            },
+            // 地物を含まないファイル(データなし・サーバー例外など)は、観測機器によらず出力する行がない
+            _ => files.push(path),
+        }
+    }
+
+    match counter {
+        Some(CounterType::Permanent) => write_csv::<PermanentProperties>(&files, option, output, &mut summary, reporter)?,
+        Some(CounterType::Cctv) => write_csv::<CctvProperties>(&files, option, output, &mut summary, reporter)?,
+        None if !files.is_empty() => bail!(
+            "{} の観測機器をファイル名・地物の属性から判定できない。--counter で観測機器を指定する必要がある",
+            files[0].display()
+        ),
+        // 変換するファイルがないときは何も出力しない
+        None => {}
+    }
+
+    Ok(summary)
+}
+
+/// 列の名前から、出力する列を選択する。空のときはすべての列
+pub fn select_columns<P: Record>(keys: &[String]) -> Result<Vec<&'static Column<P>>> {
+    if keys.is_empty() {
+        return Ok(P::COLUMNS.iter().collect());
+    }
+
+    keys.iter()
+        .map(|key| {
+            P::COLUMNS.iter().find(|c| c.key == key || c.header == key).with_context(|| {
+                let available: Vec<&str> = P::COLUMNS.iter().map(|c| c.key).collect();
+                format!("列 {} は存在しない。使用可能な列: {}", key, available.join(","))
+            })
+        })
+        .collect()
+}
+
+fn write_csv<P: Record>(
+    files: &[&PathBuf],
+    option: &ConvertOption,
+    output: &mut dyn Write,
+    summary: &mut ConvertSummary,
+    reporter: &dyn Reporter,
+) -> Result<()> {
+    let columns = select_columns::<P>(&option.columns)?;
+
+    if option.encoding == CsvEncoding::Utf8Bom {
+        output.write_all("\u{feff}".as_bytes())?;
+    }
+    let header: Vec<&str> = columns.iter().map(|c| c.header).collect();
+    write_records(&[header], option.encoding, output)?;
+
+    for path in files {
+        let body = compress::read_to_string(path)?;
+
+        // サーバー例外や不正なデータは変換せず、エラーを通知して次のファイルへ進む
+        match response::classify(&body) {
+            ResponseKind::Data { .. } => {}
+            ResponseKind::Empty => {
+                summary.files += 1;
+                continue;
+            }
+            kind => {
+                reporter.error(&format!("{}: {} のため変換しない", path.display(), kind));
+                summary.failures += 1;
+                continue;
+            }
+        }
+        let collection: FeatureCollection<P> = match serde_json::from_str(&body) {
+            Ok(collection) => collection,
+            Err(e) => {
+                reporter.error(&format!("{}: 観測値として解釈不能のため変換しない: {}", path.display(), e));
+                summary.failures += 1;
+                continue;
+            }
+        };
+
+        let rows: Vec<Vec<String>> = collection
+            .features
+            .iter()
+            .map(|feature| columns.iter().map(|c| (c.value)(feature)).collect())
+            .collect();
+        write_records(&rows, option.encoding, output).with_context(|| format!("{} の変換結果を出力できない", path.display()))?;
+
+        summary.files += 1;
+        summary.rows += rows.len();
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// CSV の行を、指定した文字コードで出力する
+fn write_records<T: AsRef<[u8]>>(records: &[Vec<T>], encoding: CsvEncoding, output: &mut dyn Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for record in records {
+        writer.write_record(record)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+
+    match encoding {
+        CsvEncoding::Utf8 | CsvEncoding::Utf8Bom => output.write_all(&bytes)?,
+        CsvEncoding::ShiftJis => {
+            let text = String::from_utf8(bytes)?;
+            let (encoded, _, had_errors) = encoding_rs::SHIFT_JIS.encode(&text);
+            ensure!(!had_errors, "Shift_JIS で表現できない文字が含まれている");
+            output.write_all(&encoded)?;
+        }
+    }
+    Ok(())
+}
+
+/// `url::create_filename` で生成したファイル名 (H202501020300P など) から観測機器を判定する
+///
+/// 圧縮したファイル (`.json.gz` など) も判定できるよう、最初の `.` より前の部分を使用する。
+fn counter_type_of(path: &Path) -> Option<CounterType> {
+    let stem = path.file_name()?.to_str()?.split('.').next()?;
+    let (head, rest) = stem.split_at_checked(1)?;
+    let (time, counter) = rest.split_at_checked(rest.len().checked_sub(1)?)?;
+
+    if !matches!(head, "H" | "M") || time.len() != 12 || !time.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match counter {
+        "P" => Some(CounterType::Permanent),
+        "C" => Some(CounterType::Cctv),
+        _ => None,
+    }
+}
+
+/// 常設トラカンのみが持つ、機器の異常フラグの属性
+const PERMANENT_ONLY: [&str; 6] = [
+    "上り・停電",
+    "上り・ループ異常",
+    "上り・超音波異常",
+    "下り・停電",
+    "下り・ループ異常",
+    "下り・超音波異常",
+];
+
+/// 地物の属性から観測機器を判定する。地物を含まないときは None を返す
+///
+/// GeoServer は値が null の属性も出力するため、機器の異常フラグの属性がある地物は常設トラカン、ないときは CCTVトラカンとする。
+fn counter_type_of_content(body: &str) -> Option<CounterType> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let properties: Vec<_> = value
+        .get("features")?
+        .as_array()?
+        .iter()
+        .filter_map(|feature| feature.get("properties")?.as_object())
+        .collect();
+
+    if properties.is_empty() {
+        None
+    } else if properties.iter().any(|p| PERMANENT_ONLY.iter().any(|key| p.contains_key(*key))) {
+        Some(CounterType::Permanent)
+    } else {
+        Some(CounterType::Cctv)
+    }
+}
+
+/// ディレクトリに含まれる `.json` / `.json.gz` / `.json.zst` ファイルを、サブディレクトリも含めてパスの順に収集する
+fn collect_json_files(dir: &Path, output: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_json_files(&path, output)?;
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| [".json", ".json.gz", ".json.zst"].iter().any(|ext| name.ends_with(ext)))
+        {
+            output.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod convert_tests {
+    use super::*;
+    use crate::compress::Compression;
+    use crate::runner::{Quiet, Recorder};
+    use crate::types::CompressFormat;
+
+    const PERMANENT: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[139.7,35.6]},
+         "properties":{"常時観測点コード":3310840,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":120,"上り・大型交通量":30,"上り・停電":0,"上り・欠測":0}},
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[139.8,35.7]},
+         "properties":{"常時観測点コード":3310850,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":null,"上り・大型交通量":5,"上り・欠測":1}}
+    ]}"#;
+
+    const CCTV: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":null,"properties":{"常時観測点コード":6120010,"時間コード":202501020300,"道路種別":3,"上り・小型交通量":10}}
+    ]}"#;
+
+    /// 一時ディレクトリにファイルを作成し、ディレクトリを展開したオプションを返す
+    fn setup(files: &[(&str, &str)]) -> (tempfile::TempDir, ConvertOption) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, body) in files {
+            std::fs::write(dir.path().join(name), body).unwrap();
+        }
+        let args = ConvertArgs {
+            inputs: vec![dir.path().to_string_lossy().into()],
+            output: None,
+            counter: None,
+            columns: vec![],
+            encoding: None,
+        };
+        let option = ConvertOption::from_args(&args).unwrap();
+        (dir, option)
+    }
+
+    fn run(option: &ConvertOption) -> Result<(String, ConvertSummary)> {
+        let mut output = vec![];
+        let summary = convert(option, &mut output, &Quiet)?;
+        Ok((String::from_utf8(output)?, summary))
+    }
+
+    #[test]
+    fn permanent() {
+        let (_dir, mut option) = setup(&[("H202501020300P.json", PERMANENT), ("readme.txt", "")]);
+        option.columns = vec![
+            "station".into(),
+            "time".into(),
+            "lon".into(),
+            "up_small".into(),
+            "上り・大型交通量".into(),
+            "up_missing".into(),
+        ];
+
+        let (csv, summary) = run(&option).unwrap();
+
+        assert_eq!(
+            csv,
+            "常時観測点コード,時間コード,経度,上り・小型交通量,上り・大型交通量,上り・欠測\n\
+             3310840,202501020300,139.7,120,30,0\n\
+             3310850,202501020300,139.8,,5,1\n"
+        );
+        assert_eq!(
+            summary,
+            ConvertSummary {
+                files: 1,
+                rows: 2,
+                skipped: 0,
+                failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn all_columns() {
+        let (_dir, option) = setup(&[("H202501020300C.json", CCTV)]);
+
+        let (csv, _) = run(&option).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0].split(',').count(), CctvProperties::COLUMNS.len());
+        // CCTVトラカンには機器の異常フラグの列がない
+        assert!(!lines[0].contains("停電"));
+        assert_eq!(lines[1], "6120010,202501020300,3,,,10,,,,,,,");
+    }
+
+    #[test]
+    fn anomalies() {
+        let (_dir, option) = setup(&[
+            ("H202501020300P.json", PERMANENT),
+            ("H202501020400P.json", r#"{"type":"FeatureCollection","features":[]}"#),
+            ("H202501020500P.json", r#"{"exceptions":[{"code":"X","text":"error"}]}"#),
+        ]);
+
+        let (csv, summary) = run(&option).unwrap();
+
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(
+            summary,
+            ConvertSummary {
+                files: 2,
+                rows: 2,
+                skipped: 0,
+                failures: 1
+            }
+        );
+
+        // 変換できなかったファイルは Reporter へ通知する
+        let reporter = Recorder::default();
+        convert(&option, &mut vec![], &reporter).unwrap();
+        let errors = reporter.errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("H202501020500P.json: サーバー例外"), "{errors:?}");
+    }
+
+    #[test]
+    fn mixed_counter_types() {
+        let (_dir, mut option) = setup(&[("H202501020300C.json", CCTV), ("H202501020300P.json", PERMANENT)]);
+
+        // 観測機器が混在しているときはエラー
+        assert!(run(&option).is_err());
+
+        // 観測機器を指定したときは、異なる観測機器のファイルを省略する
+        option.counter = Some(CounterType::Cctv);
+        let (csv, summary) = run(&option).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+        assert_eq!(summary.skipped, 1);
+    }
+
+    #[test]
+    fn unknown_file_name() {
+        // `--name-template` で保存したファイルは、地物の属性から観測機器を判定する
+        let (_dir, mut option) = setup(&[("permanent_202501020300.json", PERMANENT), ("cctv_202501020300.json", CCTV)]);
+
+        // 観測機器が混在しているときはエラー
+        assert!(run(&option).is_err());
+
+        option.counter = Some(CounterType::Permanent);
+        let (csv, summary) = run(&option).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!((summary.rows, summary.skipped), (2, 1));
+
+        option.counter = Some(CounterType::Cctv);
+        let (csv, summary) = run(&option).unwrap();
+        assert_eq!(csv.lines().nth(1), Some("6120010,202501020300,3,,,10,,,,,,,"));
+        assert_eq!((summary.rows, summary.skipped), (1, 1));
+    }
+
+    #[test]
+    fn undetectable_counter_type() {
+        let (_dir, mut option) = setup(&[("data.json", r#"{"type":"FeatureCollection","features":[]}"#)]);
+
+        // ファイル名・地物の属性から観測機器を判定できないときはエラー
+        assert!(run(&option).is_err());
+
+        option.counter = Some(CounterType::Permanent);
+        assert_eq!(run(&option).unwrap().1.files, 1);
+    }
+
+    #[test]
+    fn unknown_column() {
+        let (_dir, mut option) = setup(&[("H202501020300C.json", CCTV)]);
+
+        // CCTVトラカンには停電の列がない
+        option.columns = vec!["up_power_failure".into()];
+        assert!(run(&option).is_err());
+    }
+
+    #[test]
+    fn encoding() {
+        let (_dir, mut option) = setup(&[("H202501020300P.json", PERMANENT)]);
+        option.columns = vec!["station".into()];
+
+        option.encoding = CsvEncoding::Utf8Bom;
+        let mut output = vec![];
+        convert(&option, &mut output, &Quiet).unwrap();
+        assert!(output.starts_with(&[0xEF, 0xBB, 0xBF]));
+
+        option.encoding = CsvEncoding::ShiftJis;
+        let mut output = vec![];
+        convert(&option, &mut output, &Quiet).unwrap();
+        let (decoded, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&output);
+        assert!(!had_errors);
+        assert!(decoded.starts_with("常時観測点コード\n"));
+        // Shift_JIS では BOM を出力しない
+        assert_ne!(output[..3], [0xEF, 0xBB, 0xBF]);
+    }
+
+    #[test]
+    fn compressed() {
+        let (dir, _) = setup(&[]);
+        for (format, name) in [
+            (CompressFormat::Gzip, "H202501020300P.json.gz"),
+            (CompressFormat::Zstd, "H202501020400P.json.zst"),
+        ] {
+            let compressed = Compression::new(format, None).unwrap().compress(PERMANENT.as_bytes()).unwrap();
+            std::fs::write(dir.path().join(name), compressed).unwrap();
+        }
+        // 圧縮したファイルを作成してから、ディレクトリを展開する
+        let option = ConvertOption::from_args(&ConvertArgs {
+            inputs: vec![dir.path().to_string_lossy().into()],
+            output: None,
+            counter: None,
+            columns: vec!["station".into()],
+            encoding: None,
+        })
+        .unwrap();
+
+        let (csv, summary) = run(&option).unwrap();
+        assert_eq!(csv, "常時観測点コード\n3310840\n3310850\n3310840\n3310850\n");
+        assert_eq!((summary.files, summary.rows, summary.failures), (2, 4, 0));
+    }
+
+    #[test]
+    fn counter_type_from_file_name() {
+        assert_eq!(counter_type_of(Path::new("data/H202501020300P.json")), Some(CounterType::Permanent));
+        assert_eq!(counter_type_of(Path::new("M202501020305C.json")), Some(CounterType::Cctv));
+        assert_eq!(counter_type_of(Path::new("H202501020300P.json.zst")), Some(CounterType::Permanent));
+        assert_eq!(counter_type_of(Path::new("X202501020300P.json")), None);
+        assert_eq!(counter_type_of(Path::new("H2025010203P.json")), None);
+        assert_eq!(counter_type_of(Path::new("P.json")), None);
+    }
+
+    #[test]
+    fn counter_type_from_content() {
+        assert_eq!(counter_type_of_content(PERMANENT), Some(CounterType::Permanent));
+        assert_eq!(counter_type_of_content(CCTV), Some(CounterType::Cctv));
+        assert_eq!(counter_type_of_content(r#"{"type":"FeatureCollection","features":[]}"#), None);
+        assert_eq!(counter_type_of_content(r#"{"exceptions":[]}"#), None);
+        assert_eq!(counter_type_of_content("<xml/>"), None);
+    }
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+use crate::spatial::BBox;
+
+/// URL のクエリ値としてエンコードする文字。RFC 3986 の非予約文字以外はすべてエンコードする
+pub(crate) const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// 条件式で比較する値
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// 文字列。`'` で囲んで出力する
+    Text(String),
+    /// 整数。引用符なしで出力する
+    Integer(i64),
+}
+
+/// 比較演算子
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// WFS の `cql_filter` パラメーターに指定する CQL の条件式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// 属性と値の比較
+    Compare { attr: String, op: Op, value: Value },
+    /// 属性の値がリストのいずれかに一致する
+    In { attr: String, values: Vec<Value> },
+    /// すべての条件式を満たす。空のときは常に真
+    And(Vec<Expr>),
+    /// いずれかの条件式を満たす。空のときは常に偽
+    Or(Vec<Expr>),
+    /// 条件式を満たさない
+    Not(Box<Expr>),
+    /// ジオメトリが経度・緯度の矩形範囲と交差する
+    BBox { attr: String, bbox: BBox },
+    /// ジオメトリが WKT で表したジオメトリと交差する
+    Intersects { attr: String, wkt: String },
+}
+
+impl Expr {
+    /// `attr = value` の条件式を生成する
+    pub fn eq(attr: &str, value: Value) -> Expr {
+        Expr::Compare {
+            attr: attr.to_string(),
+            op: Op::Eq,
+            value,
+        }
+    }
+
+    /// `attr IN (values...)` の条件式を生成する
+    pub fn is_in(attr: &str, values: Vec<Value>) -> Expr {
+        Expr::In {
+            attr: attr.to_string(),
+            values,
+        }
+    }
+
+    /// 条件式を否定する
+    pub fn negate(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+
+    /// 条件式を URL のクエリ値としてパーセントエンコードした文字列を返す
+    pub fn encode(&self) -> String {
+        utf8_percent_encode(&self.to_string(), QUERY_VALUE).to_string()
+    }
+
+    /// AND / OR の項として出力する際に、括弧でくくる必要があるかどうか
+    fn needs_parens(&self) -> bool {
+        match self {
+            Expr::And(list) | Expr::Or(list) => list.len() > 1,
+            _ => false,
+        }
+    }
+
+    /// AND / OR の各項を、必要に応じて括弧でくくって区切り文字で連結する
+    fn fmt_list(f: &mut fmt::Formatter<'_>, list: &[Expr], separator: &str, empty: &str) -> fmt::Result {
+        if list.is_empty() {
+            return write!(f, "{}", empty);
+        }
+
+        for (i, expr) in list.iter().enumerate() {
+            if i > 0 {
+                write!(f, " {} ", separator)?;
+            }
+            if expr.needs_parens() && list.len() > 1 {
+                write!(f, "({})", expr)?;
+            } else {
+                write!(f, "{}", expr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Compare { attr, op, value } => write!(f, "{}{}{}", Attr(attr), op, value),
+            Expr::In { attr, values } => {
+                let list: Vec<String> = values.iter().map(Value::to_string).collect();
+                write!(f, "{} IN ({})", Attr(attr), list.join(","))
+            }
+            Expr::And(list) => Expr::fmt_list(f, list, "AND", "INCLUDE"),
+            Expr::Or(list) => Expr::fmt_list(f, list, "OR", "EXCLUDE"),
+            Expr::Not(expr) => write!(f, "NOT ({})", expr),
+            Expr::BBox { attr, bbox } => write!(
+                f,
+                "BBOX({},{},{},{},{},'EPSG:4326')",
+                Attr(attr),
+                bbox.min_lon,
+                bbox.min_lat,
+                bbox.max_lon,
+                bbox.max_lat
+            ),
+            Expr::Intersects { attr, wkt } => write!(f, "INTERSECTS({},{})", Attr(attr), wkt),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Ne => "<>",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // 文字列中の `'` は `''` にエスケープする
+            Value::Text(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            Value::Integer(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// 属性名
+///
+/// 英数字(日本語を含む)と `_` のみからなる属性名はそのまま出力し、
+/// それ以外の文字を含む場合は `"` で囲み、内部の `"` は `""` にエスケープする。
+struct Attr<'a>(&'a str);
+
+impl fmt::Display for Attr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plain = !self.0.is_empty()
+            && !self.0.starts_with(|c: char| c.is_ascii_digit())
+            && self.0.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+        if plain {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+        }
+    }
+}
+
+#[cfg(test)]
+mod display_tests {
+    use super::*;
+
+    #[test]
+    fn compare() {
+        assert_eq!(Expr::eq("道路種別", Value::Text("1".into())).to_string(), "道路種別='1'");
+        assert_eq!(
+            Expr::eq("時間コード", Value::Integer(202501020300)).to_string(),
+            "時間コード=202501020300"
+        );
+
+        let expr = Expr::Compare {
+            attr: "上り・小型交通量".into(),
+            op: Op::Ge,
+            value: Value::Integer(100),
+        };
+        assert_eq!(expr.to_string(), "\"上り・小型交通量\">=100");
+    }
+
+    #[test]
+    fn quoting() {
+        // 文字列中の引用符はエスケープされる
+        assert_eq!(Expr::eq("名称", Value::Text("O'Hare".into())).to_string(), "名称='O''Hare'");
+        // 記号を含む属性名は二重引用符で囲まれる
+        assert_eq!(Expr::eq("a\"b c", Value::Integer(1)).to_string(), "\"a\"\"b c\"=1");
+        // 数字で始まる属性名も二重引用符で囲まれる
+        assert_eq!(Expr::eq("1h", Value::Integer(1)).to_string(), "\"1h\"=1");
+    }
+
+    #[test]
+    fn is_in() {
+        let expr = Expr::is_in("常時観測点コード", vec![Value::Integer(3310840), Value::Integer(3310850)]);
+        assert_eq!(expr.to_string(), "常時観測点コード IN (3310840,3310850)");
+    }
+
+    #[test]
+    fn and_or_not() {
+        let road = Expr::Or(vec![
+            Expr::eq("道路種別", Value::Text("1".into())),
+            Expr::eq("道路種別", Value::Text("3".into())),
+        ]);
+        let expr = Expr::And(vec![
+            road.clone(),
+            Expr::eq("時間コード", Value::Integer(202501020300)),
+            Expr::is_in("常時観測点コード", vec![Value::Integer(1)]).negate(),
+        ]);
+
+        // 複数の項を持つ OR は、AND の項として括弧でくくられる
+        assert_eq!(
+            expr.to_string(),
+            "(道路種別='1' OR 道路種別='3') AND 時間コード=202501020300 AND NOT (常時観測点コード IN (1))"
+        );
+        // 単独の項は括弧でくくられない
+        assert_eq!(Expr::And(vec![road]).to_string(), "道路種別='1' OR 道路種別='3'");
+        // 1つの項のみの OR は括弧でくくられない
+        let single = Expr::And(vec![
+            Expr::Or(vec![Expr::eq("a", Value::Integer(1))]),
+            Expr::eq("b", Value::Integer(2)),
+        ]);
+        assert_eq!(single.to_string(), "a=1 AND b=2");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Expr::And(vec![]).to_string(), "INCLUDE");
+        assert_eq!(Expr::Or(vec![]).to_string(), "EXCLUDE");
+    }
+
+    #[test]
+    fn spatial() {
+        let bbox = Expr::BBox {
+            attr: "ジオメトリ".into(),
+            bbox: BBox {
+                min_lon: 139.5,
+                min_lat: 35.5,
+                max_lon: 140.0,
+                max_lat: 35.9,
+            },
+        };
+        assert_eq!(bbox.to_string(), "BBOX(ジオメトリ,139.5,35.5,140,35.9,'EPSG:4326')");
+
+        let intersects = Expr::Intersects {
+            attr: "ジオメトリ".into(),
+            wkt: "POLYGON((139 35,140 35,140 36,139 35))".into(),
+        };
+        assert_eq!(
+            intersects.to_string(),
+            "INTERSECTS(ジオメトリ,POLYGON((139 35,140 35,140 36,139 35)))"
+        );
+    }
+}
+
+#[cfg(test)]
+mod encode_tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let expr = Expr::And(vec![
+            Expr::eq("道路種別", Value::Text("1".into())),
+            Expr::eq("時間コード", Value::Integer(202501020300)),
+        ]);
+
+        assert_eq!(
+            expr.encode(),
+            "%E9%81%93%E8%B7%AF%E7%A8%AE%E5%88%A5%3D%271%27%20AND%20%E6%99%82%E9%96%93%E3%82%B3%E3%83%BC%E3%83%89%3D202501020300"
+        );
+    }
+
+    #[test]
+    fn reserved_characters() {
+        // URL の区切り文字として解釈される文字はすべてエンコードされる
+        let expr = Expr::eq("名称", Value::Text("a&b=c#d+e%".into()));
+
+        assert_eq!(expr.encode(), "%E5%90%8D%E7%A7%B0%3D%27a%26b%3Dc%23d%2Be%25%27");
+    }
+}
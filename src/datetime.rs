@@ -1,5 +1,8 @@
-use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Utc};
 
+/// 日時指定。日時はすべて、時間コードと同じ日本標準時で表す
+///
+/// 他のタイムゾーンで指定したときは、解釈時に日本標準時へ変換する。
 #[derive(Debug, PartialEq)]
 pub enum DT {
     Ymd {
@@ -23,9 +26,261 @@ pub enum DT {
         hour: u32,
         minute: u32,
     },
+    /// 開始から終了まで(両端を含む)の範囲指定。開始と終了は同じ粒度の日時指定
+    Range {
+        string: String,
+        start: Box<DT>,
+        end: Box<DT>,
+    },
+}
+
+impl DT {
+    /// 指定された期間の開始日時(含む)と終了日時(含まない)を返す
+    pub fn span(&self) -> (NaiveDateTime, NaiveDateTime) {
+        match self {
+            DT::Ymd { year, month, day, .. } => {
+                let start = naive(*year, *month, *day, 0, 0);
+                (start, start + TimeDelta::days(1))
+            }
+            DT::Ymdh {
+                year, month, day, hour, ..
+            } => {
+                let start = naive(*year, *month, *day, *hour, 0);
+                (start, start + TimeDelta::hours(1))
+            }
+            DT::Ymdhm {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                ..
+            } => {
+                let start = naive(*year, *month, *day, *hour, *minute);
+                (start, start + TimeDelta::minutes(1))
+            }
+            DT::Range { start, end, .. } => (start.span().0, end.span().1),
+        }
+    }
+
+    /// 分まで指定されている(範囲指定の場合は両端が分まで指定されている)かどうか
+    pub fn has_minute(&self) -> bool {
+        match self {
+            DT::Ymdhm { .. } => true,
+            DT::Range { start, .. } => start.has_minute(),
+            _ => false,
+        }
+    }
+}
+
+/// パース済みの値から日時を生成する。値は検証済みのため失敗しない
+fn naive(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .and_then(|d| d.and_hms_opt(hour, minute, 0))
+        .expect("検証済みの日時")
+}
+
+/// 日本標準時 (UTC+9)。時間コードは日本標準時で表す
+pub fn jst() -> FixedOffset {
+    FixedOffset::east_opt(9 * 3600).expect("有効なオフセット")
+}
+
+/// 現在の日本標準時。実行環境のタイムゾーンによらない
+pub fn now_jst() -> NaiveDateTime {
+    Utc::now().with_timezone(&jst()).naive_local()
 }
 
+/// 時間コード (YYYYMMDDHHMM、日本標準時) を日時に変換する
+pub fn from_time_code(time_code: &str) -> Option<DateTime<FixedOffset>> {
+    let time = NaiveDateTime::parse_from_str(time_code, "%Y%m%d%H%M").ok()?;
+    time.and_local_timezone(jst()).single()
+}
+
+/// 日時指定の文字列を解釈する
+/// - `YYYYMMDD` / `YYYYMMDDHH` / `YYYYMMDDHHMM` の単一指定
+/// - `YYYY-MM-DD` / `YYYY-MM-DDTHH` / `YYYY-MM-DDTHH:MM` の ISO 8601 形式の単一指定。`T` の代わりに空白も使用できる
+/// - `today` / `yesterday` / `-3d` / `-6h` / `last-hour` / `last-week` の相対的な指定。現在の日本標準時を基準とする
+/// - 単一指定の末尾に `Z` / `+HH:MM` / `-HH:MM` でタイムゾーンを指定したときは、日本標準時に変換する
+/// - `開始..終了` または `開始-終了` の範囲指定。開始と終了は同じ粒度である必要がある
 pub fn parse(input: &str) -> Option<DT> {
+    parse_at(input, now_jst())
+}
+
+/// 相対的な日時指定の基準とする現在日時 (日本標準時) を指定して、日時指定の文字列を解釈する
+pub fn parse_at(input: &str, now: NaiveDateTime) -> Option<DT> {
+    match split_range(input) {
+        Some((start, end)) => parse_range(input, start, end, now),
+        None => parse_single(input, now),
+    }
+}
+
+/// 範囲指定の区切り文字で文字列を分割する。範囲指定でない場合は None を返す
+fn split_range(input: &str) -> Option<(&str, &str)> {
+    if let Some(pair) = input.split_once("..") {
+        return Some(pair);
+    }
+
+    // `-` 区切りは、両側が数字のみのときに限り範囲指定として扱う
+    let (start, end) = input.split_once('-')?;
+    let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if is_digits(start) && is_digits(end) {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// 範囲指定を解釈する。開始と終了の粒度が異なる場合や、開始が終了より後の場合は None を返す
+fn parse_range(input: &str, start: &str, end: &str, now: NaiveDateTime) -> Option<DT> {
+    // `last-week` のような範囲となる指定は、範囲の両端に使用できない
+    // タイムゾーンを指定した年月日は日本標準時の1時間ごとの範囲となるため、開始は範囲の開始、終了は範囲の終了を使用する
+    let start = match parse_single(start, now)? {
+        DT::Range { start: first, .. } if split_offset(start).is_some() => *first,
+        DT::Range { .. } => return None,
+        dt => dt,
+    };
+    let end = match parse_single(end, now)? {
+        DT::Range { end: last, .. } if split_offset(end).is_some() => *last,
+        DT::Range { .. } => return None,
+        dt => dt,
+    };
+
+    if std::mem::discriminant(&start) != std::mem::discriminant(&end) || start.span().0 > end.span().0 {
+        return None;
+    }
+
+    Some(DT::Range {
+        string: input.to_string(),
+        start: Box::new(start),
+        end: Box::new(end),
+    })
+}
+
+/// 単一の日時指定を解釈する
+fn parse_single(input: &str, now: NaiveDateTime) -> Option<DT> {
+    if let Some((local, offset)) = split_offset(input) {
+        let dt = parse_digits(local).or_else(|| parse_iso(local))?;
+        return to_jst(dt, offset, input);
+    }
+
+    parse_digits(input)
+        .or_else(|| parse_iso(input))
+        .or_else(|| parse_relative(input, now))
+}
+
+/// 末尾のタイムゾーンの指定 (`Z` / `+HH:MM` / `-HH:MM`) を分割する。指定がないときは None を返す
+fn split_offset(input: &str) -> Option<(&str, FixedOffset)> {
+    if let Some(local) = input.strip_suffix('Z') {
+        return Some((local, FixedOffset::east_opt(0)?));
+    }
+
+    let at = input.len().checked_sub(6)?;
+    let (local, offset) = (input.get(..at)?, input.get(at..)?);
+    let sign = match offset.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = offset[1..].split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let minutes = hours.parse::<i32>().ok()? * 60 + minutes.parse::<i32>().ok()?;
+
+    Some((local, FixedOffset::east_opt(sign * minutes * 60)?))
+}
+
+/// 指定したタイムゾーンの日時指定を、日本標準時の日時指定に変換する
+///
+/// - 年月日のみの指定は、日本標準時では日をまたぐため、1時間ごとの範囲指定に変換する
+/// - 年月日・時までの指定は、日本標準時との時差が1時間単位でないときは変換できない
+fn to_jst(dt: DT, offset: FixedOffset, input: &str) -> Option<DT> {
+    let shift = TimeDelta::seconds((jst().local_minus_utc() - offset.local_minus_utc()).into());
+    if shift.is_zero() {
+        return Some(dt);
+    }
+
+    let (start, end) = dt.span();
+    let (start, end) = (start + shift, end + shift);
+    let ymdh = |time: NaiveDateTime| parse_digits(&time.format("%Y%m%d%H").to_string());
+    let whole_hours = shift.num_seconds() % 3600 == 0;
+
+    match dt {
+        DT::Ymdhm { .. } => parse_digits(&start.format("%Y%m%d%H%M").to_string()),
+        DT::Ymdh { .. } if whole_hours => ymdh(start),
+        DT::Ymd { .. } if whole_hours => Some(DT::Range {
+            string: input.to_string(),
+            start: Box::new(ymdh(start)?),
+            end: Box::new(ymdh(end - TimeDelta::hours(1))?),
+        }),
+        _ => None,
+    }
+}
+
+/// 相対的な日時指定を解釈する
+/// - `today` / `yesterday`: 今日・昨日の1日分
+/// - `-Nd` / `-Nh`: N日前の1日分・N時間前の1時間分
+/// - `last-hour`: 直前の1時間分。現在が 12:34 のときは 11:00〜12:00
+/// - `last-week`: 先週の月曜日から日曜日までの範囲
+fn parse_relative(input: &str, now: NaiveDateTime) -> Option<DT> {
+    let today = now.date();
+    let hour = now.date().and_hms_opt(now.hour(), 0, 0)?;
+    let ymd = |date: NaiveDate| parse_digits(&date.format("%Y%m%d").to_string());
+    let ymdh = |time: NaiveDateTime| parse_digits(&time.format("%Y%m%d%H").to_string());
+
+    match input {
+        "today" => ymd(today),
+        "yesterday" => ymd(today - TimeDelta::days(1)),
+        "last-hour" => ymdh(hour - TimeDelta::hours(1)),
+        "last-week" => {
+            let monday = today - TimeDelta::days(today.weekday().num_days_from_monday() as i64 + 7);
+            let sunday = monday + TimeDelta::days(6);
+            Some(DT::Range {
+                string: input.to_string(),
+                start: Box::new(ymd(monday)?),
+                end: Box::new(ymd(sunday)?),
+            })
+        }
+        _ => {
+            let offset = input.strip_prefix('-')?;
+            if let Some(n) = offset.strip_suffix('d') {
+                ymd(today.checked_sub_signed(TimeDelta::try_days(n.parse().ok()?)?)?)
+            } else if let Some(n) = offset.strip_suffix('h') {
+                ymdh(hour.checked_sub_signed(TimeDelta::try_hours(n.parse().ok()?)?)?)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// ISO 8601 形式 (`YYYY-MM-DD` / `YYYY-MM-DDTHH` / `YYYY-MM-DDTHH:MM(:SS)`) の日時指定を解釈する
+///
+/// 数字のみの形式に変換して解釈するため、同じ粒度の数字のみの指定と同じ結果になる。秒は無視する。
+fn parse_iso(input: &str) -> Option<DT> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return parse_digits(&date.format("%Y%m%d").to_string());
+    }
+
+    let (date, time) = input.split_once(['T', ' '])?;
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+
+    // 時のみの指定は、chrono のパースでは分が必須であるため `:00` を追加して解釈する
+    let (hour_minute, format) = match time.len() {
+        2 => (format!("{}:00", time), "%Y%m%d%H"),
+        5 => (time.to_string(), "%Y%m%d%H%M"),
+        8 => (
+            NaiveTime::parse_from_str(time, "%H:%M:%S").ok()?.format("%H:%M").to_string(),
+            "%Y%m%d%H%M",
+        ),
+        _ => return None,
+    };
+    let time = NaiveTime::parse_from_str(&hour_minute, "%H:%M").ok()?;
+    parse_digits(&date.and_time(time).format(format).to_string())
+}
+
+/// 数字のみの単一の日時指定を解釈する
+fn parse_digits(input: &str) -> Option<DT> {
     // YYYYMMDDHHMM
     let result = NaiveDateTime::parse_from_str(input, "%Y%m%d%H%M");
     if let Ok(dt) = result {
@@ -160,4 +415,165 @@ mod parse_tests {
         assert!(parse("abc").is_none());
         assert!(parse("").is_none());
     }
+
+    #[test]
+    fn range() {
+        assert_eq!(
+            parse("20250101-20250131"),
+            Some(DT::Range {
+                string: "20250101-20250131".into(),
+                start: Box::new(parse("20250101").unwrap()),
+                end: Box::new(parse("20250131").unwrap()),
+            })
+        );
+        assert_eq!(
+            parse("2025010100..2025010223"),
+            Some(DT::Range {
+                string: "2025010100..2025010223".into(),
+                start: Box::new(parse("2025010100").unwrap()),
+                end: Box::new(parse("2025010223").unwrap()),
+            })
+        );
+        // 開始と終了が同じ場合も範囲として扱う
+        assert!(parse("20250101..20250101").is_some());
+    }
+
+    #[test]
+    fn invalid_range() {
+        // 開始が終了より後
+        assert!(parse("20250131-20250101").is_none());
+        // 開始と終了の粒度が異なる
+        assert!(parse("20250101..2025013112").is_none());
+        // 片側が不正
+        assert!(parse("20250101-20251301").is_none());
+        assert!(parse("20250101..").is_none());
+        assert!(parse("..20250101").is_none());
+        assert!(parse("-20250101").is_none());
+    }
+
+    #[test]
+    fn iso() {
+        assert_eq!(parse("2025-01-02"), parse("20250102"));
+        assert_eq!(parse("2025-01-02T03"), parse("2025010203"));
+        assert_eq!(parse("2025-01-02T03:04"), parse("202501020304"));
+        assert_eq!(parse("2025-01-02 03:04:00"), parse("202501020304"));
+        // 時間コードとして使用するため、数字のみの形式に変換する
+        assert!(matches!(parse("2025-01-02T03:04"), Some(DT::Ymdhm { string, .. }) if string == "202501020304"));
+
+        assert_eq!(
+            parse("2025-01-01..2025-01-31"),
+            parse("20250101..20250131").map(|dt| with_string(dt, "2025-01-01..2025-01-31"))
+        );
+
+        assert!(parse("2025-01-32").is_none());
+        assert!(parse("2025-01-02T24:00").is_none());
+        assert!(parse("2025-01-02T3").is_none());
+        assert!(parse("2025-01-02T03:04:0a").is_none());
+    }
+
+    #[test]
+    fn relative() {
+        // 2025-01-08 (水) 12:34 (日本標準時)
+        let now = NaiveDateTime::parse_from_str("202501081234", "%Y%m%d%H%M").unwrap();
+        let parse = |input| parse_at(input, now);
+
+        assert_eq!(parse("today"), datetime("20250108"));
+        assert_eq!(parse("yesterday"), datetime("20250107"));
+        assert_eq!(parse("-3d"), datetime("20250105"));
+        assert_eq!(parse("-10d"), datetime("20241229"));
+        assert_eq!(parse("-6h"), datetime("2025010806"));
+        assert_eq!(parse("-13h"), datetime("2025010723"));
+        assert_eq!(parse("last-hour"), datetime("2025010811"));
+        assert_eq!(
+            parse("last-week"),
+            datetime("20241230..20250105").map(|dt| with_string(dt, "last-week"))
+        );
+
+        // 範囲指定の両端にも使用できる
+        assert_eq!(
+            parse("-7d..yesterday"),
+            datetime("20250101..20250107").map(|dt| with_string(dt, "-7d..yesterday"))
+        );
+
+        assert!(parse("-3w").is_none());
+        assert!(parse("-d").is_none());
+        assert!(parse("3d").is_none());
+        assert!(parse("tomorrow").is_none());
+        // ASCII 以外の文字を含む指定も、パニックせずに解釈不能とする
+        assert!(parse("-日").is_none());
+        assert!(parse("-3日").is_none());
+        assert!(parse("-３d").is_none());
+        assert!(parse("-3時間").is_none());
+        assert!(parse("last-week..today").is_none());
+    }
+
+    #[test]
+    fn timezone() {
+        // 日本標準時と同じ時差のときは、そのまま
+        assert_eq!(parse("2025-01-02T03:00+09:00"), parse("202501020300"));
+        // UTC 2025-01-01 18:00 = JST 2025-01-02 03:00
+        assert!(matches!(parse("2025-01-01T18:00Z"), Some(DT::Ymdhm { string, .. }) if string == "202501020300"));
+        assert_eq!(parse("202501011800Z"), parse("202501020300"));
+        assert_eq!(parse("2025-01-01T13:00-05:00"), parse("202501020300"));
+        assert_eq!(parse("2025-01-01T23:30+05:30"), parse("202501020300"));
+        assert_eq!(parse("2025010118Z"), parse("2025010203"));
+
+        // UTC の1日は、日本標準時の 09:00 から翌日の 08:00 までの1時間ごとの範囲
+        let dt = parse("2025-01-01Z").unwrap();
+        assert_eq!(dt, with_string(parse("2025010109..2025010208").unwrap(), "2025-01-01Z"));
+        assert_eq!(url_hours(&dt), 24);
+
+        // 範囲指定は、それぞれ変換した開始から終了まで
+        assert_eq!(
+            parse("2025-01-01Z..2025-01-02Z"),
+            Some(with_string(parse("2025010109..2025010308").unwrap(), "2025-01-01Z..2025-01-02Z"))
+        );
+        assert_eq!(
+            parse("2025-01-01T15:00Z..2025-01-01T16:00Z").unwrap().span(),
+            parse("202501020000..202501020100").unwrap().span()
+        );
+
+        // 時差が1時間単位でないときは、分まで指定する必要がある
+        assert!(parse("2025010118+05:30").is_none());
+        assert!(parse("20250101+05:30").is_none());
+        assert!(parse("2025-01-01T18:00+25:00").is_none());
+        assert!(parse("2025-01-01T18:00+0900").is_none());
+        assert!(parse("todayZ").is_none());
+    }
+
+    fn url_hours(dt: &DT) -> usize {
+        crate::url::get_datetime_list_1h(dt, &Default::default()).len()
+    }
+
+    #[test]
+    fn time_code() {
+        let time = from_time_code("202501020300").unwrap();
+        assert_eq!(time.to_rfc3339(), "2025-01-02T03:00:00+09:00");
+        assert_eq!(time.with_timezone(&Utc).to_rfc3339(), "2025-01-01T18:00:00+00:00");
+        assert!(from_time_code("2025010203").is_none());
+    }
+
+    /// 基準日時に依存しない、数字のみの日時指定
+    fn datetime(input: &str) -> Option<DT> {
+        parse(input)
+    }
+
+    /// 範囲指定の元の文字列を置き換える
+    fn with_string(dt: DT, input: &str) -> DT {
+        match dt {
+            DT::Range { start, end, .. } => DT::Range {
+                string: input.into(),
+                start,
+                end,
+            },
+            dt => dt,
+        }
+    }
+
+    #[test]
+    fn span() {
+        let (start, end) = parse("20250130..20250201").unwrap().span();
+        assert_eq!(start, NaiveDateTime::parse_from_str("202501300000", "%Y%m%d%H%M").unwrap());
+        assert_eq!(end, NaiveDateTime::parse_from_str("202502020000", "%Y%m%d%H%M").unwrap());
+    }
 }
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use percent_encoding::utf8_percent_encode;
+use roxmltree::{Document, Node};
+use serde::Serialize;
+
+use crate::client::Client;
+use crate::endpoint::{Endpoint, PARAM_VALUE};
+use crate::response;
+use crate::url;
+
+/// WFS のレイヤーと属性の一覧
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Description {
+    /// `GetCapabilities` で取得したレイヤーの一覧
+    pub layers: Vec<Layer>,
+    /// `DescribeFeatureType` で取得したレイヤーごとの属性
+    pub feature_types: Vec<FeatureType>,
+    /// データの取得に使用するレイヤー・属性のうち、存在しないもの。属性は `レイヤー.属性` の形式
+    pub missing: Vec<String>,
+}
+
+/// レイヤー
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Layer {
+    /// レイヤー名。`typeNames` に指定する名前
+    pub name: String,
+    /// タイトル
+    pub title: Option<String>,
+}
+
+/// レイヤーの属性の一覧
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureType {
+    /// レイヤー名
+    pub name: String,
+    /// 属性
+    pub attributes: Vec<Attribute>,
+}
+
+/// 属性
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Attribute {
+    /// 属性名
+    pub name: String,
+    /// XML Schema の型 (`xsd:long`, `gml:PointPropertyType` など)
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// 値が null になりうるかどうか
+    pub nillable: bool,
+}
+
+/// `DescribeFeatureType` の URL。レイヤー名はそれぞれエンコードし、`,` で区切る
+pub fn describe_url(endpoint: &Endpoint, layers: &[String]) -> String {
+    let names: Vec<String> = layers
+        .iter()
+        .map(|name| utf8_percent_encode(name, PARAM_VALUE).to_string())
+        .collect();
+    format!("{}&typeNames={}", endpoint.request_url("DescribeFeatureType"), names.join(","))
+}
+
+/// クライアントの取得先の WFS から、レイヤーの一覧と指定したレイヤーの属性を取得する
+///
+/// `layers` が空のときは、データの取得に使用するレイヤーを対象とする。
+pub async fn describe(client: &Client, layers: &[String]) -> Result<Description> {
+    let endpoint = client.endpoint();
+    let body = client
+        .get(&endpoint.request_url("GetCapabilities"))
+        .await
+        .context("GetCapabilities に失敗")?;
+    let capabilities = parse_capabilities(&body)?;
+
+    let targets: Vec<String> = if layers.is_empty() {
+        endpoint.layers.all().map(|name| name.to_string()).to_vec()
+    } else {
+        layers.to_vec()
+    };
+    let body = client
+        .get(&describe_url(endpoint, &targets))
+        .await
+        .context("DescribeFeatureType に失敗")?;
+    let feature_types = parse_feature_types(&body)?;
+
+    let missing = missing(&endpoint.layers.all(), &capabilities, &feature_types);
+    Ok(Description {
+        layers: capabilities,
+        feature_types,
+        missing,
+    })
+}
+
+/// データの取得に使用するレイヤー・属性のうち、存在しないものを列挙する
+///
+/// 属性は、`DescribeFeatureType` で取得したレイヤーのみを対象とする。
+fn missing(expected: &[&str], layers: &[Layer], feature_types: &[FeatureType]) -> Vec<String> {
+    let mut output = vec![];
+
+    for &name in expected {
+        if !layers.iter().any(|layer| local_name(&layer.name) == name) {
+            output.push(name.to_string());
+            continue;
+        }
+        let Some(feature_type) = feature_types.iter().find(|t| local_name(&t.name) == name) else {
+            continue;
+        };
+        for attribute in url::QUERY_ATTRIBUTES {
+            if !feature_type.attributes.iter().any(|a| a.name == attribute) {
+                output.push(format!("{}.{}", name, attribute));
+            }
+        }
+    }
+
+    output
+}
+
+/// 名前空間の接頭辞 (`topp:` など) を除いた名前
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// `GetCapabilities` のレスポンスから、レイヤーの一覧を取得する
+pub fn parse_capabilities(xml: &str) -> Result<Vec<Layer>> {
+    let doc = parse_xml(xml)?;
+
+    let layers = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("FeatureType"))
+        .filter_map(|node| {
+            Some(Layer {
+                name: child_text(node, "Name")?,
+                title: child_text(node, "Title"),
+            })
+        })
+        .collect();
+    Ok(layers)
+}
+
+/// `DescribeFeatureType` のレスポンス (XML Schema) から、レイヤーごとの属性を取得する
+pub fn parse_feature_types(xml: &str) -> Result<Vec<FeatureType>> {
+    let doc = parse_xml(xml)?;
+    let schema = doc.root_element();
+
+    // 型名からレイヤー名への対応。`<xsd:element name="レイヤー名" type="接頭辞:型名"/>`
+    let names: HashMap<&str, &str> = schema
+        .children()
+        .filter(|node| node.has_tag_name("element"))
+        .filter_map(|node| Some((local_name(node.attribute("type")?), node.attribute("name")?)))
+        .collect();
+
+    let feature_types = schema
+        .children()
+        .filter(|node| node.has_tag_name("complexType"))
+        .filter_map(|node| {
+            let type_name = node.attribute("name")?;
+            let name = names
+                .get(type_name)
+                .copied()
+                .unwrap_or_else(|| type_name.strip_suffix("Type").unwrap_or(type_name));
+            let attributes = node
+                .descendants()
+                .filter(|node| node.has_tag_name("element"))
+                .filter_map(|node| {
+                    Some(Attribute {
+                        name: node.attribute("name")?.to_string(),
+                        kind: node.attribute("type").unwrap_or_default().to_string(),
+                        nillable: node.attribute("nillable") == Some("true"),
+                    })
+                })
+                .collect();
+            Some(FeatureType {
+                name: name.to_string(),
+                attributes,
+            })
+        })
+        .collect();
+    Ok(feature_types)
+}
+
+/// XML として解釈する。サーバーが例外レポート (ルート要素が `ExceptionReport`) を返したときはエラー
+fn parse_xml(xml: &str) -> Result<Document<'_>> {
+    let doc = Document::parse(xml).context("XML として解釈不能")?;
+    if doc.root_element().tag_name().name() == "ExceptionReport" {
+        bail!("{}", response::classify(xml));
+    }
+    Ok(doc)
+}
+
+/// 指定した名前の子要素のテキスト
+fn child_text(node: Node, name: &str) -> Option<String> {
+    let text = node.children().find(|child| child.has_tag_name(name))?.text()?.trim();
+    Some(text.to_string())
+}
+
+/// レイヤーと属性の一覧を、表形式で出力する
+pub fn write_table(description: &Description, output: &mut dyn Write) -> Result<()> {
+    writeln!(output, "レイヤー ({})", description.layers.len())?;
+    let rows: Vec<[&str; 2]> = description
+        .layers
+        .iter()
+        .map(|layer| [layer.name.as_str(), layer.title.as_deref().unwrap_or("")])
+        .collect();
+    write_rows(output, ["名前", "タイトル"], &rows)?;
+
+    for feature_type in &description.feature_types {
+        writeln!(output)?;
+        writeln!(output, "{} の属性 ({})", feature_type.name, feature_type.attributes.len())?;
+        let rows: Vec<[&str; 3]> = feature_type
+            .attributes
+            .iter()
+            .map(|a| [a.name.as_str(), a.kind.as_str(), if a.nillable { "○" } else { "" }])
+            .collect();
+        write_rows(output, ["名前", "型", "null"], &rows)?;
+    }
+
+    if !description.missing.is_empty() {
+        writeln!(output)?;
+        writeln!(output, "データの取得に使用するが、存在しないレイヤー・属性")?;
+        for name in &description.missing {
+            writeln!(output, "  {}", name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 列の幅を揃えて出力する
+fn write_rows<const N: usize>(output: &mut dyn Write, header: [&str; N], rows: &[[&str; N]]) -> Result<()> {
+    let mut widths = header.map(width);
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(width(cell));
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::from(" ");
+        for (i, cell) in row.iter().enumerate() {
+            line.push(' ');
+            line.push_str(cell);
+            if i + 1 < N {
+                line.push_str(&" ".repeat(widths[i] - width(cell) + 1));
+            }
+        }
+        writeln!(output, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// 端末での表示幅。ASCII 以外の文字は全角として2文字分とする
+fn width(text: &str) -> usize {
+    text.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+#[cfg(test)]
+mod describe_tests {
+    use super::*;
+    use crate::endpoint::Layers;
+    use crate::fetch::RetryPolicy;
+    use crate::rate_limit::RateLimiter;
+    use tokio::time::Duration;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CAPABILITIES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<wfs:WFS_Capabilities version="2.0.0" xmlns:wfs="http://www.opengis.net/wfs/2.0" xmlns:ows="http://www.opengis.net/ows/1.1">
+  <ows:ServiceIdentification><ows:Title>GeoServer</ows:Title></ows:ServiceIdentification>
+  <FeatureTypeList>
+    <FeatureType xmlns:jartic="http://jartic">
+      <Name>jartic:t_travospublic_measure_1h</Name>
+      <Title>常設トラカン 1時間</Title>
+    </FeatureType>
+    <FeatureType>
+      <Name>t_travospublic_measure_5m</Name>
+    </FeatureType>
+  </FeatureTypeList>
+</wfs:WFS_Capabilities>"#;
+
+    const SCHEMA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xsd:schema xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:gml="http://www.opengis.net/gml/3.2" xmlns:jartic="http://jartic">
+  <xsd:complexType name="t_travospublic_measure_1hType">
+    <xsd:complexContent>
+      <xsd:extension base="gml:AbstractFeatureType">
+        <xsd:sequence>
+          <xsd:element maxOccurs="1" minOccurs="0" name="常時観測点コード" nillable="true" type="xsd:long"/>
+          <xsd:element maxOccurs="1" minOccurs="0" name="時間コード" nillable="false" type="xsd:long"/>
+          <xsd:element maxOccurs="1" minOccurs="0" name="道路種別" nillable="true" type="xsd:string"/>
+          <xsd:element maxOccurs="1" minOccurs="0" name="ジオメトリ" nillable="true" type="gml:PointPropertyType"/>
+        </xsd:sequence>
+      </xsd:extension>
+    </xsd:complexContent>
+  </xsd:complexType>
+  <xsd:element name="t_travospublic_measure_1h" substitutionGroup="gml:AbstractFeature" type="jartic:t_travospublic_measure_1hType"/>
+  <xsd:complexType name="t_travospublic_measure_5mType">
+    <xsd:sequence>
+      <xsd:element name="時間コード" type="xsd:long"/>
+    </xsd:sequence>
+  </xsd:complexType>
+</xsd:schema>"#;
+
+    #[test]
+    fn capabilities() {
+        let layers = parse_capabilities(CAPABILITIES).unwrap();
+
+        assert_eq!(
+            layers,
+            vec![
+                Layer {
+                    name: "jartic:t_travospublic_measure_1h".into(),
+                    title: Some("常設トラカン 1時間".into()),
+                },
+                Layer {
+                    name: "t_travospublic_measure_5m".into(),
+                    title: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn feature_types() {
+        let types = parse_feature_types(SCHEMA).unwrap();
+
+        assert_eq!(types.len(), 2);
+        assert_eq!(types[0].name, "t_travospublic_measure_1h");
+        assert_eq!(
+            types[0].attributes[0],
+            Attribute {
+                name: "常時観測点コード".into(),
+                kind: "xsd:long".into(),
+                nillable: true,
+            }
+        );
+        assert_eq!(types[0].attributes[3].kind, "gml:PointPropertyType");
+        assert!(!types[0].attributes[1].nillable);
+        // 対応する要素がないときは、型名からレイヤー名を求める
+        assert_eq!(types[1].name, "t_travospublic_measure_5m");
+    }
+
+    #[test]
+    fn exception() {
+        let body = r#"<ows:ExceptionReport xmlns:ows="http://www.opengis.net/ows/1.1"><ows:Exception><ows:ExceptionText>Feature type unknown</ows:ExceptionText></ows:Exception></ows:ExceptionReport>"#;
+
+        let error = parse_feature_types(body).unwrap_err();
+        assert!(error.to_string().contains("Feature type unknown"));
+        assert!(parse_capabilities("not xml").is_err());
+
+        // ルート要素以外に含まれる ExceptionReport は例外レポートとしない
+        let capabilities = CAPABILITIES.replace("<Title>常設トラカン 1時間</Title>", "<Title>ExceptionReport</Title>");
+        assert_eq!(
+            parse_capabilities(&capabilities).unwrap()[0].title.as_deref(),
+            Some("ExceptionReport")
+        );
+    }
+
+    #[test]
+    fn url() {
+        let endpoint = Endpoint {
+            url: "https://example.com/geoserver".into(),
+            ..Default::default()
+        };
+        let url = describe_url(&endpoint, &["jartic:measure 1h".into(), "a&b=c".into()]);
+        assert!(
+            url.ends_with("&request=DescribeFeatureType&typeNames=jartic:measure%201h,a%26b%3Dc"),
+            "{url}"
+        );
+    }
+
+    #[test]
+    fn missing_layers_and_attributes() {
+        let layers = parse_capabilities(CAPABILITIES).unwrap();
+        let types = parse_feature_types(SCHEMA).unwrap();
+
+        assert_eq!(
+            missing(&Layers::default().all(), &layers, &types),
+            vec![
+                "t_travospublic_measure_5m.道路種別",
+                "t_travospublic_measure_5m.常時観測点コード",
+                "t_travospublic_measure_5m.ジオメトリ",
+                "t_travospublic_measure_1h_img",
+                "t_travospublic_measure_5m_img",
+            ]
+        );
+    }
+
+    #[test]
+    fn table() {
+        let description = Description {
+            layers: parse_capabilities(CAPABILITIES).unwrap(),
+            feature_types: parse_feature_types(SCHEMA).unwrap()[..1].to_vec(),
+            missing: vec![],
+        };
+        let mut output = vec![];
+        write_table(&description, &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "レイヤー (2)");
+        assert_eq!(lines[1], format!("  名前{}タイトル", " ".repeat(30)));
+        assert_eq!(lines[2], "  jartic:t_travospublic_measure_1h  常設トラカン 1時間");
+        assert_eq!(lines[3], "  t_travospublic_measure_5m");
+        assert_eq!(lines[6], format!("  名前{}型{}null", " ".repeat(14), " ".repeat(21)));
+        assert_eq!(lines[7], format!("  常時観測点コード  xsd:long{}○", " ".repeat(15)));
+    }
+
+    #[tokio::test]
+    async fn request() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("request", "GetCapabilities"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(CAPABILITIES))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("request", "DescribeFeatureType"))
+            .and(query_param("typeNames", "t_travospublic_measure_1h"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SCHEMA))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let endpoint = Endpoint {
+            url: format!("{}/geoserver", server.uri()),
+            ..Default::default()
+        };
+        let client = Client::new()
+            .with_endpoint(endpoint)
+            .with_rate_limiter(RateLimiter::unlimited())
+            .with_retry_policy(RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            });
+        let description = describe(&client, &["t_travospublic_measure_1h".into()]).await.unwrap();
+
+        assert_eq!(description.layers.len(), 2);
+        assert_eq!(description.feature_types.len(), 2);
+
+        let json = serde_json::to_value(&description).unwrap();
+        assert_eq!(json["feature_types"][0]["attributes"][0]["type"], "xsd:long");
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result, ensure};
+use percent_encoding::{AsciiSet, utf8_percent_encode};
+
+use crate::config::EndpointConfig;
+use crate::cql::QUERY_VALUE;
+use crate::types::{CounterType, EndpointArgs, Interval};
+
+/// WFS のエンドポイントの URL のデフォルト値
+pub const DEFAULT_URL: &str = "https://api.jartic-open-traffic.org/geoserver";
+/// 座標参照系のデフォルト値
+pub const DEFAULT_SRS_NAME: &str = "EPSG:4326";
+/// 出力形式のデフォルト値
+pub const DEFAULT_OUTPUT_FORMAT: &str = "application/json";
+
+/// パラメーターの値のうち、エンコードする文字。`EPSG:4326` や `application/json` が読みやすいよう `:` と `/` はそのまま残す
+pub(crate) const PARAM_VALUE: &AsciiSet = &QUERY_VALUE.remove(b':').remove(b'/');
+
+/// データの取得先の WFS の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    /// エンドポイントの URL。クエリ文字列を含んでもよい
+    pub url: String,
+    /// 取得するデータの座標参照系 (`srsName`)
+    pub srs_name: String,
+    /// 出力形式 (`outputFormat`)。GeoJSON を返す形式であること
+    pub output_format: String,
+    /// 取得間隔・観測機器ごとのレイヤー名
+    pub layers: Layers,
+}
+
+/// 取得間隔・観測機器ごとのレイヤー名
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layers {
+    /// 常設トラカン・1時間
+    pub permanent_1h: String,
+    /// 常設トラカン・5分
+    pub permanent_5m: String,
+    /// CCTVトラカン・1時間
+    pub cctv_1h: String,
+    /// CCTVトラカン・5分
+    pub cctv_5m: String,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint {
+            url: DEFAULT_URL.into(),
+            srs_name: DEFAULT_SRS_NAME.into(),
+            output_format: DEFAULT_OUTPUT_FORMAT.into(),
+            layers: Layers::default(),
+        }
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            permanent_1h: "t_travospublic_measure_1h".into(),
+            permanent_5m: "t_travospublic_measure_5m".into(),
+            cctv_1h: "t_travospublic_measure_1h_img".into(),
+            cctv_5m: "t_travospublic_measure_5m_img".into(),
+        }
+    }
+}
+
+impl Layers {
+    /// 取得間隔・観測機器に対応するレイヤー名
+    pub fn get(&self, interval: Interval, counter_type: CounterType) -> &str {
+        match (counter_type, interval) {
+            (CounterType::Permanent, Interval::H1) => &self.permanent_1h,
+            (CounterType::Permanent, Interval::M5) => &self.permanent_5m,
+            (CounterType::Cctv, Interval::H1) => &self.cctv_1h,
+            (CounterType::Cctv, Interval::M5) => &self.cctv_5m,
+        }
+    }
+
+    /// すべてのレイヤー名
+    pub fn all(&self) -> [&str; 4] {
+        [&self.permanent_1h, &self.permanent_5m, &self.cctv_1h, &self.cctv_5m]
+    }
+}
+
+impl Endpoint {
+    /// コマンドライン(環境変数を含む)と設定ファイルから生成する。コマンドラインの指定を優先し、いずれも未指定の項目はデフォルト値とする
+    pub fn from_args(args: &EndpointArgs, config: &EndpointConfig) -> Result<Self> {
+        let default = Endpoint::default();
+        let pick =
+            |arg: &Option<String>, config: &Option<String>, default: String| arg.clone().or_else(|| config.clone()).unwrap_or(default);
+
+        let endpoint = Endpoint {
+            url: pick(&args.endpoint, &config.url, default.url),
+            srs_name: pick(&args.srs_name, &config.srs_name, default.srs_name),
+            output_format: pick(&args.output_format, &config.output_format, default.output_format),
+            layers: Layers {
+                permanent_1h: pick(&args.layer_permanent_1h, &config.layers.permanent_1h, default.layers.permanent_1h),
+                permanent_5m: pick(&args.layer_permanent_5m, &config.layers.permanent_5m, default.layers.permanent_5m),
+                cctv_1h: pick(&args.layer_cctv_1h, &config.layers.cctv_1h, default.layers.cctv_1h),
+                cctv_5m: pick(&args.layer_cctv_5m, &config.layers.cctv_5m, default.layers.cctv_5m),
+            },
+        };
+        endpoint.validate()?;
+
+        Ok(endpoint)
+    }
+
+    /// 設定値を検証する
+    fn validate(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.url).with_context(|| format!("エンドポイント {} を URL として解釈不能", self.url))?;
+        ensure!(
+            matches!(url.scheme(), "http" | "https") && url.fragment().is_none(),
+            "エンドポイント {} は http または https の URL で指定する必要がある",
+            self.url
+        );
+        ensure!(!self.srs_name.trim().is_empty(), "座標参照系が空");
+        ensure!(!self.output_format.trim().is_empty(), "出力形式が空");
+        for layer in self.layers.all() {
+            ensure!(
+                !layer.is_empty() && !layer.contains([',', '&', ' ']),
+                "レイヤー名 {:?} は空白・`,`・`&` を含まない名前で指定する必要がある",
+                layer
+            );
+        }
+        Ok(())
+    }
+
+    /// 指定した種類のリクエスト (`GetFeature` など) の URL。追加のパラメーターは `&` に続けて付加する
+    pub fn request_url(&self, request: &str) -> String {
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        format!("{}{}service=WFS&version=2.0.0&request={}", self.url, separator, request)
+    }
+
+    /// `GetFeature` の URL のうち、絞り込み条件 (`cql_filter`) より前の部分
+    pub fn get_feature_url(&self, layer: &str) -> String {
+        format!(
+            "{}&typeNames={}&srsName={}&outputFormat={}&exceptions=application/json",
+            self.request_url("GetFeature"),
+            utf8_percent_encode(layer, PARAM_VALUE),
+            utf8_percent_encode(&self.srs_name, PARAM_VALUE),
+            utf8_percent_encode(&self.output_format, PARAM_VALUE)
+        )
+    }
+}
+
+#[cfg(test)]
+mod endpoint_tests {
+    use super::*;
+    use crate::config::LayersConfig;
+
+    #[test]
+    fn default() {
+        let endpoint = Endpoint::from_args(&EndpointArgs::default(), &EndpointConfig::default()).unwrap();
+
+        assert_eq!(endpoint, Endpoint::default());
+        assert_eq!(
+            endpoint.get_feature_url("t_travospublic_measure_1h"),
+            "https://api.jartic-open-traffic.org/geoserver?service=WFS&version=2.0.0&request=GetFeature&typeNames=t_travospublic_measure_1h&srsName=EPSG:4326&outputFormat=application/json&exceptions=application/json"
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        let args = EndpointArgs {
+            endpoint: Some("http://localhost:8080/geoserver".into()),
+            layer_cctv_5m: Some("cctv_5m".into()),
+            ..Default::default()
+        };
+        let config = EndpointConfig {
+            url: Some("https://mirror.example.com/geoserver".into()),
+            srs_name: Some("EPSG:6668".into()),
+            layers: LayersConfig {
+                cctv_5m: Some("mirror_cctv_5m".into()),
+                cctv_1h: Some("mirror_cctv_1h".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let endpoint = Endpoint::from_args(&args, &config).unwrap();
+
+        // コマンドライン > 設定ファイル > デフォルト値
+        assert_eq!(endpoint.url, "http://localhost:8080/geoserver");
+        assert_eq!(endpoint.srs_name, "EPSG:6668");
+        assert_eq!(endpoint.output_format, "application/json");
+        assert_eq!(
+            endpoint.layers.all(),
+            [
+                "t_travospublic_measure_1h",
+                "t_travospublic_measure_5m",
+                "mirror_cctv_1h",
+                "cctv_5m"
+            ]
+        );
+        assert_eq!(endpoint.layers.get(Interval::M5, CounterType::Cctv), "cctv_5m");
+    }
+
+    #[test]
+    fn request_url() {
+        let endpoint = Endpoint {
+            url: "http://proxy.example.com/wfs?key=abc".into(),
+            output_format: "application/json; subtype=geojson".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            endpoint.request_url("GetCapabilities"),
+            "http://proxy.example.com/wfs?key=abc&service=WFS&version=2.0.0&request=GetCapabilities"
+        );
+        assert!(
+            endpoint
+                .get_feature_url("layer")
+                .ends_with("&outputFormat=application/json%3B%20subtype%3Dgeojson&exceptions=application/json")
+        );
+
+        // レイヤー名もエンコードする。名前空間の `:` はそのまま残す
+        let url = endpoint.get_feature_url("ns:a#b+c%d?e=f");
+        assert!(url.contains("&typeNames=ns:a%23b%2Bc%25d%3Fe%3Df&srsName="), "{url}");
+    }
+
+    #[test]
+    fn invalid() {
+        for url in [
+            "api.jartic-open-traffic.org/geoserver",
+            "ftp://example.com/",
+            "http://example.com/#a",
+        ] {
+            let args = EndpointArgs {
+                endpoint: Some(url.into()),
+                ..Default::default()
+            };
+            assert!(Endpoint::from_args(&args, &EndpointConfig::default()).is_err());
+        }
+
+        let args = EndpointArgs {
+            layer_permanent_1h: Some("a,b".into()),
+            ..Default::default()
+        };
+        assert!(Endpoint::from_args(&args, &EndpointConfig::default()).is_err());
+    }
+}
@@ -1,7 +1,19 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use chrono::TimeDelta;
+use tokio::time::Duration;
 
+use crate::calendar::{self, DayOfWeek, Holidays, SlotFilter};
+use crate::compress::Compression;
+use crate::config;
 use crate::datetime;
-use crate::types::{Cli, RoadType};
+use crate::endpoint::Endpoint;
+use crate::fetch::RetryPolicy;
+use crate::paging;
+use crate::rate_limit;
+use crate::spatial;
+use crate::template;
+use crate::types::{AnomalyAction, CounterType, FetchArgs, GeometryEncoding, Interval, RoadType, SinkKind};
+use crate::url::Filter;
 
 /// 実行時のオプションを保持する構造体
 #[derive(Debug)]
@@ -13,6 +25,8 @@ pub struct ExecutionOption {
     pub interval_h1: bool,
     /// 取得間隔：5分ごと
     pub interval_m5: bool,
+    /// 取得対象の時間帯の絞り込み条件
+    pub slot_filter: SlotFilter,
 
     /// 観測機器：常設トラカンを取得対象とするかどうか
     pub type_permanent: bool,
@@ -24,6 +38,48 @@ pub struct ExecutionOption {
     /// 道路種別：一般国道を取得対象とするかどうか
     pub road_normal: bool,
 
+    /// 道路種別と時間コード以外の絞り込み条件
+    pub filter: Filter,
+
+    /// データの取得先
+    pub endpoint: Endpoint,
+
+    /// 同時に取得するデータの最大数
+    pub concurrency: usize,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: f64,
+    /// 1回のリクエストで取得する地物の最大数。None のときはページ分割しない
+    pub page_size: Option<usize>,
+
+    /// 取得失敗時の再試行の設定
+    pub retry: RetryPolicy,
+
+    /// 保存先のディレクトリ
+    pub out_dir: String,
+    /// 保存先のディレクトリからの相対パスのテンプレート
+    pub name_template: String,
+    /// 保存先のディレクトリへ保存するファイルの圧縮。`None` のときは圧縮しない
+    pub compression: Option<Compression>,
+
+    /// 取得したデータの保存先。重複なし
+    pub sinks: Vec<SinkKind>,
+    /// Parquet 形式での保存先のディレクトリ
+    pub parquet_dir: Option<String>,
+    /// Parquet に保存する観測地点の位置の形式
+    pub parquet_geometry: GeometryEncoding,
+    /// SQLite のデータベースのパス
+    pub sqlite_path: Option<String>,
+
+    /// 正常なデータ以外を取得したときの扱い
+    pub on_anomaly: AnomalyAction,
+    /// 正常なデータ以外を隔離する場合の保存先ディレクトリ
+    pub quarantine_dir: String,
+
+    /// 保存済みのデータの取得を省略するかどうか
+    pub resume: bool,
+    /// 取得を省略する際に、データなし・サーバー例外として保存済みのデータを再取得するかどうか
+    pub refetch_anomalies: bool,
+
     /// 先頭の1つのみを対象とするかどうか
     pub one: bool,
     /// 実際のデータ取得を行わないドライランを行うかどうか
@@ -32,34 +88,182 @@ pub struct ExecutionOption {
 
 impl ExecutionOption {
     /// コマンドラインの実行時オプションから、実際のコード実行時のオプションを生成する
-    pub fn from_args(args: &Cli) -> Result<Self> {
-        let dt = datetime::parse(&args.date).with_context(|| format!("{} を日時指定として解釈不能", args.date))?;
+    ///
+    /// 設定ファイル (`traffic-dl.toml`) の設定を読み込み、コマンドラインで指定していない項目に使用する。
+    pub fn from_args(args: &FetchArgs) -> Result<Self> {
+        let date = args.date.as_deref().context("日時が指定されていない")?;
+        let dt = datetime::parse(date).with_context(|| format!("{} を日時指定として解釈不能", date))?;
+
+        Self::with_datetime(args, dt)
+    }
+
+    /// コマンドラインの日時指定の代わりに、指定した日時で実行時のオプションを生成する
+    ///
+    /// `watch` のように、取得する日時を実行中に決める場合に使用する。
+    pub fn with_datetime(args: &FetchArgs, dt: datetime::DT) -> Result<Self> {
+        let settings = config::load(&args.config)?;
 
         // 取得間隔
         // - 未指定時は1時間ごとのデータのみを取得
         // - `--5m` 指定時は、5分間ごとのデータのみを取得
         // - `--1h` と `--5m` の両方指定時は、両方のデータを取得
-        let h1 = args.h1 || !args.m5;
-        let m5 = args.m5;
+        // - いずれも指定せず、設定ファイルに `intervals` があるときはその指定に従う
+        let (h1, m5) = match &settings.intervals {
+            Some(intervals) if !args.h1 && !args.m5 => (intervals.contains(&Interval::H1), intervals.contains(&Interval::M5)),
+            _ => (args.h1 || !args.m5, args.m5),
+        };
+        ensure!(h1 || m5, "設定ファイルの intervals に取得間隔が指定されていない");
+
+        // 時間帯の絞り込み
+        // 祝日による絞り込みは、祝日の一覧に含まれる期間のみ指定できる
+        let slot_filter = SlotFilter {
+            day_of_week: match (args.weekdays, args.weekends) {
+                (true, _) => Some(DayOfWeek::Weekdays),
+                (_, true) => Some(DayOfWeek::Weekends),
+                _ => None,
+            },
+            hours: args.hours.as_deref().map(calendar::parse_hours).transpose()?.unwrap_or_default(),
+            holidays: match (args.exclude_holidays, args.only_holidays) {
+                (true, _) => Some(Holidays::Exclude),
+                (_, true) => Some(Holidays::Only),
+                _ => None,
+            },
+        };
+        let (start, end) = dt.span();
+        slot_filter.ensure_covered(start, end - TimeDelta::minutes(1))?;
 
         // 取得対象のセンサー。常設トラカンとCCTVトラカン
         // 基本的には両方とも対象とするが、片方のみが実行時に指定された場合はそちらのみを対象にする。
-        let type_permanent = args.permanent || !args.cctv;
-        let type_cctv = !args.permanent || args.cctv;
+        // いずれも指定せず、設定ファイルに `counters` があるときはその指定に従う
+        let (type_permanent, type_cctv) = match &settings.counters {
+            Some(counters) if !args.permanent && !args.cctv => {
+                (counters.contains(&CounterType::Permanent), counters.contains(&CounterType::Cctv))
+            }
+            _ => (args.permanent || !args.cctv, !args.permanent || args.cctv),
+        };
+        ensure!(type_permanent || type_cctv, "設定ファイルの counters に観測機器が指定されていない");
 
         // 道路種別
         // 未指定時は両方を対象とするが、片方のみが実行時に指定された場合はそちらのみを対象にする。
-        let road_highway = args.highway || !args.normal;
-        let road_normal = !args.highway || args.normal;
+        // いずれも指定せず、設定ファイルに `roads` があるときはその指定に従う
+        let (road_highway, road_normal) = match &settings.roads {
+            Some(roads) if !args.highway && !args.normal => (
+                roads.iter().any(|r| matches!(r, RoadType::Highway | RoadType::Both)),
+                roads.iter().any(|r| matches!(r, RoadType::Normal | RoadType::Both)),
+            ),
+            _ => (args.highway || !args.normal, !args.highway || args.normal),
+        };
+        ensure!(road_highway || road_normal, "設定ファイルの roads に道路種別が指定されていない");
+
+        // 観測地点
+        // `--station` と `--stations-file` の両方が指定されたときは、いずれかで指定されたコードをすべて対象とする
+        // いずれも指定しないときは、設定ファイルの `stations` を使用する
+        let mut stations = if args.stations.is_empty() && args.stations_file.is_none() {
+            settings.stations.clone().unwrap_or_default()
+        } else {
+            args.stations.clone()
+        };
+        if let Some(path) = &args.stations_file {
+            let text = std::fs::read_to_string(path).with_context(|| format!("観測地点のファイル {} を読み込めない", path))?;
+            stations.extend(parse_station_list(&text));
+        }
+        let filter = Filter {
+            stations: normalize_stations(stations)?,
+            bbox: args.bbox.as_deref().map(spatial::parse_bbox).transpose()?,
+            within: args.within.as_deref().map(spatial::read_polygon_file).transpose()?,
+        };
+
+        // 取得先
+        // コマンドライン(環境変数を含む)の指定を優先し、未指定の項目は設定ファイルの値を使用する
+        let endpoint = Endpoint::from_args(&args.endpoint, &settings.endpoint)?;
+
+        // 取得頻度
+        // 未指定時は1つずつ、1秒あたり1回までの頻度で取得する
+        let concurrency = args.concurrency.or(settings.concurrency).unwrap_or(1);
+        ensure!(concurrency >= 1, "同時に取得するデータの数は 1 以上で指定する必要がある");
+        let rate = args.rate.or(settings.rate).unwrap_or(1.0);
+        rate_limit::validate_rate(rate)?;
+
+        // ページ分割
+        // 0 を指定したときはページ分割しない
+        let page_size = match args.page_size {
+            Some(0) => None,
+            Some(n) => Some(n),
+            None => Some(paging::DEFAULT_PAGE_SIZE),
+        };
+
+        // 再試行
+        // 未指定の項目はデフォルト値を使用する
+        let mut retry = RetryPolicy::default();
+        if let Some(retries) = args.retries {
+            retry.max_retries = retries;
+        }
+        if let Some(wait) = args.retry_wait {
+            retry.base_delay =
+                Duration::try_from_secs_f64(wait).map_err(|_| anyhow!("再試行の待ち時間 {} は 0 以上の秒数で指定する必要がある", wait))?;
+        }
+
+        // 保存先
+        let name_template = args.name_template.clone().unwrap_or_else(|| template::DEFAULT_TEMPLATE.into());
+        template::validate(&name_template)?;
+        let compression = args
+            .compress
+            .map(|format| Compression::new(format, args.compress_level))
+            .transpose()?;
+
+        // 保存先。`--parquet` / `--sqlite` は対応する保存先を追加する
+        let mut requested = if args.sinks.is_empty() {
+            vec![SinkKind::Dir]
+        } else {
+            args.sinks.clone()
+        };
+        if args.parquet.is_some() {
+            requested.push(SinkKind::Parquet);
+        }
+        if args.sqlite.is_some() {
+            requested.push(SinkKind::Sqlite);
+        }
+        let mut sinks = vec![];
+        for kind in requested {
+            if !sinks.contains(&kind) {
+                sinks.push(kind);
+            }
+        }
+        ensure!(
+            !sinks.contains(&SinkKind::Parquet) || args.parquet.is_some(),
+            "--sink parquet を指定したときは、--parquet で保存先のディレクトリを指定する必要がある"
+        );
+        ensure!(
+            !sinks.contains(&SinkKind::Sqlite) || args.sqlite.is_some(),
+            "--sink sqlite を指定したときは、--sqlite でデータベースのパスを指定する必要がある"
+        );
 
         let execution_option = ExecutionOption {
             datetime: dt,
             interval_h1: h1,
             interval_m5: m5,
+            slot_filter,
             type_permanent,
             type_cctv,
             road_highway,
             road_normal,
+            filter,
+            endpoint,
+            concurrency,
+            rate,
+            page_size,
+            retry,
+            out_dir: args.out_dir.clone().or(settings.out_dir).unwrap_or_else(|| "data".into()),
+            name_template,
+            compression,
+            sinks,
+            parquet_dir: args.parquet.clone(),
+            parquet_geometry: args.parquet_geometry.unwrap_or(GeometryEncoding::Lonlat),
+            sqlite_path: args.sqlite.clone(),
+            on_anomaly: args.on_anomaly.unwrap_or(AnomalyAction::Save),
+            quarantine_dir: args.quarantine_dir.clone().unwrap_or_else(|| "quarantine".into()),
+            resume: args.resume,
+            refetch_anomalies: args.refetch_anomalies,
             one: args.one,
             dry: args.dry,
         };
@@ -80,20 +284,85 @@ impl ExecutionOption {
     }
 }
 
+/// 観測地点のファイルの内容から、常時観測点コードのリストを取得する
+/// - `#` 以降は行末までコメントとして扱う
+/// - 1行に複数のコードを `,` または空白区切りで記述できる
+fn parse_station_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|code| !code.is_empty())
+        .map(|code| code.to_string())
+        .collect()
+}
+
+/// 常時観測点コードを検証し、先頭の 0 を取り除いて重複を除いたリストを返す。数字以外を含むコードはエラー
+fn normalize_stations(stations: Vec<String>) -> Result<Vec<String>> {
+    let mut output: Vec<String> = vec![];
+
+    for code in stations {
+        let code = code.trim();
+        let number = match code.parse::<i64>() {
+            Ok(number) if code.chars().all(|c| c.is_ascii_digit()) => number,
+            _ => bail!("常時観測点コード {} は数字のみで指定する必要がある", code),
+        };
+        // 常時観測点コードは数値の属性のため、先頭の 0 を取り除いた表記にそろえる
+        let code = number.to_string();
+        if !output.contains(&code) {
+            output.push(code);
+        }
+    }
+
+    Ok(output)
+}
+
 #[cfg(test)]
 mod execute_option_from_args_test {
     use super::*;
+    use crate::types::{ConfigArgs, EndpointArgs};
 
-    /// テスト用の Cli 構造体を生成する
-    fn default_args() -> Cli {
-        Cli {
-            date: "20250102".to_string(),
+    /// テスト用の FetchArgs 構造体を生成する
+    fn default_args() -> FetchArgs {
+        FetchArgs {
+            date: Some("20250102".to_string()),
             h1: true,
             m5: false,
+            weekdays: false,
+            weekends: false,
+            hours: None,
+            exclude_holidays: false,
+            only_holidays: false,
             permanent: false,
             cctv: false,
             highway: true,
             normal: true,
+            stations: vec![],
+            stations_file: None,
+            bbox: None,
+            within: None,
+            concurrency: None,
+            rate: None,
+            page_size: None,
+            retries: None,
+            retry_wait: None,
+            endpoint: EndpointArgs::default(),
+            // 開発環境の設定ファイルに影響されないよう、設定ファイルを読み込まない
+            config: ConfigArgs {
+                no_config: true,
+                ..Default::default()
+            },
+            out_dir: None,
+            name_template: None,
+            sinks: vec![],
+            parquet: None,
+            parquet_geometry: None,
+            sqlite: None,
+            compress: None,
+            compress_level: None,
+            on_anomaly: None,
+            quarantine_dir: None,
+            resume: false,
+            refetch_anomalies: false,
             one: false,
             dry: false,
         }
@@ -107,7 +376,7 @@ mod execute_option_from_args_test {
         fn valid() {
             let mut args = default_args();
 
-            args.date = "20250901".into();
+            args.date = Some("20250901".into());
             assert!(ExecutionOption::from_args(&args).is_ok());
         }
 
@@ -115,22 +384,22 @@ mod execute_option_from_args_test {
         fn invalid() {
             let mut args = default_args();
 
-            args.date = "2025090".into();
+            args.date = Some("2025090".into());
             assert!(ExecutionOption::from_args(&args).is_err());
 
-            args.date = "202509".into();
+            args.date = Some("202509".into());
             assert!(ExecutionOption::from_args(&args).is_err());
 
-            args.date = "20251".into();
+            args.date = Some("20251".into());
             assert!(ExecutionOption::from_args(&args).is_err());
 
-            args.date = "2025".into();
+            args.date = Some("2025".into());
             assert!(ExecutionOption::from_args(&args).is_err());
 
-            args.date = "abc".into();
+            args.date = Some("abc".into());
             assert!(ExecutionOption::from_args(&args).is_err());
 
-            args.date = "".into();
+            args.date = Some("".into());
             assert!(ExecutionOption::from_args(&args).is_err());
         }
     }
@@ -184,6 +453,50 @@ mod execute_option_from_args_test {
         }
     }
 
+    #[cfg(test)]
+    mod 時間帯の絞り込み {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.slot_filter, SlotFilter::default());
+        }
+
+        #[test]
+        fn specified() {
+            let mut args = default_args();
+            args.weekdays = true;
+            args.hours = Some("7-10,17-20".into());
+            args.exclude_holidays = true;
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(
+                result.slot_filter,
+                SlotFilter {
+                    day_of_week: Some(DayOfWeek::Weekdays),
+                    hours: vec![(7, 10), (17, 20)],
+                    holidays: Some(Holidays::Exclude),
+                }
+            );
+        }
+
+        #[test]
+        fn invalid() {
+            let mut args = default_args();
+            args.hours = Some("7-25".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            // 祝日の一覧に含まれない期間
+            let mut args = default_args();
+            args.date = Some("20181231..20190102".into());
+            args.only_holidays = true;
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
     #[cfg(test)]
     mod 取得対象 {
         use super::*;
@@ -281,4 +594,485 @@ mod execute_option_from_args_test {
             assert!(result.road_normal);
         }
     }
+
+    #[cfg(test)]
+    mod 再試行 {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.retry, RetryPolicy::default());
+        }
+
+        #[test]
+        fn specified() {
+            let mut args = default_args();
+            args.retries = Some(5);
+            args.retry_wait = Some(0.5);
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.retry.max_retries, 5);
+            assert_eq!(result.retry.base_delay, Duration::from_millis(500));
+        }
+
+        #[test]
+        fn invalid_wait() {
+            let mut args = default_args();
+            args.retry_wait = Some(-1.0);
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            args.retry_wait = Some(f64::NAN);
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            // Duration で表せない秒数
+            args.retry_wait = Some(1e30);
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod 異常時の扱い {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.on_anomaly, AnomalyAction::Save);
+            assert_eq!(result.quarantine_dir, "quarantine");
+        }
+
+        #[test]
+        fn quarantine() {
+            let mut args = default_args();
+            args.on_anomaly = Some(AnomalyAction::Quarantine);
+            args.quarantine_dir = Some("broken".into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.on_anomaly, AnomalyAction::Quarantine);
+            assert_eq!(result.quarantine_dir, "broken");
+        }
+    }
+
+    #[cfg(test)]
+    mod 保存先 {
+        use super::*;
+        use crate::types::CompressFormat;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.out_dir, "data");
+            assert_eq!(result.name_template, "{name}.json");
+            assert_eq!(result.parquet_dir, None);
+            assert_eq!(result.parquet_geometry, GeometryEncoding::Lonlat);
+            assert_eq!(result.sqlite_path, None);
+        }
+
+        #[test]
+        fn specified() {
+            let mut args = default_args();
+            args.out_dir = Some("/var/lake".into());
+            args.name_template = Some("{interval}/{yyyy}/{mm}/{dd}/{counter}_{time}.json".into());
+            args.parquet = Some("/var/parquet".into());
+            args.parquet_geometry = Some(GeometryEncoding::Wkb);
+            args.sqlite = Some("traffic.db".into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.out_dir, "/var/lake");
+            assert_eq!(result.name_template, "{interval}/{yyyy}/{mm}/{dd}/{counter}_{time}.json");
+            assert_eq!(result.parquet_dir.as_deref(), Some("/var/parquet"));
+            assert_eq!(result.parquet_geometry, GeometryEncoding::Wkb);
+            assert_eq!(result.sqlite_path.as_deref(), Some("traffic.db"));
+        }
+
+        #[test]
+        fn sinks() {
+            let mut args = default_args();
+            assert_eq!(ExecutionOption::from_args(&args).unwrap().sinks, [SinkKind::Dir]);
+
+            // `--parquet` / `--sqlite` は保存先を追加する
+            args.parquet = Some("parquet".into());
+            args.sqlite = Some("traffic.db".into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+            assert_eq!(result.sinks, [SinkKind::Dir, SinkKind::Parquet, SinkKind::Sqlite]);
+
+            // `--sink` を指定したときは、デフォルトのディレクトリへは保存しない
+            args.sinks = vec![SinkKind::Stdout, SinkKind::Sqlite];
+            let result = ExecutionOption::from_args(&args).unwrap();
+            assert_eq!(result.sinks, [SinkKind::Stdout, SinkKind::Sqlite, SinkKind::Parquet]);
+        }
+
+        #[test]
+        fn sink_without_path() {
+            let mut args = default_args();
+            args.sinks = vec![SinkKind::Parquet];
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            let mut args = default_args();
+            args.sinks = vec![SinkKind::Sqlite];
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+
+        #[test]
+        fn compression() {
+            let args = default_args();
+            assert_eq!(ExecutionOption::from_args(&args).unwrap().compression, None);
+
+            let mut args = default_args();
+            args.compress = Some(CompressFormat::Zstd);
+            args.compress_level = Some(19);
+            let result = ExecutionOption::from_args(&args).unwrap();
+            assert_eq!(result.compression.map(|c| (c.format, c.level)), Some((CompressFormat::Zstd, 19)));
+
+            // 形式ごとの範囲外の圧縮レベルはエラー
+            let mut args = default_args();
+            args.compress = Some(CompressFormat::Gzip);
+            args.compress_level = Some(19);
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+
+        #[test]
+        fn invalid_template() {
+            let mut args = default_args();
+            args.name_template = Some("{date}.json".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod 取得頻度 {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.concurrency, 1);
+            assert_eq!(result.rate, 1.0);
+        }
+
+        #[test]
+        fn specified() {
+            let mut args = default_args();
+            args.concurrency = Some(4);
+            args.rate = Some(2.5);
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.concurrency, 4);
+            assert_eq!(result.rate, 2.5);
+        }
+
+        #[test]
+        fn invalid() {
+            let mut args = default_args();
+            args.concurrency = Some(0);
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            let mut args = default_args();
+            args.rate = Some(0.0);
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            args.rate = Some(f64::INFINITY);
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            // リクエストの間隔が長すぎる値
+            args.rate = Some(1e-20);
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod 取得先 {
+        use super::*;
+        use crate::types::Cli;
+        use clap::CommandFactory;
+
+        #[test]
+        fn unspecified() {
+            let result = ExecutionOption::from_args(&default_args()).unwrap();
+
+            assert_eq!(result.endpoint, Endpoint::default());
+        }
+
+        #[test]
+        fn config_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("traffic-dl.toml");
+            std::fs::write(
+                &path,
+                "[endpoint]\nurl = \"http://localhost:8080/geoserver\"\nsrs-name = \"EPSG:6668\"\n[endpoint.layers]\npermanent-1h = \"mirror_1h\"\n",
+            )
+            .unwrap();
+
+            let mut args = default_args();
+            args.config.config = Some(path.to_string_lossy().into());
+            args.config.no_config = false;
+            args.endpoint.srs_name = Some("EPSG:3857".into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            // コマンドラインの指定が設定ファイルより優先される
+            assert_eq!(result.endpoint.url, "http://localhost:8080/geoserver");
+            assert_eq!(result.endpoint.srs_name, "EPSG:3857");
+            assert_eq!(result.endpoint.layers.permanent_1h, "mirror_1h");
+
+            let targets = crate::url::create_targets(&result);
+            assert!(targets[0].url.starts_with(
+                "http://localhost:8080/geoserver?service=WFS&version=2.0.0&request=GetFeature&typeNames=mirror_1h&srsName=EPSG:3857&"
+            ));
+        }
+
+        #[test]
+        fn env() {
+            // 環境変数はコマンドラインの引数として clap が解釈する
+            let command = Cli::command();
+            let env = |id: &str| {
+                command
+                    .get_arguments()
+                    .find(|arg| arg.get_id() == id)
+                    .and_then(|arg| arg.get_env())
+                    .map(|env| env.to_string_lossy().to_string())
+            };
+
+            assert_eq!(env("endpoint").as_deref(), Some("TRAFFIC_DL_ENDPOINT"));
+            assert_eq!(env("srs_name").as_deref(), Some("TRAFFIC_DL_SRS_NAME"));
+            assert_eq!(env("output_format").as_deref(), Some("TRAFFIC_DL_OUTPUT_FORMAT"));
+            assert_eq!(env("layer_cctv_5m").as_deref(), Some("TRAFFIC_DL_LAYER_CCTV_5M"));
+            assert_eq!(env("config").as_deref(), Some("TRAFFIC_DL_CONFIG"));
+            assert_eq!(env("profile").as_deref(), Some("TRAFFIC_DL_PROFILE"));
+        }
+
+        #[test]
+        fn invalid() {
+            let mut args = default_args();
+            args.endpoint.endpoint = Some("localhost:8080".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            let mut args = default_args();
+            args.config.config = Some("not-exists.toml".into());
+            args.config.no_config = false;
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod ページ分割 {
+        use super::*;
+
+        #[test]
+        fn page_size() {
+            let mut args = default_args();
+            assert_eq!(ExecutionOption::from_args(&args).unwrap().page_size, Some(10000));
+
+            args.page_size = Some(500);
+            assert_eq!(ExecutionOption::from_args(&args).unwrap().page_size, Some(500));
+
+            // 0 のときはページ分割しない
+            args.page_size = Some(0);
+            assert_eq!(ExecutionOption::from_args(&args).unwrap().page_size, None);
+        }
+    }
+
+    #[cfg(test)]
+    mod 観測地点 {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert!(result.filter.stations.is_empty());
+        }
+
+        #[test]
+        fn station_and_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("stations.txt");
+            std::fs::write(&path, "# 首都高\n3310840\n3310850, 3310860 # 重複\n\n3310840\n").unwrap();
+
+            let mut args = default_args();
+            args.stations = vec!["1110010".into(), "3310850".into()];
+            args.stations_file = Some(path.to_string_lossy().into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            // 指定順に、重複を除いたリストになることを確認
+            assert_eq!(result.filter.stations, vec!["1110010", "3310850", "3310840", "3310860"]);
+        }
+
+        #[test]
+        fn leading_zeros() {
+            let mut args = default_args();
+            args.stations = vec!["03310840".into(), "3310840".into(), "0".into()];
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            // 数値として同じコードは重複として除く
+            assert_eq!(result.filter.stations, vec!["3310840", "0"]);
+        }
+
+        #[test]
+        fn invalid() {
+            let mut args = default_args();
+            args.stations = vec!["3310840' OR '1'='1".into()];
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            args.stations = vec!["99999999999999999999".into()];
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            let mut args = default_args();
+            args.stations_file = Some("not-exists/stations.txt".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod 空間条件 {
+        use super::*;
+
+        #[test]
+        fn unspecified() {
+            let args = default_args();
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert!(result.filter.bbox.is_none());
+            assert!(result.filter.within.is_none());
+        }
+
+        #[test]
+        fn specified() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("area.geojson");
+            std::fs::write(&path, r#"{"type":"Polygon","coordinates":[[[139,35],[140,35],[140,36],[139,35]]]}"#).unwrap();
+
+            let mut args = default_args();
+            args.bbox = Some("139.5,35.5,140.0,35.9".into());
+            args.within = Some(path.to_string_lossy().into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            assert_eq!(result.filter.bbox.unwrap().max_lat, 35.9);
+            assert_eq!(result.filter.within.unwrap(), "POLYGON((139 35,140 35,140 36,139 35))");
+        }
+
+        #[test]
+        fn invalid() {
+            let mut args = default_args();
+            args.bbox = Some("139.5,35.5".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+
+            let mut args = default_args();
+            args.within = Some("not-exists/area.geojson".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod 設定ファイル {
+        use super::*;
+
+        const CONFIG: &str = r#"
+            intervals = ["5m"]
+            counters = ["cctv"]
+            roads = ["normal"]
+            stations = [3310840]
+            out-dir = "/var/traffic"
+            concurrency = 2
+            rate = 0.5
+
+            [profile.tokyo-highway]
+            roads = ["highway"]
+            stations = ["3310850", "3310860"]
+            concurrency = 4
+        "#;
+
+        /// 設定ファイルを書き込み、取得間隔・観測機器・道路種別を指定しない引数を生成する
+        fn args_with_config(dir: &tempfile::TempDir) -> FetchArgs {
+            let path = dir.path().join("traffic-dl.toml");
+            std::fs::write(&path, CONFIG).unwrap();
+
+            let mut args = default_args();
+            args.h1 = false;
+            args.highway = false;
+            args.normal = false;
+            args.config.config = Some(path.to_string_lossy().into());
+            args.config.no_config = false;
+            args
+        }
+
+        #[test]
+        fn file_values() {
+            let dir = tempfile::tempdir().unwrap();
+            let result = ExecutionOption::from_args(&args_with_config(&dir)).unwrap();
+
+            assert!(!result.interval_h1 && result.interval_m5);
+            assert!(!result.type_permanent && result.type_cctv);
+            assert!(!result.road_highway && result.road_normal);
+            assert_eq!(result.filter.stations, vec!["3310840"]);
+            assert_eq!(result.out_dir, "/var/traffic");
+            assert_eq!(result.concurrency, 2);
+            assert_eq!(result.rate, 0.5);
+        }
+
+        #[test]
+        fn profile() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut args = args_with_config(&dir);
+            args.config.profile = Some("tokyo-highway".into());
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            // プロファイル > 共通の設定
+            assert!(result.road_highway && !result.road_normal);
+            assert_eq!(result.filter.stations, vec!["3310850", "3310860"]);
+            assert_eq!(result.concurrency, 4);
+            assert!(result.interval_m5);
+            assert_eq!(result.rate, 0.5);
+
+            args.config.profile = Some("osaka".into());
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+
+        #[test]
+        fn command_line_precedence() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut args = args_with_config(&dir);
+            args.config.profile = Some("tokyo-highway".into());
+            args.h1 = true;
+            args.permanent = true;
+            args.normal = true;
+            args.stations = vec!["1110010".into()];
+            args.out_dir = Some("out".into());
+            args.concurrency = Some(8);
+            args.rate = Some(3.0);
+            let result = ExecutionOption::from_args(&args).unwrap();
+
+            // コマンドライン > プロファイル > 共通の設定
+            assert!(result.interval_h1 && !result.interval_m5);
+            assert!(result.type_permanent && !result.type_cctv);
+            assert!(!result.road_highway && result.road_normal);
+            assert_eq!(result.filter.stations, vec!["1110010"]);
+            assert_eq!(result.out_dir, "out");
+            assert_eq!(result.concurrency, 8);
+            assert_eq!(result.rate, 3.0);
+        }
+
+        #[test]
+        fn empty_list() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("traffic-dl.toml");
+            std::fs::write(&path, "roads = []").unwrap();
+
+            let mut args = default_args();
+            args.highway = false;
+            args.normal = false;
+            args.config.config = Some(path.to_string_lossy().into());
+            args.config.no_config = false;
+            assert!(ExecutionOption::from_args(&args).is_err());
+        }
+    }
 }
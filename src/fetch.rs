@@ -0,0 +1,317 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use reqwest::{Response, StatusCode, header::RETRY_AFTER};
+use tokio::time::{Duration, sleep};
+
+use crate::rate_limit::RateLimiter;
+use crate::runner::Reporter;
+
+/// 取得失敗時の再試行の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 最大再試行回数。初回の取得は含まない
+    pub max_retries: u32,
+    /// 初回の再試行前の待ち時間。再試行ごとに2倍になる
+    pub base_delay: Duration,
+    /// 再試行前の待ち時間の上限
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `attempt` 回目(0始まり)の再試行前の待ち時間を返す
+    ///
+    /// 指数バックオフで求めた待ち時間の半分を固定分とし、残りの半分をランダムなジッターとする。
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+/// 指定した url からデータを取得し、文字列を返す。
+///
+/// 通信エラー、429 (Too Many Requests) および 5xx のときは、`policy` に従って再試行する。
+/// `Retry-After` ヘッダーが返されたときは、その指定に従って待機する。ただし `policy` の待ち時間の上限を超えて待機しない。
+/// それ以外のエラーステータスのときは、再試行せずにエラーを返す。
+///
+/// 再試行を含むすべてのリクエストの前に、`limiter` からトークンを取得する。再試行するときは、その旨を `reporter` へ通知する。
+pub async fn get_data_from_url(
+    client: &reqwest::Client,
+    url: &str,
+    policy: &RetryPolicy,
+    limiter: &RateLimiter,
+    reporter: &dyn Reporter,
+) -> Result<String> {
+    let mut attempt = 0;
+
+    loop {
+        limiter.acquire().await;
+        let (error, retry_after) = match client.get(url).send().await {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_success() {
+                    match response.text().await {
+                        Ok(text) => return Ok(text),
+                        Err(e) => (anyhow!(e).context("レスポンス本文の読み込みに失敗"), None),
+                    }
+                } else {
+                    let retry_after = retry_after(&response);
+                    let error = anyhow!("HTTP ステータス {}", status);
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+            }
+            Err(e) => (anyhow!(e).context("通信エラー"), None),
+        };
+
+        if attempt >= policy.max_retries {
+            return Err(error.context(format!("{} 回の再試行後も取得に失敗", policy.max_retries)));
+        }
+
+        let wait = match retry_after {
+            Some(retry_after) => retry_after.min(policy.max_delay),
+            None => policy.backoff(attempt),
+        };
+        attempt += 1;
+        reporter.error(&format!(
+            "{:#}。{:.1} 秒後に再試行 ({}/{})",
+            error,
+            wait.as_secs_f64(),
+            attempt,
+            policy.max_retries
+        ));
+        sleep(wait).await;
+    }
+}
+
+/// 再試行により成功する可能性があるステータスかどうか
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` ヘッダーから待ち時間を取得する。秒数と HTTP 日付の両方の形式に対応する
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // 過去の日時が指定されたときは待機しない
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod get_data_from_url_tests {
+    use super::*;
+    use crate::runner::{Quiet, Recorder};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// 待ち時間なしで再試行する設定
+    fn no_wait(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn success() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/geoserver"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/geoserver", server.uri());
+        let result = get_data_from_url(&reqwest::Client::new(), &url, &no_wait(3), &RateLimiter::unlimited(), &Quiet).await;
+
+        assert_eq!(result.unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn retry_server_error() {
+        let server = MockServer::start().await;
+        // 最初の2回は 503 を返し、その後は成功する
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let reporter = Recorder::default();
+        let result = get_data_from_url(
+            &reqwest::Client::new(),
+            &server.uri(),
+            &no_wait(3),
+            &RateLimiter::unlimited(),
+            &reporter,
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "ok");
+        // 再試行は Reporter へ通知する
+        let errors = reporter.errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("503") && errors[0].ends_with("秒後に再試行 (1/3)"), "{errors:?}");
+    }
+
+    #[tokio::test]
+    async fn give_up_after_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("error page"))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let result = get_data_from_url(
+            &reqwest::Client::new(),
+            &server.uri(),
+            &no_wait(2),
+            &RateLimiter::unlimited(),
+            &Quiet,
+        )
+        .await;
+
+        // 初回 + 再試行2回の後、エラーページの本文ではなくエラーが返されることを確認
+        let message = format!("{:#}", result.unwrap_err());
+        assert!(message.contains("2 回の再試行後も取得に失敗"));
+        assert!(message.contains("500"));
+    }
+
+    #[tokio::test]
+    async fn no_retry_client_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = get_data_from_url(
+            &reqwest::Client::new(),
+            &server.uri(),
+            &no_wait(3),
+            &RateLimiter::unlimited(),
+            &Quiet,
+        )
+        .await;
+
+        assert!(format!("{:#}", result.unwrap_err()).contains("404"));
+    }
+
+    #[tokio::test]
+    async fn honor_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(10),
+            ..no_wait(1)
+        };
+        let started = std::time::Instant::now();
+        let result = get_data_from_url(&reqwest::Client::new(), &server.uri(), &policy, &RateLimiter::unlimited(), &Quiet).await;
+
+        // バックオフの待ち時間は 0 だが、Retry-After の 1 秒は待機することを確認
+        assert_eq!(result.unwrap(), "ok");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn cap_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "3600"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(1),
+            ..no_wait(1)
+        };
+        let started = std::time::Instant::now();
+        let result = get_data_from_url(&reqwest::Client::new(), &server.uri(), &policy, &RateLimiter::unlimited(), &Quiet).await;
+
+        // Retry-After の 1 時間ではなく、待ち時間の上限の 1 秒だけ待機することを確認
+        assert_eq!(result.unwrap(), "ok");
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(10),
+            "{elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_error() {
+        // 待ち受けていないポートへの接続は通信エラーとして再試行される
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let result = get_data_from_url(&reqwest::Client::new(), &url, &no_wait(1), &RateLimiter::unlimited(), &Quiet).await;
+
+        assert!(format!("{:#}", result.unwrap_err()).contains("通信エラー"));
+    }
+}
+
+#[cfg(test)]
+mod retry_policy_tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+
+        // 待ち時間は指数的に増加し、ジッターにより本来の待ち時間の半分以上・本来の待ち時間以下になる
+        for (attempt, expected) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10), (10, 10)] {
+            let wait = policy.backoff(attempt);
+            let expected = Duration::from_secs(expected);
+            assert!(wait >= expected / 2 && wait <= expected, "{attempt}: {wait:?}");
+        }
+    }
+}
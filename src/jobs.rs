@@ -0,0 +1,499 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, ensure};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+
+use crate::client::Client;
+use crate::config;
+use crate::execution_option::ExecutionOption;
+use crate::rate_limit::{self, RateLimiter};
+use crate::runner::{self, Reporter, Summary};
+use crate::sink;
+use crate::types::{
+    AnomalyAction, CompressFormat, ConfigArgs, CounterType, FetchArgs, GeometryEncoding, Interval, RoadType, RunArgs, SinkKind,
+};
+
+/// ジョブファイルの内容
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct JobFile {
+    /// 同時に実行するジョブの最大数
+    pub parallel: Option<usize>,
+    /// 1秒あたりの最大リクエスト数。すべてのジョブで共有する
+    pub rate: Option<f64>,
+    /// `[[job]]` に記述したジョブ。記述した順に実行する
+    #[serde(rename = "job")]
+    pub jobs: Vec<Job>,
+}
+
+/// `[[job]]` に記述する1つのジョブ。データ取得時のコマンドラインのオプションに対応する
+///
+/// 未指定の項目は、データ取得時と同様に設定ファイル (`traffic-dl.toml`) の設定やデフォルト値を使用する。
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Job {
+    /// ジョブの名前。省略時は `job1` のような記述順の番号
+    pub name: Option<String>,
+    /// 日時指定。コマンドラインと同じ形式
+    pub date: Option<String>,
+    /// 設定ファイルのプロファイル。省略時は `run` の `--profile` に従う
+    pub profile: Option<String>,
+
+    /// 取得間隔 (`1h` / `5m`)
+    pub intervals: Option<Vec<Interval>>,
+    /// 観測機器 (`permanent` / `cctv`)
+    pub counters: Option<Vec<CounterType>>,
+    /// 道路種別 (`highway` / `normal` / `both`)
+    pub roads: Option<Vec<RoadType>>,
+
+    /// 平日のみを取得対象とする
+    pub weekdays: bool,
+    /// 土日のみを取得対象とする
+    pub weekends: bool,
+    /// 取得対象の時間帯 (`7-10,17-20` など)
+    pub hours: Option<String>,
+    /// 祝日を取得対象から除く
+    pub exclude_holidays: bool,
+    /// 祝日のみを取得対象とする
+    pub only_holidays: bool,
+
+    /// 常時観測点コード。数値・文字列のいずれでも記述できる
+    #[serde(deserialize_with = "config::station_codes")]
+    pub stations: Option<Vec<String>>,
+    /// 常時観測点コードを列挙したファイル
+    pub stations_file: Option<String>,
+    /// 経度・緯度の矩形範囲 (`minLon,minLat,maxLon,maxLat`)
+    pub bbox: Option<String>,
+    /// 多角形を記述した GeoJSON ファイル
+    pub within: Option<String>,
+
+    /// 同時に取得するデータの最大数
+    pub concurrency: Option<usize>,
+    /// 1回のリクエストで取得する地物の最大数
+    pub page_size: Option<usize>,
+    /// 取得失敗時の最大再試行回数
+    pub retries: Option<u32>,
+    /// 取得失敗時の最初の再試行までの待ち時間(秒)
+    pub retry_wait: Option<f64>,
+
+    /// 保存先のディレクトリ
+    pub out_dir: Option<String>,
+    /// 保存先のディレクトリからの相対パスのテンプレート
+    pub name_template: Option<String>,
+    /// 取得したデータの保存先 (`dir` / `stdout` / `parquet` / `sqlite`)
+    pub sinks: Option<Vec<SinkKind>>,
+    /// Parquet 形式での保存先のディレクトリ
+    pub parquet: Option<String>,
+    /// Parquet に保存する観測地点の位置の形式 (`lonlat` / `wkb`)
+    pub parquet_geometry: Option<GeometryEncoding>,
+    /// SQLite のデータベースのパス
+    pub sqlite: Option<String>,
+    /// 保存するファイルの圧縮形式 (`gzip` / `zstd`)
+    pub compress: Option<CompressFormat>,
+    /// 圧縮レベル
+    pub compress_level: Option<u32>,
+
+    /// 正常なデータ以外を取得したときの扱い (`save` / `skip` / `quarantine`)
+    pub on_anomaly: Option<AnomalyAction>,
+    /// 正常なデータ以外を隔離する場合の保存先ディレクトリ
+    pub quarantine_dir: Option<String>,
+    /// 保存済みのデータの取得を省略する
+    pub resume: bool,
+    /// 取得を省略する際に、データなし・サーバー例外として保存済みのデータも再取得する
+    pub refetch_anomalies: bool,
+}
+
+impl Job {
+    /// `run` の実行時オプションと合わせて、データ取得時のコマンドラインのオプションに変換する
+    ///
+    /// コマンドラインでは clap が検査する、同時に指定できないオプションの組み合わせもここで検査する。
+    fn to_fetch_args(&self, args: &RunArgs) -> Result<FetchArgs> {
+        ensure!(self.date.is_some(), "date が指定されていない");
+        ensure!(!(self.weekdays && self.weekends), "weekdays と weekends は同時に指定できない");
+        ensure!(
+            !(self.exclude_holidays && self.only_holidays),
+            "exclude-holidays と only-holidays は同時に指定できない"
+        );
+        ensure!(
+            self.parquet_geometry.is_none() || self.parquet.is_some(),
+            "parquet-geometry を指定したときは、parquet で保存先のディレクトリを指定する必要がある"
+        );
+        ensure!(
+            self.compress_level.is_none() || self.compress.is_some(),
+            "compress-level を指定したときは、compress で圧縮形式を指定する必要がある"
+        );
+        ensure!(
+            !self.refetch_anomalies || self.resume,
+            "refetch-anomalies は resume と併せて指定する必要がある"
+        );
+
+        // 取得間隔・観測機器・道路種別は、対応するフラグに変換する
+        let intervals = non_empty(&self.intervals, "intervals")?;
+        let counters = non_empty(&self.counters, "counters")?;
+        let roads = non_empty(&self.roads, "roads")?;
+
+        Ok(FetchArgs {
+            date: self.date.clone(),
+            h1: intervals.is_some_and(|v| v.contains(&Interval::H1)),
+            m5: intervals.is_some_and(|v| v.contains(&Interval::M5)),
+            weekdays: self.weekdays,
+            weekends: self.weekends,
+            hours: self.hours.clone(),
+            exclude_holidays: self.exclude_holidays,
+            only_holidays: self.only_holidays,
+            permanent: counters.is_some_and(|v| v.contains(&CounterType::Permanent)),
+            cctv: counters.is_some_and(|v| v.contains(&CounterType::Cctv)),
+            highway: roads.is_some_and(|v| v.iter().any(|r| matches!(r, RoadType::Highway | RoadType::Both))),
+            normal: roads.is_some_and(|v| v.iter().any(|r| matches!(r, RoadType::Normal | RoadType::Both))),
+            stations: self.stations.clone().unwrap_or_default(),
+            stations_file: self.stations_file.clone(),
+            bbox: self.bbox.clone(),
+            within: self.within.clone(),
+            concurrency: self.concurrency,
+            rate: None,
+            page_size: self.page_size,
+            retries: self.retries,
+            retry_wait: self.retry_wait,
+            endpoint: args.endpoint.clone(),
+            config: ConfigArgs {
+                config: args.config.config.clone(),
+                profile: self.profile.clone().or_else(|| args.config.profile.clone()),
+                no_config: args.config.no_config,
+            },
+            out_dir: self.out_dir.clone(),
+            name_template: self.name_template.clone(),
+            sinks: self.sinks.clone().unwrap_or_default(),
+            parquet: self.parquet.clone(),
+            parquet_geometry: self.parquet_geometry,
+            sqlite: self.sqlite.clone(),
+            compress: self.compress,
+            compress_level: self.compress_level,
+            on_anomaly: self.on_anomaly,
+            quarantine_dir: self.quarantine_dir.clone(),
+            resume: self.resume,
+            refetch_anomalies: self.refetch_anomalies,
+            one: false,
+            dry: args.dry,
+        })
+    }
+}
+
+/// 空のリストを記述したときはエラーにする
+fn non_empty<'a, T>(list: &'a Option<Vec<T>>, key: &str) -> Result<Option<&'a Vec<T>>> {
+    ensure!(list.as_ref().is_none_or(|v| !v.is_empty()), "{} に値が指定されていない", key);
+    Ok(list.as_ref())
+}
+
+/// ジョブファイルの内容を解釈する
+pub fn parse(text: &str) -> Result<JobFile> {
+    Ok(toml::from_str(text)?)
+}
+
+/// `run` の実行時のオプション
+#[derive(Debug)]
+pub struct JobsOption {
+    /// ジョブの名前と、取得・保存のオプション
+    pub jobs: Vec<(String, ExecutionOption)>,
+    /// 同時に実行するジョブの最大数
+    pub parallel: usize,
+    /// 1秒あたりの最大リクエスト数。すべてのジョブで共有する
+    pub rate: f64,
+}
+
+impl JobsOption {
+    /// コマンドラインの実行時オプションから、ジョブファイルを読み込んで生成する
+    pub fn from_args(args: &RunArgs) -> Result<Self> {
+        let text = std::fs::read_to_string(&args.jobs).with_context(|| format!("ジョブファイル {} を読み込めない", args.jobs))?;
+        let file = parse(&text).with_context(|| format!("ジョブファイル {} を解釈不能", args.jobs))?;
+        Self::from_file(args, file)
+    }
+
+    /// 解釈済みのジョブファイルから生成する
+    ///
+    /// いずれかのジョブの指定が不正なときは、どのジョブも実行しないようエラーを返す。
+    pub fn from_file(args: &RunArgs, file: JobFile) -> Result<Self> {
+        ensure!(!file.jobs.is_empty(), "ジョブファイルに [[job]] が記述されていない");
+
+        let mut names = HashSet::new();
+        let mut jobs = vec![];
+        for (i, job) in file.jobs.iter().enumerate() {
+            let name = job.name.clone().unwrap_or_else(|| format!("job{}", i + 1));
+            ensure!(names.insert(name.clone()), "ジョブの名前 {} が重複している", name);
+
+            let option = job
+                .to_fetch_args(args)
+                .and_then(|fetch_args| ExecutionOption::from_args(&fetch_args))
+                .with_context(|| format!("ジョブ {} の指定が不正", name))?;
+            jobs.push((name, option));
+        }
+
+        let parallel = args.parallel.or(file.parallel).unwrap_or(1);
+        ensure!(parallel >= 1, "同時に実行するジョブの数は 1 以上で指定する必要がある");
+
+        // 未指定時は、設定ファイルの共通の設定 (または --profile の設定) に従う
+        let rate = match args.rate.or(file.rate) {
+            Some(rate) => rate,
+            None => config::load(&args.config)?.rate.unwrap_or(1.0),
+        };
+        rate_limit::validate_rate(rate)?;
+
+        Ok(JobsOption { jobs, parallel, rate })
+    }
+}
+
+/// ジョブごとの実行結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobSummary {
+    /// ジョブの名前
+    pub name: String,
+    /// 取得結果の集計
+    pub summary: Summary,
+    /// 保存先の作成・完了処理に失敗したときのエラー
+    pub error: Option<String>,
+}
+
+/// すべてのジョブの実行結果
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JobsSummary {
+    /// ジョブごとの実行結果。ジョブファイルに記述した順
+    pub jobs: Vec<JobSummary>,
+}
+
+impl JobsSummary {
+    /// すべてのジョブの取得結果の合計
+    pub fn total(&self) -> Summary {
+        let mut total = Summary::default();
+        for job in &self.jobs {
+            total += &job.summary;
+        }
+        total
+    }
+
+    /// エラーで終了したジョブの数
+    pub fn errors(&self) -> usize {
+        self.jobs.iter().filter(|job| job.error.is_some()).count()
+    }
+}
+
+/// すべてのジョブを実行する
+///
+/// 最大 `parallel` 個のジョブを同時に実行し、すべてのジョブで1つのレートリミッターを共有する。
+/// ジョブがエラーで終了したときも、残りのジョブの実行を続ける。
+pub async fn run(option: &JobsOption, reporter: Arc<dyn Reporter>) -> JobsSummary {
+    let limiter = Arc::new(RateLimiter::new(option.rate));
+
+    let jobs = stream::iter(&option.jobs)
+        .map(|(name, fetch)| {
+            let limiter = limiter.clone();
+            let reporter = reporter.clone();
+            async move {
+                reporter.progress(&format!("ジョブ {} を開始", name));
+                let client = Client::from_option(fetch)
+                    .with_shared_rate_limiter(limiter)
+                    .with_reporter(reporter.clone());
+                let mut summary = Summary::default();
+                let result = async {
+                    // ドライランのときは、データベースなどの保存先を作成しない
+                    let sinks = if fetch.dry { vec![] } else { sink::from_option(fetch)? };
+                    summary = runner::run_with_client(fetch, &client, sinks).await?;
+                    anyhow::Ok(())
+                }
+                .await;
+
+                if let Err(e) = &result {
+                    reporter.error(&format!("ジョブ {} がエラーで終了: {:#}", name, e));
+                }
+                JobSummary {
+                    name: name.clone(),
+                    summary,
+                    error: result.err().map(|e| format!("{:#}", e)),
+                }
+            }
+        })
+        .buffered(option.parallel)
+        .collect()
+        .await;
+
+    JobsSummary { jobs }
+}
+
+#[cfg(test)]
+mod jobs_tests {
+    use super::*;
+    use crate::types::EndpointArgs;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn run_args() -> RunArgs {
+        RunArgs {
+            jobs: "jobs.toml".into(),
+            parallel: None,
+            rate: None,
+            endpoint: EndpointArgs::default(),
+            config: ConfigArgs {
+                no_config: true,
+                ..Default::default()
+            },
+            dry: false,
+        }
+    }
+
+    const JOBS: &str = r#"
+        parallel = 2
+        rate = 4.0
+
+        [[job]]
+        name = "tokyo-peak"
+        date = "2025-01-01..2025-01-31"
+        intervals = ["5m"]
+        counters = ["permanent"]
+        roads = ["highway"]
+        stations = [3310840, "3310850"]
+        weekdays = true
+        exclude-holidays = true
+        hours = "7-10,17-20"
+        out-dir = "data/tokyo"
+        sinks = ["dir", "stdout"]
+        compress = "zstd"
+        on-anomaly = "quarantine"
+
+        [[job]]
+        date = "20250102"
+        intervals = ["1h", "5m"]
+        roads = ["both"]
+        resume = true
+        refetch-anomalies = true
+    "#;
+
+    #[test]
+    fn from_file() {
+        let option = JobsOption::from_file(&run_args(), parse(JOBS).unwrap()).unwrap();
+
+        assert_eq!(option.parallel, 2);
+        assert_eq!(option.rate, 4.0);
+        assert_eq!(option.jobs.len(), 2);
+
+        let (name, job) = &option.jobs[0];
+        assert_eq!(name, "tokyo-peak");
+        assert_eq!((job.interval_h1, job.interval_m5), (false, true));
+        assert_eq!((job.type_permanent, job.type_cctv), (true, false));
+        assert_eq!((job.road_highway, job.road_normal), (true, false));
+        assert_eq!(job.filter.stations, vec!["3310840", "3310850"]);
+        assert_eq!(job.slot_filter.hours, vec![(7, 10), (17, 20)]);
+        assert_eq!(job.out_dir, "data/tokyo");
+        assert_eq!(job.sinks, vec![SinkKind::Dir, SinkKind::Stdout]);
+        assert!(job.compression.is_some());
+        assert_eq!(job.on_anomaly, AnomalyAction::Quarantine);
+
+        // 名前の省略時は記述順の番号とし、未指定の項目はデフォルト値を使用する
+        let (name, job) = &option.jobs[1];
+        assert_eq!(name, "job2");
+        assert_eq!((job.interval_h1, job.interval_m5), (true, true));
+        assert_eq!((job.type_permanent, job.type_cctv), (true, true));
+        assert_eq!((job.road_highway, job.road_normal), (true, true));
+        assert_eq!(job.out_dir, "data");
+        assert!(job.resume && job.refetch_anomalies);
+    }
+
+    #[test]
+    fn command_line_precedence() {
+        let mut args = run_args();
+        args.parallel = Some(3);
+        args.rate = Some(0.5);
+        args.dry = true;
+        let option = JobsOption::from_file(&args, parse(JOBS).unwrap()).unwrap();
+
+        assert_eq!(option.parallel, 3);
+        assert_eq!(option.rate, 0.5);
+        assert!(option.jobs.iter().all(|(_, job)| job.dry));
+    }
+
+    #[test]
+    fn invalid() {
+        let invalid = [
+            "",
+            "[[job]]\nintervals = [\"1h\"]",
+            "[[job]]\ndate = \"20250102\"\nrate = 2.0",
+            "[[job]]\ndate = \"20250102\"\nintervals = []",
+            "[[job]]\ndate = \"20250102\"\nweekdays = true\nweekends = true",
+            "[[job]]\ndate = \"20250102\"\ncompress-level = 3",
+            "[[job]]\ndate = \"20250102\"\nrefetch-anomalies = true",
+            "[[job]]\ndate = \"2025-13-01\"",
+            "[[job]]\nname = \"a\"\ndate = \"20250102\"\n[[job]]\nname = \"a\"\ndate = \"20250103\"",
+            "parallel = 0\n[[job]]\ndate = \"20250102\"",
+            "rate = 1e-20\n[[job]]\ndate = \"20250102\"",
+        ];
+        for text in invalid {
+            let result = parse(text).and_then(|file| JobsOption::from_file(&run_args(), file));
+            assert!(result.is_err(), "{}", text);
+        }
+
+        // ジョブの名前をエラーに含める
+        let file = parse("[[job]]\nname = \"tokyo\"\ndate = \"x\"").unwrap();
+        let error = JobsOption::from_file(&run_args(), file).unwrap_err();
+        assert!(format!("{:#}", error).contains("ジョブ tokyo"));
+    }
+
+    #[tokio::test]
+    async fn run_jobs() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"type":"FeatureCollection","features":[{}]}"#))
+            .expect(3)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_string_lossy();
+
+        let text = format!(
+            r#"
+            parallel = 2
+            rate = 1000.0
+
+            [[job]]
+            name = "a"
+            date = "2025010203..2025010204"
+            counters = ["permanent"]
+            out-dir = "{out}/a"
+            retries = 0
+
+            [[job]]
+            name = "b"
+            date = "202501020300"
+            intervals = ["5m"]
+            counters = ["cctv"]
+            out-dir = "{out}/b"
+            retries = 0
+            "#
+        );
+        let mut args = run_args();
+        args.endpoint.endpoint = Some(server.uri());
+        let option = JobsOption::from_file(&args, parse(&text).unwrap()).unwrap();
+
+        let reporter = Arc::new(runner::Recorder::default());
+        let summary = run(&option, reporter.clone()).await;
+
+        assert_eq!(summary.jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(summary.jobs[0].summary.fetched, 2);
+        assert_eq!(summary.jobs[1].summary.fetched, 1);
+        assert_eq!(
+            summary.total(),
+            Summary {
+                fetched: 3,
+                skipped: 0,
+                failures: 0
+            }
+        );
+        assert_eq!(summary.errors(), 0);
+        assert!(dir.path().join("a/H202501020400P.json").exists());
+        assert!(dir.path().join("b/M202501020300C.json").exists());
+
+        // 進捗は Reporter へ通知する
+        let progress = reporter.progress.lock().unwrap();
+        assert!(progress.contains(&"ジョブ a を開始".to_string()));
+        assert!(progress.contains(&"H202501020400P: データ (1 件)".to_string()), "{progress:?}");
+        assert!(reporter.errors.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+pub mod calendar;
+pub mod compress;
+pub mod config;
+pub mod convert;
+pub mod cql;
+pub mod datetime;
+pub mod describe;
+pub mod endpoint;
+pub mod execution_option;
+pub mod fetch;
+pub mod jobs;
+pub mod model;
+pub mod paging;
+pub mod parquet_writer;
+pub mod rate_limit;
+pub mod response;
+pub mod resume;
+pub mod sink;
+pub mod spatial;
+pub mod sqlite;
+pub mod template;
+pub mod types;
+pub mod url;
+pub mod watch;
+
+mod client;
+mod runner;
+
+pub use client::Client;
+pub use datetime::DT;
+pub use execution_option::ExecutionOption;
+pub use runner::{Quiet, Reporter, Summary, run, run_with_sinks};
+pub use sink::OutputSink;
+pub use types::{CounterType, Interval, RoadType};
+pub use url::Filter;
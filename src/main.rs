@@ -1,56 +1,158 @@
-use anyhow::Result;
-use clap::Parser;
-use tokio::time::{Duration, sleep};
+use std::io::Write;
+use std::sync::Arc;
 
-mod datetime;
-mod execution_option;
-mod types;
-mod url;
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use traffic_dl::convert::{self, ConvertOption};
+use traffic_dl::endpoint::Endpoint;
+use traffic_dl::jobs::{self, JobsOption};
+use traffic_dl::types::{Cli, Command, ConvertArgs, DescribeArgs, DescribeFormat, FetchArgs, RunArgs, SinkKind, WatchArgs};
+use traffic_dl::watch::{self, Shutdown, WatchOption};
+use traffic_dl::{Client, ExecutionOption, Reporter, config, describe};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = types::Cli::parse();
+    let args = Cli::parse();
 
-    let execute_option = execution_option::ExecutionOption::from_args(&args)?;
+    match &args.command {
+        Some(Command::Convert(convert_args)) => run_convert(convert_args),
+        Some(Command::Describe(describe_args)) => run_describe(describe_args).await,
+        Some(Command::Watch(watch_args)) => run_watch(watch_args).await,
+        Some(Command::Run(run_args)) => run_jobs(run_args).await,
+        None => run_fetch(&args.fetch).await,
+    }
+}
 
-    let names_and_urls = url::create_names_and_urls(&execute_option);
+/// 進捗を標準出力、エラーを標準エラー出力へ表示する
+struct Console {
+    /// 進捗も標準エラー出力へ表示するかどうか
+    progress_to_stderr: bool,
+}
 
-    for (name, url) in names_and_urls {
-        if execute_option.dry {
-            println!("{} - {}", &name, &url);
+impl Console {
+    /// 標準出力へデータを出力するときは、データと混ざらないよう進捗を標準エラー出力へ表示する
+    fn new(sinks: &[SinkKind]) -> Self {
+        Console {
+            progress_to_stderr: sinks.contains(&SinkKind::Stdout),
+        }
+    }
+}
+
+impl Reporter for Console {
+    fn progress(&self, message: &str) {
+        if self.progress_to_stderr {
+            eprintln!("{}", message);
         } else {
-            // 実際にデータを取得してファイルとして保存する
-            let content = get_data_from_url(&url).await?;
-            let path = format!("{}.json", name);
-            save_to_file(&path, "data", &content).await?;
-
-            if execute_option.one {
-                break; // `--one` が指定されているときは、最初の1つのみを処理して終了する
-            } else {
-                sleep(Duration::from_secs(1)).await; // 取得頻度を下げるために間隔を開ける
-            }
+            println!("{}", message);
         }
     }
 
+    fn error(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// データを取得する
+async fn run_fetch(args: &FetchArgs) -> Result<()> {
+    let execute_option = ExecutionOption::from_args(args)?;
+
+    let summary = traffic_dl::run(&execute_option, Arc::new(Console::new(&execute_option.sinks))).await?;
+
+    if summary.failures > 0 {
+        bail!("{} 件のデータの取得に失敗", summary.failures);
+    }
+
     Ok(())
 }
 
-/// 指定した url からデータを取得し、文字列を返す。
-async fn get_data_from_url(url: &str) -> Result<String> {
-    let response = reqwest::get(url).await?;
-    let text = response.text().await?;
+/// 公開された最新の時間帯のデータを継続して取得する
+async fn run_watch(args: &WatchArgs) -> Result<()> {
+    let mut option = WatchOption::from_args(args)?;
 
-    Ok(text)
+    let console = Arc::new(Console::new(&option.fetch.sinks));
+    let summary = watch::watch(&mut option, Shutdown::on_signals(console.clone())?, console).await?;
+
+    // 終了の要求による終了は正常終了とし、取得に失敗したデータの数のみを表示する
+    if summary.failures > 0 {
+        eprintln!("{} 件のデータの取得に失敗", summary.failures);
+    }
+
+    Ok(())
 }
 
-/// データを指定フォルダへ保存する。
-async fn save_to_file(filename: &str, dir: &str, content: &str) -> Result<()> {
-    // 出力先ディレクトリが存在しないときは作成する
-    if !std::path::Path::exists(std::path::Path::new(dir)) {
-        std::fs::create_dir(dir)?;
+/// ジョブファイルに記述した複数の取得を実行する
+async fn run_jobs(args: &RunArgs) -> Result<()> {
+    let option = JobsOption::from_args(args)?;
+
+    let sinks: Vec<SinkKind> = option.jobs.iter().flat_map(|(_, job)| job.sinks.clone()).collect();
+    let summary = jobs::run(&option, Arc::new(Console::new(&sinks))).await;
+
+    // 標準出力へデータを出力するジョブがあるため、集計は標準エラー出力へ表示する
+    eprintln!("ジョブ\t取得\t省略\t失敗");
+    for job in &summary.jobs {
+        let status = if job.error.is_some() { " (エラー)" } else { "" };
+        eprintln!(
+            "{}{}\t{}\t{}\t{}",
+            job.name, status, job.summary.fetched, job.summary.skipped, job.summary.failures
+        );
+    }
+    let total = summary.total();
+    eprintln!("合計\t{}\t{}\t{}", total.fetched, total.skipped, total.failures);
+
+    if summary.errors() > 0 {
+        bail!("{} 件のジョブがエラーで終了", summary.errors());
+    }
+    if total.failures > 0 {
+        bail!("{} 件のデータの取得に失敗", total.failures);
+    }
+
+    Ok(())
+}
+
+/// 保存済みのデータを CSV に変換する
+fn run_convert(args: &ConvertArgs) -> Result<()> {
+    let option = ConvertOption::from_args(args)?;
+
+    let mut output: Box<dyn Write> = match &option.output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("{} を作成できない", path))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    // 標準出力へ CSV を出力することがあるため、進捗は標準エラー出力へ表示する
+    let summary = convert::convert(&option, &mut output, &Console { progress_to_stderr: true })?;
+
+    // 標準出力へ CSV を出力することがあるため、集計は標準エラー出力へ表示する
+    eprintln!("{} ファイル・{} 行を変換", summary.files, summary.rows);
+    if summary.skipped > 0 {
+        eprintln!("観測機器が異なる {} ファイルを省略", summary.skipped);
+    }
+    if summary.failures > 0 {
+        bail!("{} ファイルの変換に失敗", summary.failures);
+    }
+
+    Ok(())
+}
+
+/// WFS のレイヤーと属性の一覧を表示する
+async fn run_describe(args: &DescribeArgs) -> Result<()> {
+    let settings = config::load(&args.config)?;
+    let endpoint = Endpoint::from_args(&args.endpoint, &settings.endpoint)?;
+    let description = describe::describe(&Client::new().with_endpoint(endpoint), &args.layers).await?;
+
+    let mut output = std::io::stdout().lock();
+    match args.format.unwrap_or(DescribeFormat::Table) {
+        DescribeFormat::Table => describe::write_table(&description, &mut output)?,
+        DescribeFormat::Json => writeln!(output, "{}", serde_json::to_string_pretty(&description)?)?,
+    }
+
+    // スキーマの変更を検知できるよう、取得に使用するレイヤー・属性が存在しないときはエラーとする
+    if !description.missing.is_empty() {
+        bail!(
+            "データの取得に使用するレイヤー・属性が存在しない: {}",
+            description.missing.join(", ")
+        );
     }
 
-    let path = format!("{}/{}", dir, filename);
-    tokio::fs::write(&path, content).await?;
     Ok(())
 }
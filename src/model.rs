@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::types::CounterType;
+
+/// 観測値の FeatureCollection
+///
+/// `P` は観測機器ごとの属性 (`PermanentProperties` / `CctvProperties`)。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FeatureCollection<P> {
+    pub features: Vec<Feature<P>>,
+    /// 条件に一致した地物の総数
+    #[serde(rename = "numberMatched", default, skip_serializing_if = "Option::is_none")]
+    pub number_matched: Option<u64>,
+    /// このレスポンスに含まれる地物の数
+    #[serde(rename = "numberReturned", default, skip_serializing_if = "Option::is_none")]
+    pub number_returned: Option<u64>,
+}
+
+/// 観測地点ごとの観測値
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Feature<P> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub geometry: Option<Geometry>,
+    pub properties: P,
+}
+
+/// 観測地点のジオメトリ
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Geometry {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub coordinates: Value,
+}
+
+impl Geometry {
+    /// 代表点の経度・緯度を返す。Point 以外のときは最初の座標を代表点とする
+    pub fn lon_lat(&self) -> Option<(f64, f64)> {
+        let mut value = &self.coordinates;
+        // 入れ子の配列から最初の座標を取り出す
+        while let Some(first) = value.get(0).filter(|v| v.is_array()) {
+            value = first;
+        }
+
+        Some((value.get(0)?.as_f64()?, value.get(1)?.as_f64()?))
+    }
+}
+
+/// 常設トラカン (`t_travospublic_measure_1h` / `t_travospublic_measure_5m`) の属性
+///
+/// 交通量と品質フラグは、観測されていないときに null や空文字列となることがあるため、すべて Option とする。
+/// 定義していない属性は `extra` に保持する。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PermanentProperties {
+    /// 常時観測点コード
+    #[serde(rename = "常時観測点コード", deserialize_with = "code")]
+    pub station_code: String,
+    /// 時間コード (YYYYMMDDHHMM)
+    #[serde(rename = "時間コード", deserialize_with = "code")]
+    pub time_code: String,
+    /// 道路種別。`1`: 高速自動車国道、`3`: 一般国道
+    #[serde(rename = "道路種別", default, deserialize_with = "optional_code")]
+    pub road_type: Option<String>,
+
+    /// 上り・小型交通量
+    #[serde(rename = "上り・小型交通量", default, deserialize_with = "count")]
+    pub up_small: Option<u32>,
+    /// 上り・大型交通量
+    #[serde(rename = "上り・大型交通量", default, deserialize_with = "count")]
+    pub up_large: Option<u32>,
+    /// 上り・車種判別不能交通量
+    #[serde(rename = "上り・車種判別不能交通量", default, deserialize_with = "count")]
+    pub up_unknown: Option<u32>,
+    /// 上り・停電
+    #[serde(rename = "上り・停電", default, deserialize_with = "flag")]
+    pub up_power_failure: Option<bool>,
+    /// 上り・ループ異常
+    #[serde(rename = "上り・ループ異常", default, deserialize_with = "flag")]
+    pub up_loop_error: Option<bool>,
+    /// 上り・超音波異常
+    #[serde(rename = "上り・超音波異常", default, deserialize_with = "flag")]
+    pub up_ultrasonic_error: Option<bool>,
+    /// 上り・欠測
+    #[serde(rename = "上り・欠測", default, deserialize_with = "flag")]
+    pub up_missing: Option<bool>,
+
+    /// 下り・小型交通量
+    #[serde(rename = "下り・小型交通量", default, deserialize_with = "count")]
+    pub down_small: Option<u32>,
+    /// 下り・大型交通量
+    #[serde(rename = "下り・大型交通量", default, deserialize_with = "count")]
+    pub down_large: Option<u32>,
+    /// 下り・車種判別不能交通量
+    #[serde(rename = "下り・車種判別不能交通量", default, deserialize_with = "count")]
+    pub down_unknown: Option<u32>,
+    /// 下り・停電
+    #[serde(rename = "下り・停電", default, deserialize_with = "flag")]
+    pub down_power_failure: Option<bool>,
+    /// 下り・ループ異常
+    #[serde(rename = "下り・ループ異常", default, deserialize_with = "flag")]
+    pub down_loop_error: Option<bool>,
+    /// 下り・超音波異常
+    #[serde(rename = "下り・超音波異常", default, deserialize_with = "flag")]
+    pub down_ultrasonic_error: Option<bool>,
+    /// 下り・欠測
+    #[serde(rename = "下り・欠測", default, deserialize_with = "flag")]
+    pub down_missing: Option<bool>,
+
+    /// 上記以外の属性
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// CCTVトラカン (`t_travospublic_measure_1h_img` / `t_travospublic_measure_5m_img`) の属性
+///
+/// 画像解析による観測のため、常設トラカンのような機器の異常フラグは持たない。
+/// 定義していない属性は `extra` に保持する。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CctvProperties {
+    /// 常時観測点コード
+    #[serde(rename = "常時観測点コード", deserialize_with = "code")]
+    pub station_code: String,
+    /// 時間コード (YYYYMMDDHHMM)
+    #[serde(rename = "時間コード", deserialize_with = "code")]
+    pub time_code: String,
+    /// 道路種別。`1`: 高速自動車国道、`3`: 一般国道
+    #[serde(rename = "道路種別", default, deserialize_with = "optional_code")]
+    pub road_type: Option<String>,
+
+    /// 上り・小型交通量
+    #[serde(rename = "上り・小型交通量", default, deserialize_with = "count")]
+    pub up_small: Option<u32>,
+    /// 上り・大型交通量
+    #[serde(rename = "上り・大型交通量", default, deserialize_with = "count")]
+    pub up_large: Option<u32>,
+    /// 上り・車種判別不能交通量
+    #[serde(rename = "上り・車種判別不能交通量", default, deserialize_with = "count")]
+    pub up_unknown: Option<u32>,
+    /// 上り・欠測
+    #[serde(rename = "上り・欠測", default, deserialize_with = "flag")]
+    pub up_missing: Option<bool>,
+
+    /// 下り・小型交通量
+    #[serde(rename = "下り・小型交通量", default, deserialize_with = "count")]
+    pub down_small: Option<u32>,
+    /// 下り・大型交通量
+    #[serde(rename = "下り・大型交通量", default, deserialize_with = "count")]
+    pub down_large: Option<u32>,
+    /// 下り・車種判別不能交通量
+    #[serde(rename = "下り・車種判別不能交通量", default, deserialize_with = "count")]
+    pub down_unknown: Option<u32>,
+    /// 下り・欠測
+    #[serde(rename = "下り・欠測", default, deserialize_with = "flag")]
+    pub down_missing: Option<bool>,
+
+    /// 上記以外の属性
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// 交通量の列名。上り・下りそれぞれの小型・大型・車種判別不能の順
+pub const COUNTS: [&str; 6] = ["up_small", "up_large", "up_unknown", "down_small", "down_large", "down_unknown"];
+
+/// 観測機器によらない観測値の属性
+///
+/// Parquet・SQLite などへの出力で、常設トラカンと CCTVトラカンの属性を共通に扱うために使用する。
+pub trait Observation {
+    /// 観測機器
+    const COUNTER_TYPE: CounterType;
+    /// 品質フラグの列名。`flags` の順
+    const FLAGS: &'static [&'static str];
+
+    fn station_code(&self) -> &str;
+    fn time_code(&self) -> &str;
+    fn road_type(&self) -> Option<&str>;
+    /// 交通量。`COUNTS` の順
+    fn counts(&self) -> [Option<u32>; 6];
+    /// 品質フラグ。`FLAGS` の順
+    fn flags(&self) -> Vec<Option<bool>>;
+}
+
+impl Observation for PermanentProperties {
+    const COUNTER_TYPE: CounterType = CounterType::Permanent;
+    const FLAGS: &'static [&'static str] = &[
+        "up_power_failure",
+        "up_loop_error",
+        "up_ultrasonic_error",
+        "up_missing",
+        "down_power_failure",
+        "down_loop_error",
+        "down_ultrasonic_error",
+        "down_missing",
+    ];
+
+    fn station_code(&self) -> &str {
+        &self.station_code
+    }
+    fn time_code(&self) -> &str {
+        &self.time_code
+    }
+    fn road_type(&self) -> Option<&str> {
+        self.road_type.as_deref()
+    }
+    fn counts(&self) -> [Option<u32>; 6] {
+        [
+            self.up_small,
+            self.up_large,
+            self.up_unknown,
+            self.down_small,
+            self.down_large,
+            self.down_unknown,
+        ]
+    }
+    fn flags(&self) -> Vec<Option<bool>> {
+        vec![
+            self.up_power_failure,
+            self.up_loop_error,
+            self.up_ultrasonic_error,
+            self.up_missing,
+            self.down_power_failure,
+            self.down_loop_error,
+            self.down_ultrasonic_error,
+            self.down_missing,
+        ]
+    }
+}
+
+impl Observation for CctvProperties {
+    const COUNTER_TYPE: CounterType = CounterType::Cctv;
+    const FLAGS: &'static [&'static str] = &["up_missing", "down_missing"];
+
+    fn station_code(&self) -> &str {
+        &self.station_code
+    }
+    fn time_code(&self) -> &str {
+        &self.time_code
+    }
+    fn road_type(&self) -> Option<&str> {
+        self.road_type.as_deref()
+    }
+    fn counts(&self) -> [Option<u32>; 6] {
+        [
+            self.up_small,
+            self.up_large,
+            self.up_unknown,
+            self.down_small,
+            self.down_large,
+            self.down_unknown,
+        ]
+    }
+    fn flags(&self) -> Vec<Option<bool>> {
+        vec![self.up_missing, self.down_missing]
+    }
+}
+
+/// 常設トラカンのレスポンスを解釈する
+pub fn parse_permanent(body: &str) -> Result<FeatureCollection<PermanentProperties>> {
+    serde_json::from_str(body).context("常設トラカンのデータとして解釈不能")
+}
+
+/// CCTVトラカンのレスポンスを解釈する
+pub fn parse_cctv(body: &str) -> Result<FeatureCollection<CctvProperties>> {
+    serde_json::from_str(body).context("CCTVトラカンのデータとして解釈不能")
+}
+
+/// 文字列と数値のどちらでも返されることがある値を、文字列として取得する。null と空文字列は None とする
+fn string_or_number(value: Value) -> Result<Option<String>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) if s.trim().is_empty() => Ok(None),
+        Value::String(s) => Ok(Some(s.trim().to_string())),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        other => Err(format!("文字列または数値が必要: {}", other)),
+    }
+}
+
+/// 必須のコード値
+fn code<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    string_or_number(Value::deserialize(deserializer)?)
+        .map_err(de::Error::custom)?
+        .ok_or_else(|| de::Error::custom("コードが空"))
+}
+
+/// 省略可能なコード値
+fn optional_code<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    string_or_number(Value::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+/// 交通量。数値または数字の文字列
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match string_or_number(Value::deserialize(deserializer)?).map_err(de::Error::custom)? {
+        Some(s) => s
+            .parse()
+            .map(Some)
+            .map_err(|_| de::Error::custom(format!("交通量 {} を解釈不能", s))),
+        None => Ok(None),
+    }
+}
+
+/// 品質フラグ。`0` / `1` の数値・文字列、または真偽値
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    if let Value::Bool(b) = value {
+        return Ok(Some(b));
+    }
+
+    match string_or_number(value).map_err(de::Error::custom)?.as_deref() {
+        Some("0") => Ok(Some(false)),
+        Some("1") => Ok(Some(true)),
+        Some(other) => Err(de::Error::custom(format!("フラグ {} を解釈不能", other))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+
+    const PERMANENT: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [{
+            "type": "Feature",
+            "id": "t_travospublic_measure_1h.1",
+            "geometry": {"type": "Point", "coordinates": [139.7, 35.6]},
+            "properties": {
+                "地方整備局等番号": 3,
+                "常時観測点コード": 3310840,
+                "時間コード": 202501020300,
+                "道路種別": "1",
+                "上り・小型交通量": 120,
+                "上り・大型交通量": "30",
+                "上り・車種判別不能交通量": null,
+                "上り・停電": 0,
+                "上り・ループ異常": "0",
+                "上り・超音波異常": false,
+                "上り・欠測": 1,
+                "下り・小型交通量": 98,
+                "下り・大型交通量": 25,
+                "下り・車種判別不能交通量": 0,
+                "下り・欠測": ""
+            }
+        }],
+        "totalFeatures": 1,
+        "numberMatched": 1,
+        "numberReturned": 1
+    }"#;
+
+    #[test]
+    fn permanent() {
+        let collection = parse_permanent(PERMANENT).unwrap();
+
+        assert_eq!(collection.number_matched, Some(1));
+        assert_eq!(collection.features.len(), 1);
+
+        let feature = &collection.features[0];
+        assert_eq!(feature.geometry.as_ref().unwrap().lon_lat(), Some((139.7, 35.6)));
+
+        let p = &feature.properties;
+        assert_eq!(p.station_code, "3310840");
+        assert_eq!(p.time_code, "202501020300");
+        assert_eq!(p.road_type.as_deref(), Some("1"));
+        assert_eq!((p.up_small, p.up_large, p.up_unknown), (Some(120), Some(30), None));
+        assert_eq!((p.down_small, p.down_large, p.down_unknown), (Some(98), Some(25), Some(0)));
+        assert_eq!(p.up_power_failure, Some(false));
+        assert_eq!(p.up_loop_error, Some(false));
+        assert_eq!(p.up_ultrasonic_error, Some(false));
+        assert_eq!(p.up_missing, Some(true));
+        // 存在しない属性と空文字列は None になる
+        assert_eq!(p.down_power_failure, None);
+        assert_eq!(p.down_missing, None);
+        // 定義していない属性は保持される
+        assert_eq!(p.extra.get("地方整備局等番号"), Some(&Value::from(3)));
+    }
+
+    #[test]
+    fn cctv() {
+        let body = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": {"type": "MultiPoint", "coordinates": [[135.5, 34.7]]},
+                "properties": {
+                    "常時観測点コード": "6120010",
+                    "時間コード": "202501020305",
+                    "道路種別": 3,
+                    "上り・小型交通量": 10,
+                    "下り・小型交通量": 12,
+                    "下り・欠測": "0"
+                }
+            }]
+        }"#;
+
+        let collection = parse_cctv(body).unwrap();
+        let feature = &collection.features[0];
+        let p = &feature.properties;
+
+        assert_eq!(feature.geometry.as_ref().unwrap().lon_lat(), Some((135.5, 34.7)));
+        assert_eq!(p.station_code, "6120010");
+        assert_eq!(p.time_code, "202501020305");
+        assert_eq!(p.road_type.as_deref(), Some("3"));
+        assert_eq!((p.up_small, p.down_small), (Some(10), Some(12)));
+        assert_eq!(p.down_missing, Some(false));
+    }
+
+    #[test]
+    fn invalid() {
+        // 常時観測点コードがない
+        assert!(parse_permanent(r#"{"features":[{"properties":{"時間コード":202501020300}}]}"#).is_err());
+        // 交通量が数値でない
+        assert!(parse_cctv(r#"{"features":[{"properties":{"常時観測点コード":1,"時間コード":1,"上り・小型交通量":"abc"}}]}"#).is_err());
+        // 負の交通量
+        assert!(parse_cctv(r#"{"features":[{"properties":{"常時観測点コード":1,"時間コード":1,"上り・小型交通量":-1}}]}"#).is_err());
+        // フラグが 0/1 でない
+        assert!(parse_permanent(r#"{"features":[{"properties":{"常時観測点コード":1,"時間コード":1,"上り・停電":2}}]}"#).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{Context, Result, bail};
+use percent_encoding::utf8_percent_encode;
+use serde_json::Value;
+
+use crate::cql::QUERY_VALUE;
+use crate::fetch::{self, RetryPolicy};
+use crate::rate_limit::RateLimiter;
+use crate::response;
+use crate::runner::Reporter;
+
+/// 1回のリクエストで取得する地物の最大数のデフォルト値
+pub const DEFAULT_PAGE_SIZE: usize = 10000;
+
+/// ページ分割して取得する際の並び順の属性名。ページ間で地物が重複・欠落しないよう、順序を固定する
+const SORT_BY: &str = "常時観測点コード";
+
+/// 指定した url のデータを、`page_size` 件ずつページ分割して取得し、1つの FeatureCollection にまとめて返す
+///
+/// `numberMatched` の件数に達するまで取得を続ける。サーバーが件数を返さないときは、取得した地物の数が `page_size` 未満になるまで取得を続ける。
+/// サーバーが `page_size` より少ない件数に制限しているときも、取得した件数ずつ `startIndex` を進めて取得する。
+/// 前のページと同じ地物から始まるページが返されたときは、サーバーが `startIndex` に対応していないものとしてエラーにする。
+/// 1ページのみのときは、レスポンスをそのまま返す。
+/// 最初のページが FeatureCollection でない(サーバー例外など)ときは、そのまま返して `response::classify` での分類に任せる。
+pub async fn get_all_pages(
+    client: &reqwest::Client,
+    url: &str,
+    page_size: usize,
+    policy: &RetryPolicy,
+    limiter: &RateLimiter,
+    reporter: &dyn Reporter,
+) -> Result<String> {
+    let mut pages: Vec<String> = vec![];
+    let mut start_index = 0;
+    let mut previous: Option<Value> = None;
+
+    loop {
+        let body = fetch::get_data_from_url(client, &page_url(url, page_size, start_index), policy, limiter, reporter).await?;
+
+        let Some(page) = Page::parse(&body) else {
+            if pages.is_empty() {
+                return Ok(body);
+            }
+            bail!("{} 件目以降のページ: {}", start_index + 1, response::classify(&body));
+        };
+        if page.first.is_some() && page.first == previous {
+            bail!(
+                "{} 件目以降のページが前のページと同じ地物を返した。サーバーが startIndex によるページ分割に対応していない",
+                start_index + 1
+            );
+        }
+        pages.push(body);
+
+        if page.is_last(page_size, start_index)? {
+            break;
+        }
+        start_index += page.features;
+        previous = page.first;
+    }
+
+    merge(pages)
+}
+
+/// 取得先 URL に、ページ分割のパラメーターを追加する
+pub fn page_url(url: &str, count: usize, start_index: usize) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}count={}&startIndex={}&sortBy={}",
+        url,
+        separator,
+        count,
+        start_index,
+        utf8_percent_encode(SORT_BY, QUERY_VALUE)
+    )
+}
+
+/// 1ページ分のレスポンスの件数
+#[derive(Debug, PartialEq)]
+struct Page {
+    /// ページに含まれる地物の数
+    features: usize,
+    /// 条件に一致する地物の総数。サーバーが件数を返さない (`unknown`) ときは None
+    number_matched: Option<usize>,
+    /// ページの最初の地物。地物がないときは None
+    first: Option<Value>,
+}
+
+impl Page {
+    /// レスポンスを解釈する。FeatureCollection でないときは None
+    fn parse(body: &str) -> Option<Page> {
+        let value: Value = serde_json::from_str(body).ok()?;
+        if value.get("type").and_then(Value::as_str) != Some("FeatureCollection") {
+            return None;
+        }
+
+        let features = value.get("features")?.as_array()?;
+        Some(Page {
+            features: features.len(),
+            number_matched: value.get("numberMatched").and_then(Value::as_u64).map(|n| n as usize),
+            first: features.first().cloned(),
+        })
+    }
+
+    /// 最後のページかどうか
+    ///
+    /// - 総数が分かるときは、総数に達したページを最後とする。総数に達する前に空のページが返されたときはエラー
+    /// - 総数が不明なときは、地物の数が `count` と異なるページを最後とする。`count` を超える地物が返されたときは、
+    ///   サーバーがページ分割に対応していないものとして終了する
+    fn is_last(&self, count: usize, start_index: usize) -> Result<bool> {
+        match self.number_matched {
+            Some(matched) if start_index + self.features >= matched => Ok(true),
+            Some(matched) if self.features == 0 => bail!(
+                "{} 件目以降のページが空だが、条件に一致する地物は {} 件ある",
+                start_index + 1,
+                matched
+            ),
+            Some(_) => Ok(false),
+            None => Ok(self.features != count),
+        }
+    }
+}
+
+/// 複数ページのレスポンスを、最初のページの地物に以降のページの地物を追加した1つの FeatureCollection にまとめる
+fn merge(mut pages: Vec<String>) -> Result<String> {
+    if pages.len() == 1 {
+        return Ok(pages.remove(0));
+    }
+
+    let mut pages = pages.iter().map(|body| serde_json::from_str::<Value>(body));
+    let mut collection = pages.next().context("ページが存在しない")??;
+    let mut features = vec![];
+    for page in pages {
+        let mut page = page?;
+        if let Value::Array(page_features) = page["features"].take() {
+            features.extend(page_features);
+        }
+    }
+
+    let all = collection["features"].as_array_mut().context("features が存在しない")?;
+    all.extend(features);
+    let returned = all.len();
+    if let Some(number_returned) = collection.get_mut("numberReturned") {
+        *number_returned = returned.into();
+    }
+
+    Ok(serde_json::to_string(&collection)?)
+}
+
+#[cfg(test)]
+mod paging_tests {
+    use super::*;
+    use crate::runner::Quiet;
+    use tokio::time::Duration;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// 指定した常時観測点コードの地物を含むページ
+    fn page(codes: &[u32], matched: Option<usize>) -> String {
+        let features: Vec<String> = codes
+            .iter()
+            .map(|code| format!(r#"{{"type":"Feature","properties":{{"常時観測点コード":{}}}}}"#, code))
+            .collect();
+        let matched = matched.map_or("\"unknown\"".into(), |m| m.to_string());
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}],"totalFeatures":{},"numberMatched":{},"numberReturned":{}}}"#,
+            features.join(","),
+            matched,
+            matched,
+            codes.len()
+        )
+    }
+
+    async fn mount(server: &MockServer, start_index: &str, body: String) {
+        Mock::given(method("GET"))
+            .and(query_param("startIndex", start_index))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    async fn fetch(server: &MockServer, page_size: usize) -> Result<String> {
+        let policy = RetryPolicy {
+            max_retries: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        };
+        let url = format!("{}/geoserver?service=WFS", server.uri());
+        get_all_pages(&reqwest::Client::new(), &url, page_size, &policy, &RateLimiter::unlimited(), &Quiet).await
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            page_url("https://example.com/geoserver?service=WFS", 1000, 2000),
+            "https://example.com/geoserver?service=WFS&count=1000&startIndex=2000&sortBy=%E5%B8%B8%E6%99%82%E8%A6%B3%E6%B8%AC%E7%82%B9%E3%82%B3%E3%83%BC%E3%83%89"
+        );
+        assert!(page_url("http://127.0.0.1:8080", 10, 0).starts_with("http://127.0.0.1:8080?count=10&startIndex=0&"));
+    }
+
+    #[test]
+    fn last_page() {
+        let page = |features, number_matched| Page {
+            features,
+            number_matched,
+            first: None,
+        };
+
+        assert!(page(1, None).is_last(2, 0).unwrap());
+        assert!(page(0, None).is_last(2, 4).unwrap());
+        assert!(!page(2, None).is_last(2, 0).unwrap());
+        assert!(!page(2, Some(5)).is_last(2, 2).unwrap());
+        assert!(page(2, Some(4)).is_last(2, 2).unwrap());
+        // サーバーが1ページの件数を制限しているときは、総数に達するまで続ける
+        assert!(!page(2, Some(5)).is_last(3, 0).unwrap());
+        assert!(page(1, Some(5)).is_last(3, 4).unwrap());
+        assert!(page(0, Some(5)).is_last(3, 4).is_err());
+        // ページ分割に対応していないサーバー
+        assert!(page(3, None).is_last(2, 0).unwrap());
+        assert!(page(5, Some(5)).is_last(2, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn merge_pages() {
+        let server = MockServer::start().await;
+        mount(&server, "0", page(&[1, 2], Some(5))).await;
+        mount(&server, "2", page(&[3, 4], Some(5))).await;
+        mount(&server, "4", page(&[5], Some(5))).await;
+
+        let body = fetch(&server, 2).await.unwrap();
+        let value: Value = serde_json::from_str(&body).unwrap();
+
+        let codes: Vec<u64> = value["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["properties"]["常時観測点コード"].as_u64().unwrap())
+            .collect();
+        assert_eq!(codes, [1, 2, 3, 4, 5]);
+        assert_eq!(value["numberMatched"], 5);
+        assert_eq!(value["numberReturned"], 5);
+        // 属性の順序は変更しない
+        assert!(body.starts_with(r#"{"type":"FeatureCollection","features":["#));
+    }
+
+    #[tokio::test]
+    async fn capped_page_size() {
+        // サーバーが1ページを2件に制限しているときも、取得した件数ずつ進めて総数まで取得する
+        let server = MockServer::start().await;
+        mount(&server, "0", page(&[1, 2], Some(5))).await;
+        mount(&server, "2", page(&[3, 4], Some(5))).await;
+        mount(&server, "4", page(&[5], Some(5))).await;
+
+        let body = fetch(&server, 3).await.unwrap();
+        assert_eq!(response::classify(&body), response::ResponseKind::Data { features: 5 });
+    }
+
+    #[tokio::test]
+    async fn number_matched_reached() {
+        // 件数がページの大きさで割り切れるときは、`numberMatched` に達した時点で終了する
+        let server = MockServer::start().await;
+        mount(&server, "0", page(&[1, 2], Some(4))).await;
+        mount(&server, "2", page(&[3, 4], Some(4))).await;
+
+        let body = fetch(&server, 2).await.unwrap();
+        assert_eq!(response::classify(&body), response::ResponseKind::Data { features: 4 });
+    }
+
+    #[tokio::test]
+    async fn number_matched_unknown() {
+        // 総数が不明なときは、空のページを取得するまで続ける
+        let server = MockServer::start().await;
+        mount(&server, "0", page(&[1, 2], None)).await;
+        mount(&server, "2", page(&[], None)).await;
+
+        let body = fetch(&server, 2).await.unwrap();
+        assert_eq!(response::classify(&body), response::ResponseKind::Data { features: 2 });
+    }
+
+    #[tokio::test]
+    async fn start_index_ignored() {
+        // startIndex を無視して同じページを返し続けるサーバーでは、2ページ目でエラーにする
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(page(&[1, 2], None)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let error = fetch(&server, 2).await.unwrap_err();
+        assert!(error.to_string().contains("startIndex"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn single_page() {
+        // 1ページのみのときは、レスポンスをそのまま返す
+        let server = MockServer::start().await;
+        let body = page(&[1], Some(1));
+        mount(&server, "0", body.clone()).await;
+
+        assert_eq!(fetch(&server, 2).await.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn exception() {
+        let exception = r#"{"exceptions":[{"code":"NoApplicableCode","text":"error"}]}"#;
+
+        // 最初のページの例外は、そのまま返す
+        let server = MockServer::start().await;
+        mount(&server, "0", exception.into()).await;
+        assert_eq!(fetch(&server, 2).await.unwrap(), exception);
+
+        // 途中のページの例外は、取得の失敗とする
+        let server = MockServer::start().await;
+        mount(&server, "0", page(&[1, 2], None)).await;
+        mount(&server, "2", exception.into()).await;
+        assert!(fetch(&server, 2).await.is_err());
+    }
+}
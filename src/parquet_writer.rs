@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, BinaryArray, BooleanArray, Float64Array, RecordBatch, StringArray, TimestampSecondArray, UInt32Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::de::DeserializeOwned;
+
+use crate::model::{COUNTS, CctvProperties, Feature, FeatureCollection, Observation, PermanentProperties};
+use crate::types::{CounterType, GeometryEncoding};
+use crate::url::Target;
+use crate::{datetime, template};
+
+/// パーティションのディレクトリのテンプレート。Hive 形式 (`key=value`) で、取得間隔・観測機器・日付ごとに分割する
+const PARTITION_TEMPLATE: &str = "interval={interval}/counter={counter}/date={yyyy}-{mm}-{dd}";
+
+/// 観測日時のタイムゾーン。日本標準時の観測日時は、タイムゾーンを持たない現地時刻として別の列に保存する
+const UTC: &str = "UTC";
+
+/// 取得したデータを、パーティション分割した Parquet ファイルへ書き出す
+///
+/// 実行ごとにパーティション内へ新しいファイル (`part-<実行日時>-<乱数>.parquet`) を追加するため、
+/// 複数回の実行の結果を同じディレクトリへ追記できる。書き込み中のファイルは `.` で始まる一時ファイルとし、
+/// `finish` で正式なファイル名へ変更する。
+pub struct ParquetWriter {
+    root: PathBuf,
+    geometry: GeometryEncoding,
+    /// 今回の実行で作成するファイル名
+    file_name: String,
+    /// パーティションのディレクトリごとの書き込み中のファイル
+    parts: HashMap<PathBuf, Part>,
+}
+
+struct Part {
+    writer: ArrowWriter<File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl ParquetWriter {
+    /// 指定したディレクトリへ書き出すライターを生成する。ファイルは最初のデータの追加時に作成する
+    pub fn new(root: impl Into<PathBuf>, geometry: GeometryEncoding) -> Self {
+        let file_name = format!(
+            "part-{}-{:08x}.parquet",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+            fastrand::u32(..)
+        );
+
+        ParquetWriter {
+            root: root.into(),
+            geometry,
+            file_name,
+            parts: HashMap::new(),
+        }
+    }
+
+    /// 取得したレスポンスを追加する。追加した行数を返す
+    ///
+    /// レスポンスは `response::classify` で正常なデータと判定済みであること。
+    pub fn add(&mut self, target: &Target, body: &str) -> Result<usize> {
+        let batch = match target.counter_type {
+            CounterType::Permanent => record_batch(&parse::<PermanentProperties>(body)?, self.geometry)?,
+            CounterType::Cctv => record_batch(&parse::<CctvProperties>(body)?, self.geometry)?,
+        };
+        if batch.num_rows() == 0 {
+            return Ok(0);
+        }
+
+        let dir = self.root.join(template::render(PARTITION_TEMPLATE, target));
+        let part = match self.parts.entry(dir) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let part = Part::create(entry.key(), &self.file_name, batch.schema())?;
+                entry.insert(part)
+            }
+        };
+        part.writer.write(&batch).context("Parquet ファイルへ書き込めない")?;
+
+        Ok(batch.num_rows())
+    }
+
+    /// 書き込み中のファイルを閉じ、正式なファイル名へ変更する。作成したファイルのパスを返す
+    pub fn finish(self) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+
+        for part in self.parts.into_values() {
+            part.writer.close().context("Parquet ファイルへ書き込めない")?;
+            std::fs::rename(&part.temp_path, &part.path).with_context(|| format!("{} を作成できない", part.path.display()))?;
+            paths.push(part.path);
+        }
+
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+impl Part {
+    fn create(dir: &Path, file_name: &str, schema: SchemaRef) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("ディレクトリ {} を作成できない", dir.display()))?;
+
+        let temp_path = dir.join(format!(".{}.tmp", file_name));
+        let file = File::create(&temp_path).with_context(|| format!("{} を作成できない", temp_path.display()))?;
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(file, schema, Some(properties))?;
+
+        Ok(Part {
+            writer,
+            temp_path,
+            path: dir.join(file_name),
+        })
+    }
+}
+
+fn parse<P: DeserializeOwned>(body: &str) -> Result<FeatureCollection<P>> {
+    serde_json::from_str(body).context("観測値として解釈不能")
+}
+
+/// 観測機器とジオメトリの形式ごとのスキーマ
+///
+/// 列の構成はデータの内容によらず一定で、値が存在しないときは null とする。
+pub fn schema(counter_type: CounterType, geometry: GeometryEncoding) -> SchemaRef {
+    let flags = match counter_type {
+        CounterType::Permanent => PermanentProperties::FLAGS,
+        CounterType::Cctv => CctvProperties::FLAGS,
+    };
+
+    let mut fields = vec![
+        Field::new("station", DataType::Utf8, false),
+        Field::new("time", DataType::Utf8, false),
+        Field::new("observed_at", DataType::Timestamp(TimeUnit::Second, Some(UTC.into())), true),
+        Field::new("observed_at_jst", DataType::Timestamp(TimeUnit::Second, None), true),
+        Field::new("road", DataType::Utf8, true),
+    ];
+    match geometry {
+        GeometryEncoding::Lonlat => {
+            fields.push(Field::new("lon", DataType::Float64, true));
+            fields.push(Field::new("lat", DataType::Float64, true));
+        }
+        GeometryEncoding::Wkb => fields.push(Field::new("geometry", DataType::Binary, true)),
+    }
+    fields.extend(COUNTS.iter().map(|name| Field::new(*name, DataType::UInt32, true)));
+    fields.extend(flags.iter().map(|name| Field::new(*name, DataType::Boolean, true)));
+
+    Arc::new(Schema::new(fields))
+}
+
+/// 観測値を Parquet に書き込む列形式に変換する
+fn record_batch<P: Observation>(collection: &FeatureCollection<P>, geometry: GeometryEncoding) -> Result<RecordBatch> {
+    let features = &collection.features;
+    let properties: Vec<&P> = features.iter().map(|f| &f.properties).collect();
+    let lon_lat: Vec<Option<(f64, f64)>> = features.iter().map(lon_lat).collect();
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(properties.iter().map(|p| p.station_code()))),
+        Arc::new(StringArray::from_iter_values(properties.iter().map(|p| p.time_code()))),
+        Arc::new(TimestampSecondArray::from_iter(properties.iter().map(|p| observed_at(p.time_code()))).with_timezone(UTC)),
+        Arc::new(TimestampSecondArray::from_iter(
+            properties.iter().map(|p| observed_at_jst(p.time_code())),
+        )),
+        Arc::new(StringArray::from_iter(properties.iter().map(|p| p.road_type()))),
+    ];
+    match geometry {
+        GeometryEncoding::Lonlat => {
+            columns.push(Arc::new(Float64Array::from_iter(lon_lat.iter().map(|p| p.map(|(lon, _)| lon)))));
+            columns.push(Arc::new(Float64Array::from_iter(lon_lat.iter().map(|p| p.map(|(_, lat)| lat)))));
+        }
+        GeometryEncoding::Wkb => {
+            columns.push(Arc::new(BinaryArray::from_iter(lon_lat.iter().map(|p| p.map(point_wkb)))));
+        }
+    }
+    for i in 0..COUNTS.len() {
+        columns.push(Arc::new(UInt32Array::from_iter(properties.iter().map(|p| p.counts()[i]))));
+    }
+    let flags: Vec<Vec<Option<bool>>> = properties.iter().map(|p| p.flags()).collect();
+    for i in 0..P::FLAGS.len() {
+        columns.push(Arc::new(BooleanArray::from_iter(flags.iter().map(|f| f[i]))));
+    }
+
+    Ok(RecordBatch::try_new(schema(P::COUNTER_TYPE, geometry), columns)?)
+}
+
+fn lon_lat<P>(feature: &Feature<P>) -> Option<(f64, f64)> {
+    feature.geometry.as_ref().and_then(|g| g.lon_lat())
+}
+
+/// 時間コード (YYYYMMDDHHMM、日本時間) を UNIX 時間に変換する
+fn observed_at(time_code: &str) -> Option<i64> {
+    Some(datetime::from_time_code(time_code)?.timestamp())
+}
+
+/// 時間コード (YYYYMMDDHHMM、日本時間) を、日本標準時の現地時刻を UTC とみなした UNIX 時間に変換する
+fn observed_at_jst(time_code: &str) -> Option<i64> {
+    Some(datetime::from_time_code(time_code)?.naive_local().and_utc().timestamp())
+}
+
+/// 経度・緯度の点を WKB (リトルエンディアン) に変換する
+fn point_wkb((lon, lat): (f64, f64)) -> Vec<u8> {
+    let mut wkb = Vec::with_capacity(21);
+    wkb.push(1); // リトルエンディアン
+    wkb.extend_from_slice(&1u32.to_le_bytes()); // Point
+    wkb.extend_from_slice(&lon.to_le_bytes());
+    wkb.extend_from_slice(&lat.to_le_bytes());
+    wkb
+}
+
+#[cfg(test)]
+mod parquet_writer_tests {
+    use super::*;
+    use crate::types::{Interval, RoadType};
+    use arrow_array::Array;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{TimestampSecondType, UInt32Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const PERMANENT: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[139.7,35.6]},
+         "properties":{"常時観測点コード":3310840,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":120,"上り・欠測":0}},
+        {"type":"Feature","geometry":null,
+         "properties":{"常時観測点コード":3310850,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":null,"上り・欠測":1}}
+    ]}"#;
+
+    const CCTV: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[135.5,34.7]},
+         "properties":{"常時観測点コード":6120010,"時間コード":202501020300,"道路種別":3,"下り・小型交通量":10}}
+    ]}"#;
+
+    fn target(time: &str, counter_type: CounterType) -> Target {
+        Target {
+            time: time.into(),
+            interval: Interval::H1,
+            road_type: RoadType::Both,
+            counter_type,
+            name: String::new(),
+            url: String::new(),
+        }
+    }
+
+    fn read(path: &Path) -> Vec<RecordBatch> {
+        let file = File::open(path).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        reader.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetWriter::new(dir.path(), GeometryEncoding::Lonlat);
+
+        assert_eq!(writer.add(&target("202501020300", CounterType::Permanent), PERMANENT).unwrap(), 2);
+        assert_eq!(writer.add(&target("202501020400", CounterType::Permanent), PERMANENT).unwrap(), 2);
+        assert_eq!(writer.add(&target("202501030000", CounterType::Permanent), PERMANENT).unwrap(), 2);
+        assert_eq!(writer.add(&target("202501020300", CounterType::Cctv), CCTV).unwrap(), 1);
+        let paths = writer.finish().unwrap();
+
+        let partitions: Vec<PathBuf> = paths
+            .iter()
+            .map(|p| p.parent().unwrap().strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            partitions,
+            [
+                "interval=1h/counter=cctv/date=2025-01-02",
+                "interval=1h/counter=permanent/date=2025-01-02",
+                "interval=1h/counter=permanent/date=2025-01-03",
+            ]
+            .map(PathBuf::from)
+        );
+
+        // 同じパーティションのデータは1つのファイルにまとめられる
+        let rows: usize = read(&paths[1]).iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, 4);
+        // 一時ファイルが残っていない
+        let names: Vec<_> = std::fs::read_dir(paths[1].parent().unwrap()).unwrap().collect();
+        assert_eq!(names.len(), 1);
+    }
+
+    #[test]
+    fn columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetWriter::new(dir.path(), GeometryEncoding::Lonlat);
+        writer.add(&target("202501020300", CounterType::Permanent), PERMANENT).unwrap();
+        let paths = writer.finish().unwrap();
+
+        let batch = &read(&paths[0])[0];
+        assert_eq!(batch.schema(), schema(CounterType::Permanent, GeometryEncoding::Lonlat));
+
+        let station = batch.column_by_name("station").unwrap().as_string::<i32>();
+        assert_eq!((station.value(0), station.value(1)), ("3310840", "3310850"));
+        // 2025-01-02 03:00 JST = 2025-01-01 18:00 UTC
+        let observed_at = batch.column_by_name("observed_at").unwrap();
+        assert_eq!(observed_at.data_type(), &DataType::Timestamp(TimeUnit::Second, Some("UTC".into())));
+        assert_eq!(observed_at.as_primitive::<TimestampSecondType>().value(0), 1735754400);
+        // 日本標準時の観測日時は、タイムゾーンを持たない現地時刻 (2025-01-02 03:00) とする
+        let observed_at_jst = batch.column_by_name("observed_at_jst").unwrap();
+        assert_eq!(observed_at_jst.data_type(), &DataType::Timestamp(TimeUnit::Second, None));
+        assert_eq!(observed_at_jst.as_primitive::<TimestampSecondType>().value(0), 1735786800);
+        let lon = batch
+            .column_by_name("lon")
+            .unwrap()
+            .as_primitive::<arrow_array::types::Float64Type>();
+        assert_eq!(lon.value(0), 139.7);
+        assert!(lon.is_null(1));
+        let up_small = batch.column_by_name("up_small").unwrap().as_primitive::<UInt32Type>();
+        assert_eq!(up_small.value(0), 120);
+        assert!(up_small.is_null(1));
+        let up_missing = batch.column_by_name("up_missing").unwrap().as_boolean();
+        assert!(!up_missing.value(0) && up_missing.value(1));
+        // 値が存在しない列も null として出力される
+        assert_eq!(batch.column_by_name("down_loop_error").unwrap().null_count(), 2);
+    }
+
+    #[test]
+    fn wkb() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetWriter::new(dir.path(), GeometryEncoding::Wkb);
+        writer.add(&target("202501020300", CounterType::Cctv), CCTV).unwrap();
+        let paths = writer.finish().unwrap();
+
+        let batch = &read(&paths[0])[0];
+        assert_eq!(batch.schema(), schema(CounterType::Cctv, GeometryEncoding::Wkb));
+        assert!(batch.column_by_name("lon").is_none());
+
+        let geometry = batch.column_by_name("geometry").unwrap().as_binary::<i32>();
+        assert_eq!(geometry.value(0), point_wkb((135.5, 34.7)));
+        assert_eq!(geometry.value(0)[..5], [1, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn append() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // 実行ごとに、同じパーティションへ別のファイルを追加する
+        for _ in 0..2 {
+            let mut writer = ParquetWriter::new(dir.path(), GeometryEncoding::Lonlat);
+            writer.add(&target("202501020300", CounterType::Permanent), PERMANENT).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let partition = dir.path().join("interval=1h/counter=permanent/date=2025-01-02");
+        assert_eq!(std::fs::read_dir(partition).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetWriter::new(dir.path(), GeometryEncoding::Lonlat);
+
+        // データのないレスポンスではファイルを作成しない
+        let body = r#"{"type":"FeatureCollection","features":[]}"#;
+        assert_eq!(writer.add(&target("202501020300", CounterType::Permanent), body).unwrap(), 0);
+        assert!(writer.finish().unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
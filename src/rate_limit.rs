@@ -0,0 +1,156 @@
+use anyhow::{Result, ensure};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant, sleep_until};
+
+/// バケットの容量。連続して即座に実行できるリクエスト数
+///
+/// 1 とすることで、連続したリクエストの間隔を常に `1 / rate` 秒以上に保つ。
+const CAPACITY: f64 = 1.0;
+
+/// 指定できる1秒あたりのリクエスト数の最小値。リクエストの間隔は最大 1000 秒となる
+pub const MIN_RATE: f64 = 0.001;
+
+/// 1秒あたりのリクエスト数が指定できる値かどうかを確認する
+pub fn validate_rate(rate: f64) -> Result<()> {
+    ensure!(
+        rate.is_finite() && rate >= MIN_RATE,
+        "1秒あたりのリクエスト数 {} は {} 以上で指定する必要がある",
+        rate,
+        MIN_RATE
+    );
+    Ok(())
+}
+
+/// トークンバケット方式のレートリミッター
+///
+/// 複数のタスクから共有して使用し、全体のリクエスト頻度を一定以下に抑える。
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// 1秒あたりに補充するトークン数。None のときは制限しない
+    rate: Option<f64>,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// 残りのトークン数。待機中のリクエストに予約された分だけ負になる
+    tokens: f64,
+    /// 最後にトークンを補充した時刻
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// 1秒あたり `rate` 回までにリクエストを制限するレートリミッターを生成する。`rate` は [`validate_rate`] で確認した値とする
+    pub fn new(rate: f64) -> Self {
+        Self::with_rate(Some(rate))
+    }
+
+    /// リクエストを制限しないレートリミッターを生成する
+    pub fn unlimited() -> Self {
+        Self::with_rate(None)
+    }
+
+    fn with_rate(rate: Option<f64>) -> Self {
+        RateLimiter {
+            rate,
+            state: Mutex::new(Bucket {
+                tokens: CAPACITY,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// トークンを1つ取得する。トークンがないときは、補充されるまで待機する
+    pub async fn acquire(&self) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+
+        let wake = {
+            let mut bucket = self.state.lock().await;
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(CAPACITY);
+            bucket.updated = now;
+
+            // トークンを先に予約し、不足分が補充されるまで待機する
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            now + Duration::from_secs_f64(-bucket.tokens / rate)
+        };
+
+        sleep_until(wake).await;
+    }
+}
+
+#[cfg(test)]
+mod rate_limiter_tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn sequential() {
+        let limiter = RateLimiter::new(2.0);
+        let started = Instant::now();
+
+        // 最初の1回は即座に取得でき、以降は 0.5 秒ごとに取得できる
+        for i in 0..5 {
+            limiter.acquire().await;
+            let elapsed = started.elapsed().as_secs_f64();
+            assert!((elapsed - i as f64 * 0.5).abs() < 0.01, "{i}: {elapsed}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shared() {
+        let limiter = Arc::new(RateLimiter::new(1.0));
+        let started = Instant::now();
+
+        // 4つのタスクで共有しても、全体で1秒あたり1回に制限される
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move {
+                    limiter.acquire().await;
+                    started.elapsed().as_secs_f64().round() as u64
+                })
+            })
+            .collect();
+
+        let mut times = vec![];
+        for handle in handles {
+            times.push(handle.await.unwrap());
+        }
+        times.sort();
+
+        assert_eq!(times, vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_burst_after_idle() {
+        let limiter = RateLimiter::new(1.0);
+        limiter.acquire().await;
+
+        // 長時間待機しても、バケットの容量を超えてトークンが貯まらない
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let started = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+
+        assert!((started.elapsed().as_secs_f64() - 1.0).abs() < 0.01);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unlimited() {
+        let limiter = RateLimiter::unlimited();
+        let started = Instant::now();
+
+        for _ in 0..100 {
+            limiter.acquire().await;
+        }
+
+        assert_eq!(started.elapsed(), Duration::ZERO);
+    }
+}
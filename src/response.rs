@@ -0,0 +1,160 @@
+use std::fmt;
+
+use serde_json::Value;
+
+/// 取得したレスポンスの分類
+#[derive(Debug, PartialEq)]
+pub enum ResponseKind {
+    /// 1件以上の地物を含むデータ
+    Data { features: usize },
+    /// 地物を含まない FeatureCollection。まだデータが公開されていない場合など
+    Empty,
+    /// サーバーが返した例外レポート。クエリの誤りなど
+    Exception(String),
+    /// JSON として解釈できない、または FeatureCollection でないレスポンス
+    Invalid(String),
+}
+
+impl ResponseKind {
+    /// 正常なデータ以外(保存時の扱いを選択できるもの)かどうか
+    pub fn is_anomaly(&self) -> bool {
+        !matches!(self, ResponseKind::Data { .. })
+    }
+
+    /// 取得の失敗として扱うべきものかどうか
+    pub fn is_failure(&self) -> bool {
+        matches!(self, ResponseKind::Exception(_) | ResponseKind::Invalid(_))
+    }
+}
+
+impl fmt::Display for ResponseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseKind::Data { features } => write!(f, "データ ({} 件)", features),
+            ResponseKind::Empty => write!(f, "データなし"),
+            ResponseKind::Exception(message) => write!(f, "サーバー例外: {}", message),
+            ResponseKind::Invalid(message) => write!(f, "不正なレスポンス: {}", message),
+        }
+    }
+}
+
+/// レスポンスの本文を解釈して分類する
+pub fn classify(body: &str) -> ResponseKind {
+    // `exceptions=application/json` を指定していても、XML の例外レポートが返されることがある
+    let trimmed = body.trim_start();
+    if trimmed.starts_with('<') {
+        if trimmed.contains("ExceptionReport") {
+            return ResponseKind::Exception(xml_exception_text(trimmed));
+        }
+        return ResponseKind::Invalid("JSON ではなく XML/HTML が返された".into());
+    }
+
+    let value: Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(e) => return ResponseKind::Invalid(format!("JSON として解釈不能: {}", e)),
+    };
+
+    // GeoServer の JSON 形式の例外レポート
+    // {"version":"2.0.0","exceptions":[{"code":"...","locator":"...","text":"..."}]}
+    if let Some(exceptions) = value.get("exceptions").and_then(Value::as_array) {
+        let messages: Vec<String> = exceptions.iter().map(json_exception_text).collect();
+        return ResponseKind::Exception(messages.join(" / "));
+    }
+
+    if value.get("type").and_then(Value::as_str) != Some("FeatureCollection") {
+        return ResponseKind::Invalid("FeatureCollection ではない".into());
+    }
+
+    let features = match value.get("features").and_then(Value::as_array) {
+        Some(features) => features.len(),
+        None => return ResponseKind::Invalid("features が存在しない".into()),
+    };
+
+    if features == 0 {
+        ResponseKind::Empty
+    } else {
+        ResponseKind::Data { features }
+    }
+}
+
+/// JSON の例外レポートの1件を文字列にする
+fn json_exception_text(exception: &Value) -> String {
+    let code = exception.get("code").and_then(Value::as_str).unwrap_or("不明");
+    let text = exception.get("text").and_then(Value::as_str).unwrap_or("").trim();
+    format!("{}: {}", code, text)
+}
+
+/// XML の例外レポートから ExceptionText 要素の内容を取り出す。取り出せない場合は本文の先頭を返す
+fn xml_exception_text(body: &str) -> String {
+    let text = body
+        .split_once("ExceptionText>")
+        .and_then(|(_, rest)| rest.split_once("</"))
+        .map(|(text, _)| text.trim());
+
+    match text {
+        Some(text) => text.to_string(),
+        None => body.chars().take(200).collect(),
+    }
+}
+
+#[cfg(test)]
+mod classify_tests {
+    use super::*;
+
+    #[test]
+    fn data() {
+        let body = r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{}},{"type":"Feature","properties":{}}],"totalFeatures":2}"#;
+        assert_eq!(classify(body), ResponseKind::Data { features: 2 });
+    }
+
+    #[test]
+    fn empty() {
+        let body = r#"{"type":"FeatureCollection","features":[],"totalFeatures":0,"numberMatched":0,"numberReturned":0}"#;
+        assert_eq!(classify(body), ResponseKind::Empty);
+    }
+
+    #[test]
+    fn json_exception() {
+        let body =
+            r#"{"version":"2.0.0","exceptions":[{"code":"InvalidParameterValue","locator":"typeName","text":"Feature type foo unknown"}]}"#;
+        assert_eq!(
+            classify(body),
+            ResponseKind::Exception("InvalidParameterValue: Feature type foo unknown".into())
+        );
+    }
+
+    #[test]
+    fn xml_exception() {
+        let body = r#"<?xml version="1.0" ?>
+<ows:ExceptionReport version="2.0.0" xmlns:ows="http://www.opengis.net/ows/1.1">
+  <ows:Exception exceptionCode="NoApplicableCode">
+    <ows:ExceptionText>Could not parse CQL filter</ows:ExceptionText>
+  </ows:Exception>
+</ows:ExceptionReport>"#;
+        assert_eq!(classify(body), ResponseKind::Exception("Could not parse CQL filter".into()));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            classify("<html><body>502 Bad Gateway</body></html>"),
+            ResponseKind::Invalid(_)
+        ));
+        assert!(matches!(
+            classify(r#"{"type":"FeatureCollection","features":["#),
+            ResponseKind::Invalid(_)
+        ));
+        assert!(matches!(classify(r#"{"type":"Feature"}"#), ResponseKind::Invalid(_)));
+        assert!(matches!(classify(r#"{"type":"FeatureCollection"}"#), ResponseKind::Invalid(_)));
+        assert!(matches!(classify(""), ResponseKind::Invalid(_)));
+    }
+
+    #[test]
+    fn anomaly_and_failure() {
+        assert!(!ResponseKind::Data { features: 1 }.is_anomaly());
+        assert!(ResponseKind::Empty.is_anomaly());
+        assert!(!ResponseKind::Empty.is_failure());
+        assert!(ResponseKind::Exception("".into()).is_failure());
+        assert!(ResponseKind::Invalid("".into()).is_failure());
+    }
+}
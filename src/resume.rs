@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use crate::compress;
+use crate::response::{self, ResponseKind};
+
+/// 再開モードで、保存済みのファイルがあるため取得を省略できるかどうかを判定する
+///
+/// - 圧縮なし・`.gz`・`.zst` のいずれかのファイルで判定する。複数あるときは圧縮なしのファイルを優先する
+/// - ファイルが存在しない、または読み込めないときは取得する
+/// - 不正な内容(途中で中断された書き込みなど)のときは取得する
+/// - データなし・サーバー例外のときは、`refetch_anomalies` が指定されていれば取得する
+/// - 正常なデータのときは取得を省略する
+pub async fn can_skip(path: &Path, refetch_anomalies: bool) -> bool {
+    let Some(path) = compress::candidates(path).into_iter().find(|p| p.is_file()) else {
+        return false;
+    };
+    let Ok(bytes) = tokio::fs::read(&path).await else {
+        return false;
+    };
+    let Ok(content) = compress::decode(&bytes) else {
+        return false;
+    };
+
+    match response::classify(&content) {
+        ResponseKind::Data { .. } => true,
+        ResponseKind::Empty | ResponseKind::Exception(_) => !refetch_anomalies,
+        ResponseKind::Invalid(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod can_skip_tests {
+    use super::*;
+    use crate::compress::Compression;
+    use crate::types::CompressFormat;
+
+    const DATA: &str = r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{}}]}"#;
+    const EMPTY: &str = r#"{"type":"FeatureCollection","features":[]}"#;
+    const EXCEPTION: &str = r#"{"exceptions":[{"code":"NoApplicableCode","text":"error"}]}"#;
+
+    #[tokio::test]
+    async fn not_exists() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(!can_skip(&dir.path().join("H202501020000P.json"), false).await);
+    }
+
+    #[tokio::test]
+    async fn data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("H202501020000P.json");
+        std::fs::write(&path, DATA).unwrap();
+
+        assert!(can_skip(&path, false).await);
+        assert!(can_skip(&path, true).await);
+    }
+
+    #[tokio::test]
+    async fn anomaly() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("H202501020000P.json");
+
+        for content in [EMPTY, EXCEPTION] {
+            std::fs::write(&path, content).unwrap();
+
+            // 指定時のみ再取得することを確認
+            assert!(can_skip(&path, false).await);
+            assert!(!can_skip(&path, true).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("H202501020000P.json");
+        std::fs::write(&path, r#"{"type":"FeatureCollection","features":[{"#).unwrap();
+
+        assert!(!can_skip(&path, false).await);
+    }
+
+    #[tokio::test]
+    async fn compressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("H202501020000P.json");
+
+        for (format, name) in [
+            (CompressFormat::Gzip, "H202501020000P.json.gz"),
+            (CompressFormat::Zstd, "H202501020000P.json.zst"),
+        ] {
+            let compression = Compression::new(format, None).unwrap();
+            std::fs::write(dir.path().join(name), compression.compress(EMPTY.as_bytes()).unwrap()).unwrap();
+
+            // 圧縮したファイルの内容で判定することを確認
+            assert!(can_skip(&path, false).await);
+            assert!(!can_skip(&path, true).await);
+
+            std::fs::write(dir.path().join(name), compression.compress(DATA.as_bytes()).unwrap()).unwrap();
+            assert!(can_skip(&path, true).await);
+            std::fs::remove_file(dir.path().join(name)).unwrap();
+        }
+
+        // 展開できないファイルは再取得する
+        std::fs::write(dir.path().join("H202501020000P.json.gz"), [0x1f, 0x8b, 0x00]).unwrap();
+        assert!(!can_skip(&path, false).await);
+    }
+}
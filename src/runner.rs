@@ -0,0 +1,197 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+
+use crate::client::Client;
+use crate::execution_option::ExecutionOption;
+use crate::sink::{self, Meta, OutputSink};
+use crate::types::AnomalyAction;
+use crate::url::Target;
+use crate::{response, resume, template, url};
+
+/// 取得対象ごとの処理結果
+enum Outcome {
+    /// 再開モードで、保存済みのため取得を省略した
+    Skipped,
+    /// ドライランのため取得しなかった
+    Dry,
+    /// 取得に成功した
+    Fetched(String),
+    /// 再試行しても取得に失敗した
+    Failed(anyhow::Error),
+}
+
+/// 取得・保存の進捗とエラーの通知先
+///
+/// CLI では進捗を標準出力、エラーを標準エラー出力へ表示する。ライブラリとして使用するときは、ログへの記録などに置き換えられる。
+pub trait Reporter: Send + Sync {
+    /// 進捗を通知する
+    fn progress(&self, message: &str);
+    /// 個々のデータの取得・保存の失敗や、取得の再試行を通知する。失敗しても、次の取得対象の処理へ進む
+    fn error(&self, message: &str);
+}
+
+/// 何も通知しない Reporter
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Quiet;
+
+impl Reporter for Quiet {
+    fn progress(&self, _message: &str) {}
+    fn error(&self, _message: &str) {}
+}
+
+/// 通知されたメッセージを記録する Reporter。テスト用
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    pub progress: std::sync::Mutex<Vec<String>>,
+    pub errors: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl Reporter for Recorder {
+    fn progress(&self, message: &str) {
+        self.progress.lock().unwrap().push(message.to_string());
+    }
+    fn error(&self, message: &str) {
+        self.errors.lock().unwrap().push(message.to_string());
+    }
+}
+
+/// 実行結果の集計
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    /// 取得したデータの数。データなし・サーバー例外などを含む
+    pub fetched: usize,
+    /// 再開モードで、保存済みのため取得を省略したデータの数
+    pub skipped: usize,
+    /// 取得に失敗した、またはサーバー例外・不正なレスポンスを取得したデータの数
+    pub failures: usize,
+}
+
+impl std::ops::AddAssign<&Summary> for Summary {
+    fn add_assign(&mut self, other: &Summary) {
+        self.fetched += other.fetched;
+        self.skipped += other.skipped;
+        self.failures += other.failures;
+    }
+}
+
+/// 実行時のオプションに従ってデータを取得し、オプションで指定した保存先へ保存する
+pub async fn run(option: &ExecutionOption, reporter: Arc<dyn Reporter>) -> Result<Summary> {
+    // ドライランのときは、データベースなどの保存先を作成しない
+    let sinks = if option.dry { vec![] } else { sink::from_option(option)? };
+    run_with_sinks(option, sinks, reporter).await
+}
+
+/// 実行時のオプションに従ってデータを取得し、指定した保存先へ保存する
+///
+/// 個々のデータの取得・保存の失敗は `reporter` へ通知して集計し、保存の完了処理に失敗したときのみエラーを返す。
+pub async fn run_with_sinks(option: &ExecutionOption, sinks: Vec<Box<dyn OutputSink>>, reporter: Arc<dyn Reporter>) -> Result<Summary> {
+    // 取得頻度を下げるため、すべての取得でクライアントのレートリミッターを共有する
+    run_with_client(option, &Client::from_option(option).with_reporter(reporter), sinks).await
+}
+
+/// 実行時のオプションに従って、指定したクライアントでデータを取得し、指定した保存先へ保存する。クライアントの通知先へ通知する
+pub(crate) async fn run_with_client(option: &ExecutionOption, client: &Client, mut sinks: Vec<Box<dyn OutputSink>>) -> Result<Summary> {
+    let reporter = client.reporter();
+    let targets = url::create_targets(option);
+    // `--one` が指定されているときは、先読みして余分なデータを取得しないよう1つずつ処理する
+    let concurrency = if option.one { 1 } else { option.concurrency };
+    let mut summary = Summary::default();
+
+    // 最大 `concurrency` 個のデータを同時に取得し、結果は取得対象の順に処理する
+    let mut outcomes = stream::iter(targets)
+        .map(|target| {
+            async move {
+                // 保存先のディレクトリからの相対パス
+                let path = template::render(&option.name_template, &target);
+
+                // 再開モードのときは、保存済みのデータの取得を省略する
+                let outcome = if option.resume && is_saved(&path, option).await {
+                    Outcome::Skipped
+                } else if option.dry {
+                    Outcome::Dry
+                } else {
+                    match client.fetch_target(&target).await {
+                        Ok(content) => Outcome::Fetched(content),
+                        Err(e) => Outcome::Failed(e),
+                    }
+                };
+                (target, path, outcome)
+            }
+        })
+        .buffered(concurrency);
+
+    while let Some((target, path, outcome)) = outcomes.next().await {
+        let (name, url) = (&target.name, &target.url);
+
+        match outcome {
+            Outcome::Skipped => {
+                reporter.progress(&format!("{}: 保存済みのため省略", name));
+                summary.skipped += 1;
+                continue;
+            }
+            Outcome::Dry => reporter.progress(&format!("{} - {}", name, url)),
+            Outcome::Fetched(content) => save(&mut sinks, &target, &path, &content, &mut summary, reporter).await,
+            // 取得に失敗したときはエラーを通知して、次の取得対象へ進む
+            Outcome::Failed(e) => {
+                reporter.error(&format!("{} の取得に失敗: {:#} ({})", name, e, url));
+                summary.failures += 1;
+            }
+        }
+
+        if option.one && !option.dry {
+            break; // `--one` が指定されているときは、最初の1つのみを処理して終了する
+        }
+    }
+
+    for sink in sinks.iter_mut() {
+        sink.finish().await?;
+    }
+
+    Ok(summary)
+}
+
+/// 取得したデータを分類して、すべての保存先へ保存する。保存の失敗はエラーを通知して集計する
+pub(crate) async fn save(
+    sinks: &mut [Box<dyn OutputSink>],
+    target: &Target,
+    path: &Path,
+    content: &str,
+    summary: &mut Summary,
+    reporter: &dyn Reporter,
+) {
+    let name = &target.name;
+    summary.fetched += 1;
+
+    // サーバー例外や空のデータを、正常なデータと区別して扱う
+    let kind = response::classify(content);
+    reporter.progress(&format!("{}: {}", name, &kind));
+    if kind.is_failure() {
+        summary.failures += 1;
+    }
+
+    let meta = Meta { target, path, kind: &kind };
+    for sink in sinks.iter_mut() {
+        if let Err(e) = sink.write(name, &meta, content).await {
+            reporter.error(&format!("{} の保存に失敗: {:#}", name, e));
+            summary.failures += 1;
+        }
+    }
+}
+
+/// 指定したデータが保存済みかどうかを判定する。隔離用のディレクトリへ保存したものも対象とする
+pub(crate) async fn is_saved(path: &Path, option: &ExecutionOption) -> bool {
+    if resume::can_skip(&Path::new(&option.out_dir).join(path), option.refetch_anomalies).await {
+        return true;
+    }
+
+    if option.on_anomaly == AnomalyAction::Quarantine {
+        return resume::can_skip(&Path::new(&option.quarantine_dir).join(path), option.refetch_anomalies).await;
+    }
+
+    false
+}
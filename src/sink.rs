@@ -0,0 +1,513 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{Context, Result, ensure};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use crate::compress::{self, Compression};
+use crate::datetime;
+use crate::execution_option::ExecutionOption;
+use crate::parquet_writer::ParquetWriter;
+use crate::response::ResponseKind;
+use crate::sqlite::SqliteStore;
+use crate::types::{AnomalyAction, GeometryEncoding, SinkKind};
+use crate::url::Target;
+
+/// 保存するデータの情報
+#[derive(Debug, Clone, Copy)]
+pub struct Meta<'a> {
+    /// 取得対象
+    pub target: &'a Target,
+    /// 保存先のディレクトリからの相対パス。`--name-template` から生成したもの
+    pub path: &'a Path,
+    /// レスポンスの分類
+    pub kind: &'a ResponseKind,
+}
+
+impl Meta<'_> {
+    /// 観測日時 (日本標準時)。取得対象の時間コードから求める
+    pub fn observed_at_jst(&self) -> Option<DateTime<FixedOffset>> {
+        datetime::from_time_code(&self.target.time)
+    }
+
+    /// 観測日時 (UTC)。`observed_at_jst` と同じ時刻を UTC で表したもの
+    pub fn observed_at_utc(&self) -> Option<DateTime<Utc>> {
+        self.observed_at_jst().map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// 取得したデータの保存先
+///
+/// データなし・サーバー例外などの正常なデータ以外も渡されるため、必要に応じて `meta.kind` で判定する。
+/// `write` は取得対象の順に呼び出され、すべての取得の完了後に `finish` を1回呼び出す。
+#[async_trait]
+pub trait OutputSink: Send {
+    /// 取得したデータを保存する
+    async fn write(&mut self, name: &str, meta: &Meta<'_>, body: &str) -> Result<()>;
+
+    /// 保存を完了する。バッファリングしているデータの書き出しなどを行う
+    async fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 実行時のオプションで指定した保存先を生成する
+pub fn from_option(option: &ExecutionOption) -> Result<Vec<Box<dyn OutputSink>>> {
+    let mut sinks: Vec<Box<dyn OutputSink>> = vec![];
+
+    for kind in &option.sinks {
+        let sink: Box<dyn OutputSink> = match kind {
+            SinkKind::Dir => {
+                Box::new(DirSink::new(&option.out_dir, option.on_anomaly, &option.quarantine_dir).with_compression(option.compression))
+            }
+            SinkKind::Stdout => Box::new(StdoutSink::new()),
+            SinkKind::Parquet => {
+                let dir = option
+                    .parquet_dir
+                    .as_ref()
+                    .context("Parquet の保存先のディレクトリが指定されていない")?;
+                Box::new(ParquetSink::new(dir, option.parquet_geometry))
+            }
+            SinkKind::Sqlite => {
+                let path = option
+                    .sqlite_path
+                    .as_ref()
+                    .context("SQLite のデータベースのパスが指定されていない")?;
+                Box::new(SqliteSink::new(SqliteStore::open(path)?))
+            }
+        };
+        sinks.push(sink);
+    }
+
+    ensure!(!sinks.is_empty(), "保存先が指定されていない");
+    Ok(sinks)
+}
+
+/// ディレクトリへ1つのデータを1つのファイルとして保存する
+///
+/// 正常なデータ以外は `on_anomaly` に従い、保存しないか隔離用のディレクトリへ保存する。
+/// 圧縮を指定したときは、圧縮形式の拡張子 (`.gz` / `.zst`) を追加したファイルへ保存する。
+/// レスポンスはそのまま保存するため、観測日時 (日本標準時・UTC) はデータのファイル名に `.meta` を追加したファイルへ JSON で保存する。
+pub struct DirSink {
+    out_dir: PathBuf,
+    on_anomaly: AnomalyAction,
+    quarantine_dir: PathBuf,
+    compression: Option<Compression>,
+}
+
+impl DirSink {
+    pub fn new(out_dir: impl Into<PathBuf>, on_anomaly: AnomalyAction, quarantine_dir: impl Into<PathBuf>) -> Self {
+        DirSink {
+            out_dir: out_dir.into(),
+            on_anomaly,
+            quarantine_dir: quarantine_dir.into(),
+            compression: None,
+        }
+    }
+
+    /// 保存するファイルの圧縮を指定する
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+#[async_trait]
+impl OutputSink for DirSink {
+    async fn write(&mut self, _name: &str, meta: &Meta<'_>, body: &str) -> Result<()> {
+        let dir = match (meta.kind.is_anomaly(), self.on_anomaly) {
+            (true, AnomalyAction::Skip) => return Ok(()),
+            (true, AnomalyAction::Quarantine) => &self.quarantine_dir,
+            _ => &self.out_dir,
+        };
+
+        let path = compress::with_extension(&dir.join(meta.path), self.compression.as_ref());
+        match &self.compression {
+            Some(compression) => save_to_file(&path, &compression.compress(body.as_bytes())?).await?,
+            None => save_to_file(&path, body.as_bytes()).await?,
+        }
+
+        let mut meta_path = path.into_os_string();
+        meta_path.push(".meta");
+        save_to_file(Path::new(&meta_path), sidecar(meta).as_bytes()).await
+    }
+}
+
+/// データのファイルと並べて保存する、観測日時などの情報の JSON
+fn sidecar(meta: &Meta<'_>) -> String {
+    let value = serde_json::json!({
+        "name": meta.target.name,
+        "time_code": meta.target.time,
+        "observed_at_jst": meta.observed_at_jst().map(|t| t.to_rfc3339()),
+        "observed_at_utc": meta.observed_at_utc().map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    });
+    format!("{value}\n")
+}
+
+/// データを指定パスへ保存する。
+async fn save_to_file(path: &Path, content: &[u8]) -> Result<()> {
+    // 出力先ディレクトリが存在しないときは、途中のディレクトリも含めて作成する
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    tokio::fs::write(path, content)
+        .await
+        .with_context(|| format!("{} へ保存できない", path.display()))?;
+    Ok(())
+}
+
+/// 複数の `StdoutSink` から共有する出力先
+type SharedWriter = Arc<Mutex<dyn AsyncWrite + Send + Unpin>>;
+
+/// すべての `StdoutSink` で共有する標準出力
+static STDOUT: LazyLock<SharedWriter> = LazyLock::new(|| Arc::new(Mutex::new(tokio::io::stdout())));
+
+/// 標準出力へ、正常なデータを1行に1つずつ出力する
+///
+/// `jq` などへパイプで渡すことを想定し、レスポンスの末尾の改行を除いて改行で区切る。
+/// 同時に実行するジョブの出力が混ざらないよう、すべての `StdoutSink` で1つの標準出力を共有し、
+/// ロックを取得したうえで改行を含む1行を書き込んでフラッシュする。
+pub struct StdoutSink {
+    stdout: SharedWriter,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        StdoutSink { stdout: STDOUT.clone() }
+    }
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl OutputSink for StdoutSink {
+    async fn write(&mut self, _name: &str, meta: &Meta<'_>, body: &str) -> Result<()> {
+        if !matches!(meta.kind, ResponseKind::Data { .. }) {
+            return Ok(());
+        }
+
+        let mut line = Vec::with_capacity(body.len() + 1);
+        line.extend_from_slice(body.trim_end().as_bytes());
+        line.push(b'\n');
+
+        let mut stdout = self.stdout.lock().await;
+        stdout.write_all(&line).await?;
+        stdout.flush().await?;
+        Ok(())
+    }
+}
+
+/// 正常なデータを、パーティション分割した Parquet ファイルへ保存する
+pub struct ParquetSink {
+    writer: Option<ParquetWriter>,
+}
+
+impl ParquetSink {
+    pub fn new(root: impl Into<PathBuf>, geometry: GeometryEncoding) -> Self {
+        ParquetSink {
+            writer: Some(ParquetWriter::new(root, geometry)),
+        }
+    }
+}
+
+#[async_trait]
+impl OutputSink for ParquetSink {
+    async fn write(&mut self, _name: &str, meta: &Meta<'_>, body: &str) -> Result<()> {
+        if let (Some(writer), ResponseKind::Data { .. }) = (&mut self.writer, meta.kind) {
+            writer.add(meta.target, body).context("Parquet に保存できない")?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// 正常なデータを SQLite のデータベースへ保存する
+///
+/// データベースへの書き込みはブロッキング処理のため、`spawn_blocking` のスレッドで行う。
+pub struct SqliteSink {
+    /// 書き込み中はスレッドへ渡すため `None` となる
+    store: Option<SqliteStore>,
+}
+
+impl SqliteSink {
+    pub fn new(store: SqliteStore) -> Self {
+        SqliteSink { store: Some(store) }
+    }
+}
+
+#[async_trait]
+impl OutputSink for SqliteSink {
+    async fn write(&mut self, _name: &str, meta: &Meta<'_>, body: &str) -> Result<()> {
+        if !matches!(meta.kind, ResponseKind::Data { .. }) {
+            return Ok(());
+        }
+
+        let mut store = self
+            .store
+            .take()
+            .context("SQLite のデータベースが以前の保存の失敗により閉じられている")?;
+        let (target, body) = (meta.target.clone(), body.to_string());
+        let (store, result) = tokio::task::spawn_blocking(move || {
+            let result = store.add(&target, &body);
+            (store, result)
+        })
+        .await
+        .context("SQLite への保存が中断された")?;
+        self.store = Some(store);
+
+        result.context("SQLite に保存できない")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod dir_sink_tests {
+    use super::*;
+    use crate::types::{CompressFormat, CounterType, Interval, RoadType};
+
+    fn target() -> Target {
+        Target {
+            time: "202501020300".into(),
+            interval: Interval::H1,
+            road_type: RoadType::Both,
+            counter_type: CounterType::Permanent,
+            name: "H202501020300P".into(),
+            url: String::new(),
+        }
+    }
+
+    async fn write(action: AnomalyAction, kind: ResponseKind) -> (tempfile::TempDir, bool, bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let (out_dir, quarantine_dir) = (dir.path().join("data"), dir.path().join("quarantine"));
+        let mut sink = DirSink::new(&out_dir, action, &quarantine_dir);
+
+        let target = target();
+        let path = Path::new("1h/H202501020300P.json");
+        let meta = Meta {
+            target: &target,
+            path,
+            kind: &kind,
+        };
+        sink.write(&target.name, &meta, "{}").await.unwrap();
+        sink.finish().await.unwrap();
+
+        let saved = out_dir.join(path).exists();
+        let quarantined = quarantine_dir.join(path).exists();
+        (dir, saved, quarantined)
+    }
+
+    #[tokio::test]
+    async fn observed_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let compression = Compression::new(CompressFormat::Gzip, None).unwrap();
+        let mut sink = DirSink::new(dir.path(), AnomalyAction::Save, dir.path()).with_compression(Some(compression));
+
+        let target = target();
+        let meta = Meta {
+            target: &target,
+            path: Path::new("H202501020300P.json"),
+            kind: &ResponseKind::Data { features: 1 },
+        };
+        sink.write(&target.name, &meta, "{}").await.unwrap();
+
+        // 観測日時は、圧縮後のファイル名に `.meta` を追加したファイルへ日本標準時と UTC の両方で保存する
+        let content = std::fs::read_to_string(dir.path().join("H202501020300P.json.gz.meta")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "H202501020300P",
+                "time_code": "202501020300",
+                "observed_at_jst": "2025-01-02T03:00:00+09:00",
+                "observed_at_utc": "2025-01-01T18:00:00Z",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn data() {
+        // 正常なデータは、指定によらず保存先へ保存する
+        for action in [AnomalyAction::Save, AnomalyAction::Skip, AnomalyAction::Quarantine] {
+            let (_dir, saved, quarantined) = write(action, ResponseKind::Data { features: 1 }).await;
+            assert!(saved && !quarantined);
+        }
+    }
+
+    #[tokio::test]
+    async fn compressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let compression = Compression::new(CompressFormat::Gzip, None).unwrap();
+        let mut sink = DirSink::new(dir.path(), AnomalyAction::Save, dir.path()).with_compression(Some(compression));
+
+        let target = target();
+        let meta = Meta {
+            target: &target,
+            path: Path::new("H202501020300P.json"),
+            kind: &ResponseKind::Data { features: 1 },
+        };
+        sink.write(&target.name, &meta, "{}").await.unwrap();
+
+        assert!(!dir.path().join("H202501020300P.json").exists());
+        let path = dir.path().join("H202501020300P.json.gz");
+        assert_eq!(compress::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn anomaly() {
+        let (_dir, saved, quarantined) = write(AnomalyAction::Save, ResponseKind::Empty).await;
+        assert!(saved && !quarantined);
+
+        let (_dir, saved, quarantined) = write(AnomalyAction::Skip, ResponseKind::Empty).await;
+        assert!(!saved && !quarantined);
+
+        let (_dir, saved, quarantined) = write(AnomalyAction::Quarantine, ResponseKind::Exception("x".into())).await;
+        assert!(!saved && quarantined);
+    }
+}
+
+#[cfg(test)]
+mod stdout_sink_tests {
+    use super::*;
+    use crate::types::{CounterType, Interval, RoadType};
+
+    /// 1回の書き込みで最大 `limit` バイトのみ受け付け、書き込みのたびに他のタスクへ切り替える出力先
+    struct Chunked {
+        output: Arc<std::sync::Mutex<Vec<u8>>>,
+        limit: usize,
+        yielded: bool,
+    }
+
+    impl AsyncWrite for Chunked {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            if !self.yielded {
+                self.yielded = true;
+                cx.waker().wake_by_ref();
+                return std::task::Poll::Pending;
+            }
+            self.yielded = false;
+            let len = buf.len().min(self.limit);
+            self.output.lock().unwrap().extend_from_slice(&buf[..len]);
+            std::task::Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    fn target(counter_type: CounterType) -> Target {
+        Target {
+            time: "202501020300".into(),
+            interval: Interval::H1,
+            road_type: RoadType::Both,
+            counter_type,
+            name: String::new(),
+            url: String::new(),
+        }
+    }
+
+    async fn write_lines(mut sink: StdoutSink, counter_type: CounterType, body: String) {
+        let target = target(counter_type);
+        let meta = Meta {
+            target: &target,
+            path: Path::new(""),
+            kind: &ResponseKind::Data { features: 1 },
+        };
+        for _ in 0..10 {
+            sink.write(&target.name, &meta, &format!("{body}\n")).await.unwrap();
+        }
+        sink.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent() {
+        let output = Arc::new(std::sync::Mutex::new(vec![]));
+        let writer: SharedWriter = Arc::new(Mutex::new(Chunked {
+            output: output.clone(),
+            limit: 3,
+            yielded: false,
+        }));
+
+        // 同時に書き込んでも、行の途中で他の出力が混ざらない
+        let (a, b) = ("a".repeat(20), "b".repeat(20));
+        tokio::join!(
+            write_lines(StdoutSink { stdout: writer.clone() }, CounterType::Permanent, a.clone()),
+            write_lines(StdoutSink { stdout: writer.clone() }, CounterType::Cctv, b.clone()),
+        );
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 20);
+        assert!(lines.iter().all(|line| *line == a || *line == b), "{output}");
+    }
+}
+
+#[cfg(test)]
+mod sqlite_sink_tests {
+    use super::*;
+    use crate::types::{CounterType, Interval, RoadType};
+
+    const PERMANENT: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":null,"properties":{"常時観測点コード":3310840,"時間コード":202501020300,"上り・小型交通量":120}}
+    ]}"#;
+
+    #[tokio::test]
+    async fn write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traffic.db");
+        let mut sink = SqliteSink::new(SqliteStore::open(&path).unwrap());
+
+        let target = Target {
+            time: "202501020300".into(),
+            interval: Interval::H1,
+            road_type: RoadType::Both,
+            counter_type: CounterType::Permanent,
+            name: "H202501020300P".into(),
+            url: String::new(),
+        };
+        let path_in_dir = Path::new("H202501020300P.json");
+        for kind in [
+            ResponseKind::Data { features: 1 },
+            ResponseKind::Empty,
+            ResponseKind::Data { features: 1 },
+        ] {
+            let meta = Meta {
+                target: &target,
+                path: path_in_dir,
+                kind: &kind,
+            };
+            sink.write(&target.name, &meta, PERMANENT).await.unwrap();
+        }
+        sink.finish().await.unwrap();
+        drop(sink);
+
+        // 別のスレッドで書き込んだデータが保存され、同じデータの2回目の保存は上書きとなる
+        let count: i64 = rusqlite::Connection::open(&path)
+            .unwrap()
+            .query_row("SELECT count(*) FROM measurements", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::Value;
+
+/// WKT の最大文字数
+///
+/// 多角形は URL のクエリとして送信するため、長すぎるとサーバーに拒否される。
+/// パーセントエンコード後の長さも考慮し、余裕を持った値とする。
+const MAX_WKT_LENGTH: usize = 4000;
+
+/// 座標の小数点以下の桁数。約 0.1m の精度であり、これ以上の精度は URL を長くするだけのため丸める
+const COORDINATE_DECIMALS: i32 = 6;
+
+/// 経度・緯度による矩形範囲
+#[derive(Debug, Clone, PartialEq)]
+pub struct BBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// `minLon,minLat,maxLon,maxLat` 形式の文字列から矩形範囲を生成する
+pub fn parse_bbox(input: &str) -> Result<BBox> {
+    let values = input
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("矩形範囲 {} に数値でない値が含まれている", input))?;
+
+    let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+        bail!("矩形範囲 {} は minLon,minLat,maxLon,maxLat の4つの値で指定する必要がある", input);
+    };
+
+    ensure!(
+        [min_lon, max_lon].iter().all(|v| (-180.0..=180.0).contains(v)) && [min_lat, max_lat].iter().all(|v| (-90.0..=90.0).contains(v)),
+        "矩形範囲 {} の経度は -180〜180、緯度は -90〜90 の範囲で指定する必要がある",
+        input
+    );
+    ensure!(
+        min_lon < max_lon && min_lat < max_lat,
+        "矩形範囲 {} の最小値は最大値より小さい必要がある",
+        input
+    );
+
+    Ok(BBox {
+        min_lon,
+        min_lat,
+        max_lon,
+        max_lat,
+    })
+}
+
+/// GeoJSON のファイルを読み込み、含まれる多角形を WKT に変換する
+pub fn read_polygon_file(path: &str) -> Result<String> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("多角形のファイル {} を読み込めない", path))?;
+    let value: Value = serde_json::from_str(&text).with_context(|| format!("{} を GeoJSON として解釈不能", path))?;
+
+    geojson_to_wkt(&value).with_context(|| format!("{} の多角形を変換できない", path))
+}
+
+/// GeoJSON に含まれる多角形を WKT に変換する
+/// - Polygon / MultiPolygon のジオメトリ、それらを持つ Feature / FeatureCollection に対応する
+/// - 多角形が1つのときは POLYGON、複数のときは MULTIPOLYGON とする
+/// - WKT が長すぎるときはエラー
+pub fn geojson_to_wkt(value: &Value) -> Result<String> {
+    let mut polygons = vec![];
+    collect_polygons(value, &mut polygons)?;
+
+    let wkt = match &polygons[..] {
+        [] => bail!("Polygon または MultiPolygon が含まれていない"),
+        [polygon] => format!("POLYGON{}", polygon_wkt(polygon)),
+        _ => {
+            let list: Vec<String> = polygons.iter().map(polygon_wkt).collect();
+            format!("MULTIPOLYGON({})", list.join(","))
+        }
+    };
+
+    ensure!(
+        wkt.len() <= MAX_WKT_LENGTH,
+        "多角形が大きすぎる (WKT で {} 文字、上限 {} 文字)。頂点数を減らすよう単純化するか、--bbox を使用する必要がある",
+        wkt.len(),
+        MAX_WKT_LENGTH
+    );
+
+    Ok(wkt)
+}
+
+/// 多角形。外周と穴のリングのリストで、各リングは経度・緯度の座標のリスト
+type Polygon = Vec<Vec<(f64, f64)>>;
+
+/// GeoJSON のオブジェクトから多角形を収集する
+fn collect_polygons(value: &Value, output: &mut Vec<Polygon>) -> Result<()> {
+    match value.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => {
+            let features = value.get("features").and_then(Value::as_array).context("features が存在しない")?;
+            for feature in features {
+                collect_polygons(feature, output)?;
+            }
+        }
+        Some("Feature") => {
+            // ジオメトリが null の Feature は無視する
+            if let Some(geometry) = value.get("geometry").filter(|g| !g.is_null()) {
+                collect_polygons(geometry, output)?;
+            }
+        }
+        Some("Polygon") => output.push(parse_polygon(coordinates(value)?)?),
+        Some("MultiPolygon") => {
+            let list = coordinates(value)?.as_array().context("MultiPolygon の座標が配列でない")?;
+            for polygon in list {
+                output.push(parse_polygon(polygon)?);
+            }
+        }
+        Some(other) => bail!("{} には対応していない。Polygon または MultiPolygon を指定する必要がある", other),
+        None => bail!("GeoJSON の type が存在しない"),
+    }
+
+    Ok(())
+}
+
+fn coordinates(value: &Value) -> Result<&Value> {
+    value.get("coordinates").context("coordinates が存在しない")
+}
+
+/// 多角形の座標を解釈する。リングは4点以上で、始点と終点が一致する必要がある
+fn parse_polygon(value: &Value) -> Result<Polygon> {
+    let rings = value.as_array().context("多角形の座標が配列でない")?;
+    ensure!(!rings.is_empty(), "多角形に外周が存在しない");
+
+    rings
+        .iter()
+        .map(|ring| {
+            let points = ring
+                .as_array()
+                .context("リングの座標が配列でない")?
+                .iter()
+                .map(parse_point)
+                .collect::<Result<Vec<_>>>()?;
+
+            ensure!(points.len() >= 4, "リングは4点以上で構成する必要がある");
+            ensure!(points.first() == points.last(), "リングの始点と終点が一致していない");
+            Ok(points)
+        })
+        .collect()
+}
+
+/// `[経度, 緯度]` の座標を解釈する。3次元目以降は無視する
+fn parse_point(point: &Value) -> Result<(f64, f64)> {
+    let lon = point.get(0).and_then(Value::as_f64);
+    let lat = point.get(1).and_then(Value::as_f64);
+
+    match (lon, lat) {
+        (Some(lon), Some(lat)) => Ok((lon, lat)),
+        _ => bail!("座標 {} を経度・緯度として解釈不能", point),
+    }
+}
+
+/// 多角形を WKT の `((x y, ...), (...))` 部分に変換する
+fn polygon_wkt(polygon: &Polygon) -> String {
+    let rings: Vec<String> = polygon
+        .iter()
+        .map(|ring| {
+            let points: Vec<String> = ring.iter().map(|(lon, lat)| format!("{} {}", round(*lon), round(*lat))).collect();
+            format!("({})", points.join(","))
+        })
+        .collect();
+
+    format!("({})", rings.join(","))
+}
+
+fn round(value: f64) -> f64 {
+    let scale = 10f64.powi(COORDINATE_DECIMALS);
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod parse_bbox_tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        assert_eq!(
+            parse_bbox("139.5,35.5,140.0,35.9").unwrap(),
+            BBox {
+                min_lon: 139.5,
+                min_lat: 35.5,
+                max_lon: 140.0,
+                max_lat: 35.9,
+            }
+        );
+        assert!(parse_bbox(" 139.5, 35.5, 140, 35.9 ").is_ok());
+    }
+
+    #[test]
+    fn invalid() {
+        // 値の数が不正
+        assert!(parse_bbox("139.5,35.5,140.0").is_err());
+        assert!(parse_bbox("139.5,35.5,140.0,35.9,1").is_err());
+        // 数値でない
+        assert!(parse_bbox("139.5,35.5,140.0,abc").is_err());
+        // 範囲外
+        assert!(parse_bbox("139.5,35.5,190.0,35.9").is_err());
+        assert!(parse_bbox("139.5,-95,140.0,35.9").is_err());
+        // 最小値と最大値が逆
+        assert!(parse_bbox("140.0,35.5,139.5,35.9").is_err());
+    }
+}
+
+#[cfg(test)]
+mod geojson_to_wkt_tests {
+    use super::*;
+
+    fn square(lon: f64, lat: f64) -> Value {
+        serde_json::json!([[[lon, lat], [lon + 1.0, lat], [lon + 1.0, lat + 1.0], [lon, lat + 1.0], [lon, lat]]])
+    }
+
+    #[test]
+    fn polygon() {
+        let value = serde_json::json!({"type": "Polygon", "coordinates": square(139.0, 35.0)});
+
+        assert_eq!(geojson_to_wkt(&value).unwrap(), "POLYGON((139 35,140 35,140 36,139 36,139 35))");
+    }
+
+    #[test]
+    fn feature_collection() {
+        let value = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": square(139.0, 35.0)}},
+                {"type": "Feature", "properties": {}, "geometry": {"type": "MultiPolygon", "coordinates": [square(135.0, 34.0)]}},
+                {"type": "Feature", "properties": {}, "geometry": null}
+            ]
+        });
+
+        assert_eq!(
+            geojson_to_wkt(&value).unwrap(),
+            "MULTIPOLYGON(((139 35,140 35,140 36,139 36,139 35)),((135 34,136 34,136 35,135 35,135 34)))"
+        );
+    }
+
+    #[test]
+    fn round_coordinates() {
+        let value = serde_json::json!({"type": "Polygon", "coordinates": [[[139.123456789, 35.0], [140.0, 35.0], [140.0, 36.0], [139.123456789, 35.0]]]});
+
+        assert_eq!(
+            geojson_to_wkt(&value).unwrap(),
+            "POLYGON((139.123457 35,140 35,140 36,139.123457 35))"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        // 多角形でない
+        assert!(geojson_to_wkt(&serde_json::json!({"type": "Point", "coordinates": [139.0, 35.0]})).is_err());
+        // 多角形が含まれていない
+        assert!(geojson_to_wkt(&serde_json::json!({"type": "FeatureCollection", "features": []})).is_err());
+        // リングが閉じていない
+        let open = serde_json::json!([[[139.0, 35.0], [140.0, 35.0], [140.0, 36.0], [139.0, 36.0]]]);
+        assert!(geojson_to_wkt(&serde_json::json!({"type": "Polygon", "coordinates": open})).is_err());
+    }
+
+    #[test]
+    fn too_large() {
+        // 頂点数が多すぎる多角形
+        let mut ring: Vec<Value> = (0..1000)
+            .map(|i| serde_json::json!([139.0 + i as f64 * 0.000123, 35.123456]))
+            .collect();
+        ring.push(ring[0].clone());
+        let value = serde_json::json!({"type": "Polygon", "coordinates": [ring]});
+
+        let message = format!("{:#}", geojson_to_wkt(&value).unwrap_err());
+        assert!(message.contains("多角形が大きすぎる"));
+    }
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, params_from_iter};
+
+use crate::datetime;
+use crate::model::{self, CctvProperties, FeatureCollection, Observation, PermanentProperties};
+use crate::types::{CounterType, Interval};
+use crate::url::Target;
+
+/// テーブルの定義
+///
+/// - `stations`: 観測地点。名称はレスポンスに含まれないため、利用者が設定するための列として用意し、更新時には変更しない
+/// - `measurements`: 観測値。観測地点・時間コード・取得間隔・観測機器の組み合わせごとに1行とする。
+///   観測日時は、日本標準時と UTC の両方を RFC 3339 形式で保存する
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS stations (
+    code TEXT PRIMARY KEY,
+    name TEXT,
+    road_type TEXT,
+    lon REAL,
+    lat REAL,
+    geometry TEXT
+);
+CREATE TABLE IF NOT EXISTS measurements (
+    station TEXT NOT NULL REFERENCES stations (code),
+    time_code TEXT NOT NULL,
+    interval TEXT NOT NULL,
+    counter TEXT NOT NULL,
+    observed_at_jst TEXT,
+    observed_at_utc TEXT,
+    up_small INTEGER,
+    up_large INTEGER,
+    up_unknown INTEGER,
+    down_small INTEGER,
+    down_large INTEGER,
+    down_unknown INTEGER,
+    up_power_failure INTEGER,
+    up_loop_error INTEGER,
+    up_ultrasonic_error INTEGER,
+    up_missing INTEGER,
+    down_power_failure INTEGER,
+    down_loop_error INTEGER,
+    down_ultrasonic_error INTEGER,
+    down_missing INTEGER,
+    PRIMARY KEY (station, time_code, interval, counter)
+);
+CREATE INDEX IF NOT EXISTS measurements_time_code ON measurements (time_code);
+";
+
+/// 観測地点の登録・更新。位置などが取得できなかったときは、登録済みの値を残す
+const UPSERT_STATION: &str = "
+INSERT INTO stations (code, road_type, lon, lat, geometry) VALUES (?1, ?2, ?3, ?4, ?5)
+ON CONFLICT (code) DO UPDATE SET
+    road_type = coalesce(excluded.road_type, road_type),
+    lon = coalesce(excluded.lon, lon),
+    lat = coalesce(excluded.lat, lat),
+    geometry = coalesce(excluded.geometry, geometry)
+";
+
+/// 品質フラグの列。CCTVトラカンは欠測のみのため、それ以外は null とする
+const FLAGS: [&str; 8] = [
+    "up_power_failure",
+    "up_loop_error",
+    "up_ultrasonic_error",
+    "up_missing",
+    "down_power_failure",
+    "down_loop_error",
+    "down_ultrasonic_error",
+    "down_missing",
+];
+
+/// 取得したデータを SQLite のデータベースへ保存する
+///
+/// 同じ観測地点・時間コード・取得間隔・観測機器のデータは上書きするため、同じデータを再取得しても重複しない。
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// データベースを開く。ファイルやテーブルが存在しないときは作成する
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path).with_context(|| format!("データベース {} を開けない", path.display()))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("データベース {} にテーブルを作成できない", path.display()))?;
+
+        Ok(SqliteStore { conn })
+    }
+
+    /// 取得したレスポンスの観測値を保存する。保存した行数を返す
+    ///
+    /// レスポンスは `response::classify` で正常なデータと判定済みであること。
+    pub fn add(&mut self, target: &Target, body: &str) -> Result<usize> {
+        match target.counter_type {
+            CounterType::Permanent => self.upsert::<PermanentProperties>(target, &model::parse_permanent(body)?),
+            CounterType::Cctv => self.upsert::<CctvProperties>(target, &model::parse_cctv(body)?),
+        }
+    }
+
+    /// 1つのレスポンスの観測値を、1つのトランザクションで保存する
+    fn upsert<P: Observation>(&mut self, target: &Target, collection: &FeatureCollection<P>) -> Result<usize> {
+        let interval = match target.interval {
+            Interval::H1 => "1h",
+            Interval::M5 => "5m",
+        };
+        let counter = match P::COUNTER_TYPE {
+            CounterType::Permanent => "permanent",
+            CounterType::Cctv => "cctv",
+        };
+
+        let columns: Vec<&str> = ["station", "time_code", "interval", "counter", "observed_at_jst", "observed_at_utc"]
+            .into_iter()
+            .chain(model::COUNTS)
+            .chain(FLAGS)
+            .collect();
+        let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        let updates: Vec<String> = columns[4..].iter().map(|c| format!("{c} = excluded.{c}")).collect();
+        let upsert_measurement = format!(
+            "INSERT INTO measurements ({}) VALUES ({}) ON CONFLICT (station, time_code, interval, counter) DO UPDATE SET {}",
+            columns.join(", "),
+            placeholders.join(", "),
+            updates.join(", ")
+        );
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut station = tx.prepare_cached(UPSERT_STATION)?;
+            let mut measurement = tx.prepare_cached(&upsert_measurement)?;
+
+            for feature in &collection.features {
+                let p = &feature.properties;
+                let lon_lat = feature.geometry.as_ref().and_then(|g| g.lon_lat());
+                let geometry = feature.geometry.as_ref().map(serde_json::to_string).transpose()?;
+                station.execute((
+                    p.station_code(),
+                    p.road_type(),
+                    lon_lat.map(|(lon, _)| lon),
+                    lon_lat.map(|(_, lat)| lat),
+                    geometry,
+                ))?;
+
+                let flags = p.flags();
+                let observed_at = datetime::from_time_code(p.time_code());
+                let mut values = vec![
+                    Value::from(p.station_code().to_string()),
+                    Value::from(p.time_code().to_string()),
+                    Value::from(interval.to_string()),
+                    Value::from(counter.to_string()),
+                    Value::from(observed_at.map(|t| t.to_rfc3339())),
+                    Value::from(observed_at.map(|t| t.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true))),
+                ];
+                values.extend(p.counts().map(Value::from));
+                values.extend(FLAGS.iter().map(|name| {
+                    let flag = P::FLAGS.iter().position(|f| f == name).and_then(|i| flags[i]);
+                    Value::from(flag)
+                }));
+                measurement.execute(params_from_iter(values))?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(collection.features.len())
+    }
+}
+
+#[cfg(test)]
+mod sqlite_store_tests {
+    use super::*;
+    use crate::types::RoadType;
+
+    const PERMANENT: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[139.7,35.6]},
+         "properties":{"常時観測点コード":3310840,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":120,"上り・停電":0,"上り・欠測":1}},
+        {"type":"Feature","geometry":null,
+         "properties":{"常時観測点コード":3310850,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":null}}
+    ]}"#;
+
+    const CCTV: &str = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","geometry":{"type":"Point","coordinates":[139.7,35.6]},
+         "properties":{"常時観測点コード":3310840,"時間コード":202501020300,"道路種別":"1","上り・小型交通量":100,"下り・欠測":0}}
+    ]}"#;
+
+    fn target(interval: Interval, counter_type: CounterType) -> Target {
+        Target {
+            time: "202501020300".into(),
+            interval,
+            road_type: RoadType::Both,
+            counter_type,
+            name: String::new(),
+            url: String::new(),
+        }
+    }
+
+    fn count(store: &SqliteStore, table: &str) -> i64 {
+        store
+            .conn
+            .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn upsert() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("traffic.db")).unwrap();
+
+        assert_eq!(store.add(&target(Interval::H1, CounterType::Permanent), PERMANENT).unwrap(), 2);
+        assert_eq!(count(&store, "stations"), 2);
+        assert_eq!(count(&store, "measurements"), 2);
+
+        let row: (Option<i64>, Option<i64>, Option<i64>, Option<i64>) = store
+            .conn
+            .query_row(
+                "SELECT up_small, up_power_failure, up_missing, down_missing FROM measurements WHERE station = '3310840'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(row, (Some(120), Some(0), Some(1), None));
+
+        let (lon, lat): (f64, f64) = store
+            .conn
+            .query_row("SELECT lon, lat FROM stations WHERE code = '3310840'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((lon, lat), (139.7, 35.6));
+    }
+
+    #[test]
+    fn observed_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("traffic.db")).unwrap();
+        store.add(&target(Interval::H1, CounterType::Permanent), PERMANENT).unwrap();
+
+        let (jst, utc): (String, String) = store
+            .conn
+            .query_row(
+                "SELECT observed_at_jst, observed_at_utc FROM measurements WHERE station = '3310840'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(jst, "2025-01-02T03:00:00+09:00");
+        assert_eq!(utc, "2025-01-01T18:00:00Z");
+    }
+
+    #[test]
+    fn idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traffic.db");
+
+        // 再取得したデータは上書きされ、重複しない
+        for _ in 0..2 {
+            let mut store = SqliteStore::open(&path).unwrap();
+            store.add(&target(Interval::H1, CounterType::Permanent), PERMANENT).unwrap();
+        }
+        let mut store = SqliteStore::open(&path).unwrap();
+        assert_eq!(count(&store, "measurements"), 2);
+
+        // 取得間隔・観測機器が異なるデータは別の行になり、観測地点は共有する
+        store.add(&target(Interval::M5, CounterType::Permanent), PERMANENT).unwrap();
+        store.add(&target(Interval::H1, CounterType::Cctv), CCTV).unwrap();
+        assert_eq!(count(&store, "measurements"), 5);
+        assert_eq!(count(&store, "stations"), 2);
+    }
+
+    #[test]
+    fn keep_station_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("traffic.db")).unwrap();
+        store.add(&target(Interval::H1, CounterType::Permanent), PERMANENT).unwrap();
+        store
+            .conn
+            .execute("UPDATE stations SET name = '東京' WHERE code = '3310840'", [])
+            .unwrap();
+
+        // 位置のないデータで更新しても、登録済みの位置と名称は変更されない
+        let body = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","geometry":null,"properties":{"常時観測点コード":3310840,"時間コード":202501020400}}
+        ]}"#;
+        store.add(&target(Interval::H1, CounterType::Permanent), body).unwrap();
+
+        let (name, lon, road_type): (String, f64, String) = store
+            .conn
+            .query_row("SELECT name, lon, road_type FROM stations WHERE code = '3310840'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((name.as_str(), lon, road_type.as_str()), ("東京", 139.7, "1"));
+    }
+
+    #[test]
+    fn invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path().join("traffic.db")).unwrap();
+
+        assert!(
+            store
+                .add(&target(Interval::H1, CounterType::Permanent), r#"{"features":[{}]}"#)
+                .is_err()
+        );
+        assert_eq!(count(&store, "measurements"), 0);
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+use crate::types::{CounterType, Interval, RoadType};
+use crate::url::Target;
+
+/// デフォルトの保存先パスのテンプレート。従来のファイル名 (例: `H202501020300P.json`) になる
+pub const DEFAULT_TEMPLATE: &str = "{name}.json";
+
+/// テンプレート中で使用可能なプレースホルダー
+const PLACEHOLDERS: [&str; 10] = ["name", "interval", "counter", "road", "time", "yyyy", "mm", "dd", "hh", "mi"];
+
+/// テンプレートを検証する
+/// - 未知のプレースホルダーや、閉じられていない `{` を含む場合はエラー
+/// - 絶対パスや `..` を含むなど、保存先のディレクトリの外を指す場合はエラー
+pub fn validate(template: &str) -> Result<()> {
+    for key in placeholders(template)? {
+        if !PLACEHOLDERS.contains(&key) {
+            bail!("テンプレート {} に未知のプレースホルダー {{{}}} が含まれている", template, key);
+        }
+    }
+
+    let path = Path::new(template);
+    if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        bail!("テンプレート {} は保存先のディレクトリからの相対パスで指定する必要がある", template);
+    }
+    if template.is_empty() || template.ends_with('/') || template.ends_with('\\') {
+        bail!("テンプレート {} はファイル名で終わる必要がある", template);
+    }
+
+    Ok(())
+}
+
+/// テンプレートのプレースホルダーを取得対象の値で置き換え、保存先のディレクトリからの相対パスを生成する
+///
+/// テンプレートは `validate` で検証済みであること。
+pub fn render(template: &str, target: &Target) -> PathBuf {
+    let t = &target.time;
+    let mut output = template.to_string();
+
+    for key in PLACEHOLDERS {
+        let value = match key {
+            "name" => target.name.as_str(),
+            "interval" => match target.interval {
+                Interval::H1 => "1h",
+                Interval::M5 => "5m",
+            },
+            "counter" => match target.counter_type {
+                CounterType::Permanent => "permanent",
+                CounterType::Cctv => "cctv",
+            },
+            "road" => match target.road_type {
+                RoadType::Highway => "highway",
+                RoadType::Normal => "normal",
+                RoadType::Both => "both",
+            },
+            "time" => t,
+            "yyyy" => &t[0..4],
+            "mm" => &t[4..6],
+            "dd" => &t[6..8],
+            "hh" => &t[8..10],
+            "mi" => &t[10..12],
+            _ => unreachable!(),
+        };
+        output = output.replace(&format!("{{{}}}", key), value);
+    }
+
+    PathBuf::from(output)
+}
+
+/// テンプレートに含まれるプレースホルダー名のリストを取得する
+fn placeholders(template: &str) -> Result<Vec<&str>> {
+    let mut output = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            bail!("テンプレート {} に閉じられていない {{ が含まれている", template);
+        };
+        output.push(&rest[start + 1..start + end]);
+        rest = &rest[start + end + 1..];
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::*;
+
+    fn target() -> Target {
+        Target {
+            time: "202501020305".into(),
+            interval: Interval::M5,
+            road_type: RoadType::Highway,
+            counter_type: CounterType::Cctv,
+            name: "M202501020305C".into(),
+            url: "".into(),
+        }
+    }
+
+    #[test]
+    fn default_template() {
+        assert!(validate(DEFAULT_TEMPLATE).is_ok());
+        assert_eq!(render(DEFAULT_TEMPLATE, &target()), PathBuf::from("M202501020305C.json"));
+    }
+
+    #[test]
+    fn nested() {
+        let template = "{interval}/{yyyy}/{mm}/{dd}/{counter}_{time}.json";
+
+        assert!(validate(template).is_ok());
+        assert_eq!(render(template, &target()), PathBuf::from("5m/2025/01/02/cctv_202501020305.json"));
+    }
+
+    #[test]
+    fn all_placeholders() {
+        let template = "{road}-{hh}{mi}-{name}";
+
+        assert!(validate(template).is_ok());
+        assert_eq!(render(template, &target()), PathBuf::from("highway-0305-M202501020305C"));
+    }
+
+    #[test]
+    fn invalid() {
+        // 未知のプレースホルダー
+        assert!(validate("{unknown}.json").is_err());
+        // 閉じられていない括弧
+        assert!(validate("{name.json").is_err());
+        // 保存先のディレクトリの外を指すパス
+        assert!(validate("/tmp/{name}.json").is_err());
+        assert!(validate("../{name}.json").is_err());
+        assert!(validate("{yyyy}/../../{name}.json").is_err());
+        // ファイル名がない
+        assert!(validate("{yyyy}/").is_err());
+        assert!(validate("").is_err());
+    }
+}
@@ -1,10 +1,39 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 
 /// コマンド実行時のオプション定義
+///
+/// サブコマンドを指定しないときは、データを取得する。
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// YYYYMMDDフォーマットの日付
-    pub date: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+}
+
+/// サブコマンド
+#[derive(Subcommand)]
+pub enum Command {
+    /// 保存済みのデータを CSV に変換する
+    Convert(ConvertArgs),
+    /// WFS のレイヤーと属性の一覧を表示する
+    Describe(DescribeArgs),
+    /// 公開された最新の時間帯のデータを、継続して取得する
+    #[command(mut_arg("date", |arg| arg.required(false).hide(true)))]
+    Watch(Box<WatchArgs>),
+    /// ジョブファイルに記述した複数の取得を実行する
+    Run(RunArgs),
+}
+
+/// データ取得時のオプション定義
+#[derive(Args)]
+pub struct FetchArgs {
+    /// YYYYMMDD(HH(MM))フォーマットの日時。`2025-01-02T03:00` のような ISO 8601 形式や、`today` / `yesterday` / `-3d` / `-6h` / `last-hour` / `last-week` の相対的な指定(日本標準時)も可能。末尾に `Z` / `+HH:MM` を付けると、そのタイムゾーンの日時として日本標準時に変換する。`開始..終了` または `開始-終了` で範囲を指定可能
+    #[arg(required = true)]
+    pub date: Option<String>,
 
     /// 取得間隔：1時間ごとのデータを取得 (デフォルト)
     #[arg(long = "1h")]
@@ -13,6 +42,22 @@ pub struct Cli {
     #[arg(long = "5m")]
     pub m5: bool,
 
+    /// 曜日：平日 (月〜金曜日) のみを取得対象とする。祝日も含むため、除くときは --exclude-holidays を併せて指定する
+    #[arg(long = "weekdays", conflicts_with = "weekends")]
+    pub weekdays: bool,
+    /// 曜日：土日のみを取得対象とする
+    #[arg(long = "weekends")]
+    pub weekends: bool,
+    /// 時間帯：`7-10,17-20` のように指定した時間帯のみを取得対象とする。`7-10` は 7:00〜9:55 の時間帯
+    #[arg(long = "hours", value_name = "HOURS")]
+    pub hours: Option<String>,
+    /// 祝日：国民の祝日・振替休日・国民の休日を取得対象から除く
+    #[arg(long = "exclude-holidays", conflicts_with = "only_holidays")]
+    pub exclude_holidays: bool,
+    /// 祝日：国民の祝日・振替休日・国民の休日のみを取得対象とする
+    #[arg(long = "only-holidays")]
+    pub only_holidays: bool,
+
     /// 観測機器：常設トラカンのみを取得対象とする
     #[arg(long = "permanent")]
     pub permanent: bool,
@@ -27,6 +72,89 @@ pub struct Cli {
     #[arg(long = "normal")]
     pub normal: bool,
 
+    /// 観測地点：指定した常時観測点コードのみを取得対象とする。複数回指定可能
+    #[arg(long = "station", value_name = "CODE")]
+    pub stations: Vec<String>,
+    /// 観測地点：ファイルに列挙した常時観測点コードのみを取得対象とする。1行に1つ、または `,` 区切りで記述する
+    #[arg(long = "stations-file", value_name = "PATH")]
+    pub stations_file: Option<String>,
+
+    /// 観測地点：経度・緯度の矩形範囲 `minLon,minLat,maxLon,maxLat` に含まれる地点のみを取得対象とする
+    #[arg(long = "bbox", value_name = "MINLON,MINLAT,MAXLON,MAXLAT", allow_hyphen_values = true)]
+    pub bbox: Option<String>,
+    /// 観測地点：GeoJSON ファイルの多角形 (Polygon / MultiPolygon) に含まれる地点のみを取得対象とする
+    #[arg(long = "within", value_name = "PATH")]
+    pub within: Option<String>,
+
+    /// 同時に取得するデータの最大数 (デフォルト: 1)
+    #[arg(long = "concurrency", value_name = "N")]
+    pub concurrency: Option<usize>,
+    /// 1秒あたりの最大リクエスト数。同時取得時も全体で共有する (デフォルト: 1)
+    #[arg(long = "rate", value_name = "RPS")]
+    pub rate: Option<f64>,
+
+    /// 1回のリクエストで取得する地物の最大数。超えるときはページ分割して取得し、1つのファイルにまとめる。0 でページ分割しない (デフォルト: 10000)
+    #[arg(long = "page-size", value_name = "N")]
+    pub page_size: Option<usize>,
+
+    /// 取得失敗時の最大再試行回数 (デフォルト: 3)
+    #[arg(long = "retries", value_name = "N")]
+    pub retries: Option<u32>,
+    /// 取得失敗時の最初の再試行までの待ち時間(秒)。再試行ごとに2倍になる (デフォルト: 1)
+    #[arg(long = "retry-wait", value_name = "SECONDS")]
+    pub retry_wait: Option<f64>,
+
+    #[command(flatten)]
+    pub endpoint: EndpointArgs,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// 保存先のディレクトリ (デフォルト: data)
+    #[arg(long = "out-dir", value_name = "DIR")]
+    pub out_dir: Option<String>,
+    /// 保存先のディレクトリからの相対パスのテンプレート (デフォルト: {name}.json)
+    ///
+    /// 使用可能なプレースホルダー: {name} {interval} {counter} {road} {time} {yyyy} {mm} {dd} {hh} {mi}
+    #[arg(long = "name-template", value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
+    /// 取得したデータの保存先。複数指定可能 (デフォルト: dir)
+    ///
+    /// `--parquet` / `--sqlite` を指定したときは、それぞれの保存先を自動的に追加する。
+    #[arg(long = "sink", value_name = "SINK", value_delimiter = ',')]
+    pub sinks: Vec<SinkKind>,
+    /// 取得したデータを、指定したディレクトリへ Parquet 形式で保存する
+    #[arg(long = "parquet", value_name = "DIR")]
+    pub parquet: Option<String>,
+    /// Parquet に保存する観測地点の位置の形式 (デフォルト: lonlat)
+    #[arg(long = "parquet-geometry", value_name = "FORMAT", requires = "parquet")]
+    pub parquet_geometry: Option<GeometryEncoding>,
+    /// 取得したデータを、指定した SQLite のデータベースへ保存する
+    #[arg(long = "sqlite", value_name = "PATH")]
+    pub sqlite: Option<String>,
+
+    /// `--out-dir` へ保存するファイルの圧縮形式。拡張子 `.gz` / `.zst` を追加する (デフォルト: 圧縮しない)
+    #[arg(long = "compress", value_name = "FORMAT")]
+    pub compress: Option<CompressFormat>,
+    /// 圧縮レベル。gzip は 0〜9、zstd は 1〜22 (デフォルト: gzip 6, zstd 3)
+    #[arg(long = "compress-level", value_name = "N", requires = "compress")]
+    pub compress_level: Option<u32>,
+
+    /// データなし・サーバー例外・不正なレスポンスを取得したときの扱い (デフォルト: save)
+    #[arg(long = "on-anomaly", value_name = "ACTION")]
+    pub on_anomaly: Option<AnomalyAction>,
+    /// `--on-anomaly quarantine` 指定時の保存先ディレクトリ (デフォルト: quarantine)
+    #[arg(long = "quarantine-dir", value_name = "DIR")]
+    pub quarantine_dir: Option<String>,
+
+    /// 保存済みの正常なファイルがあるデータの取得を省略する
+    #[arg(long = "resume", visible_alias = "skip-existing")]
+    pub resume: bool,
+    /// `--resume` 指定時、データなし・サーバー例外として保存済みのデータも再取得する
+    #[arg(long = "refetch-anomalies", requires = "resume")]
+    pub refetch_anomalies: bool,
+
     /// 先頭の1つのデータのみ取得・保存を実行する
     #[arg(long = "one")]
     pub one: bool,
@@ -35,17 +163,155 @@ pub struct Cli {
     pub dry: bool,
 }
 
+/// `convert` サブコマンドのオプション定義
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// 変換するファイル、またはファイルを含むディレクトリ。ディレクトリはサブディレクトリを含めて `.json` / `.json.gz` / `.json.zst` ファイルを対象とする
+    #[arg(required = true, value_name = "PATH")]
+    pub inputs: Vec<String>,
+
+    /// 出力先の CSV ファイル。省略時は標準出力へ出力する
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<String>,
+
+    /// 観測機器。省略時はファイル名 (H202501020300P.json など)、または地物の属性から判定する
+    #[arg(long = "counter", value_name = "TYPE")]
+    pub counter: Option<CounterType>,
+
+    /// 出力する列。`,` 区切りで指定する (デフォルト: すべての列)
+    #[arg(long = "columns", value_name = "COLUMNS", value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    /// 出力する CSV の文字コード (デフォルト: utf8)
+    #[arg(long = "encoding", value_name = "ENCODING")]
+    pub encoding: Option<CsvEncoding>,
+}
+
+/// `describe` サブコマンドのオプション定義
+#[derive(Args)]
+pub struct DescribeArgs {
+    /// 属性を表示するレイヤー。省略時はデータの取得に使用するレイヤーを対象とする
+    #[arg(value_name = "LAYER")]
+    pub layers: Vec<String>,
+
+    /// 出力形式 (デフォルト: table)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<DescribeFormat>,
+
+    #[command(flatten)]
+    pub endpoint: EndpointArgs,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+/// `watch` サブコマンドのオプション定義
+///
+/// 日時指定以外は、データ取得時と同じオプションを指定できる。
+#[derive(Args)]
+pub struct WatchArgs {
+    /// 時間帯の終了から、データの公開の確認を始めるまでの待ち時間(分) (デフォルト: 10)
+    #[arg(long = "delay", value_name = "MINUTES")]
+    pub delay: Option<u32>,
+    /// データが公開されていないときに、再確認するまでの間隔(秒) (デフォルト: 60)
+    #[arg(long = "poll-interval", value_name = "SECONDS")]
+    pub poll_interval: Option<u64>,
+    /// 公開の確認を始めてから、データの公開を待つ最大時間(分)。超えたときは保存せず、次回に再取得する (デフォルト: 60)
+    #[arg(long = "max-wait", value_name = "MINUTES")]
+    pub max_wait: Option<u32>,
+    /// 停止中に取得できなかった時間帯を、遡って取得する最大期間(時間) (デフォルト: 24)
+    #[arg(long = "max-backfill", value_name = "HOURS")]
+    pub max_backfill: Option<u32>,
+    /// 取得済みの時間帯を記録するファイル (デフォルト: <out-dir>/.watch-state.json)
+    #[arg(long = "state", value_name = "PATH")]
+    pub state: Option<String>,
+
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+}
+
+/// `run` サブコマンドのオプション定義
+#[derive(Args)]
+pub struct RunArgs {
+    /// ジョブファイル (TOML)
+    #[arg(required = true, value_name = "PATH")]
+    pub jobs: String,
+
+    /// 同時に実行するジョブの最大数 (デフォルト: ジョブファイルの parallel、未指定時は 1)
+    #[arg(long = "parallel", value_name = "N")]
+    pub parallel: Option<usize>,
+    /// 1秒あたりの最大リクエスト数。すべてのジョブで共有する (デフォルト: ジョブファイルの rate、未指定時は 1)
+    #[arg(long = "rate", value_name = "RPS")]
+    pub rate: Option<f64>,
+
+    #[command(flatten)]
+    pub endpoint: EndpointArgs,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// ドライランとして実行し、データの取得・保存を行わない
+    #[arg(long = "dry")]
+    pub dry: bool,
+}
+
+/// データの取得先の WFS のオプション定義
+///
+/// 環境変数でも指定できる。コマンドラインの指定を優先し、いずれも未指定のときは設定ファイルの値を使用する。
+#[derive(Args, Default, Clone)]
+pub struct EndpointArgs {
+    /// WFS のエンドポイントの URL (デフォルト: https://api.jartic-open-traffic.org/geoserver)
+    #[arg(long = "endpoint", value_name = "URL", env = "TRAFFIC_DL_ENDPOINT")]
+    pub endpoint: Option<String>,
+    /// 取得するデータの座標参照系 (デフォルト: EPSG:4326)
+    #[arg(long = "srs-name", value_name = "CRS", env = "TRAFFIC_DL_SRS_NAME")]
+    pub srs_name: Option<String>,
+    /// 出力形式。GeoJSON を返す形式を指定する (デフォルト: application/json)
+    #[arg(long = "output-format", value_name = "FORMAT", env = "TRAFFIC_DL_OUTPUT_FORMAT")]
+    pub output_format: Option<String>,
+
+    /// 常設トラカン・1時間のレイヤー名 (デフォルト: t_travospublic_measure_1h)
+    #[arg(long = "layer-permanent-1h", value_name = "NAME", env = "TRAFFIC_DL_LAYER_PERMANENT_1H")]
+    pub layer_permanent_1h: Option<String>,
+    /// 常設トラカン・5分のレイヤー名 (デフォルト: t_travospublic_measure_5m)
+    #[arg(long = "layer-permanent-5m", value_name = "NAME", env = "TRAFFIC_DL_LAYER_PERMANENT_5M")]
+    pub layer_permanent_5m: Option<String>,
+    /// CCTVトラカン・1時間のレイヤー名 (デフォルト: t_travospublic_measure_1h_img)
+    #[arg(long = "layer-cctv-1h", value_name = "NAME", env = "TRAFFIC_DL_LAYER_CCTV_1H")]
+    pub layer_cctv_1h: Option<String>,
+    /// CCTVトラカン・5分のレイヤー名 (デフォルト: t_travospublic_measure_5m_img)
+    #[arg(long = "layer-cctv-5m", value_name = "NAME", env = "TRAFFIC_DL_LAYER_CCTV_5M")]
+    pub layer_cctv_5m: Option<String>,
+}
+
+/// 設定ファイルのオプション定義
+#[derive(Args, Default, Clone)]
+pub struct ConfigArgs {
+    /// 設定ファイル。省略時はカレントディレクトリ、XDG の設定ディレクトリ (~/.config/traffic-dl) の順に traffic-dl.toml を探す
+    #[arg(long = "config", value_name = "PATH", env = "TRAFFIC_DL_CONFIG")]
+    pub config: Option<String>,
+    /// 設定ファイルの `[profile.NAME]` の設定を使用する
+    #[arg(long = "profile", value_name = "NAME", env = "TRAFFIC_DL_PROFILE")]
+    pub profile: Option<String>,
+    /// 設定ファイルを読み込まない。--config・環境変数 TRAFFIC_DL_CONFIG の指定も無視する
+    #[arg(long = "no-config")]
+    pub no_config: bool,
+}
+
 /// データの取得間隔
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum Interval {
     /// 1時間ごと
+    #[serde(rename = "1h")]
     H1,
     /// 5分ごと
+    #[serde(rename = "5m")]
     M5,
 }
 
 /// 観測機器
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CounterType {
     /// 常設トラカン
     Permanent,
@@ -54,7 +320,8 @@ pub enum CounterType {
 }
 
 /// 道路種別
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RoadType {
     /// 高速自動車国道
     Highway,
@@ -63,3 +330,70 @@ pub enum RoadType {
     /// 高速自動車国道と一般国道の両方
     Both,
 }
+
+/// 正常なデータ以外(データなし・サーバー例外・不正なレスポンス)を取得したときの扱い
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnomalyAction {
+    /// 正常なデータと同様に保存する
+    Save,
+    /// 保存しない
+    Skip,
+    /// 隔離用のディレクトリへ保存する
+    Quarantine,
+}
+
+/// 取得したデータの保存先
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    /// `--out-dir` のディレクトリへ、1つのデータを1つの JSON ファイルとして保存する
+    Dir,
+    /// 標準出力へ、正常なデータを1行に1つずつ出力する
+    Stdout,
+    /// `--parquet` のディレクトリへ Parquet 形式で保存する
+    Parquet,
+    /// `--sqlite` のデータベースへ保存する
+    Sqlite,
+}
+
+/// `--out-dir` へ保存するファイルの圧縮形式
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressFormat {
+    /// gzip (`.gz`)
+    Gzip,
+    /// zstd (`.zst`)
+    Zstd,
+}
+
+/// Parquet に保存する観測地点の位置の形式
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeometryEncoding {
+    /// 経度 (`lon`)・緯度 (`lat`) の2つの列
+    Lonlat,
+    /// WKB 形式の Point (`geometry`) の列
+    Wkb,
+}
+
+/// CSV の文字コード
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CsvEncoding {
+    /// UTF-8 (BOM なし)
+    Utf8,
+    /// UTF-8 (BOM 付き)。Excel で開く場合に使用する
+    Utf8Bom,
+    /// Shift_JIS
+    #[value(name = "sjis", alias = "shift-jis")]
+    ShiftJis,
+}
+
+/// `describe` サブコマンドの出力形式
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DescribeFormat {
+    /// 表形式
+    Table,
+    /// JSON
+    Json,
+}
@@ -1,45 +1,113 @@
+use chrono::{NaiveDateTime, TimeDelta, Timelike};
+
+use crate::calendar::SlotFilter;
+use crate::cql::{Expr, Value};
 use crate::datetime::DT;
+use crate::endpoint::Endpoint;
 use crate::execution_option::ExecutionOption;
+use crate::spatial::BBox;
 use crate::types::*;
 
-const URL_1: &str = "https://api.jartic-open-traffic.org/geoserver?service=WFS&version=2.0.0&request=GetFeature&typeNames=";
-const URL_2: &str = "&srsName=EPSG:4326&outputFormat=application/json&exceptions=application/json&cql_filter=";
+/// 空間条件で絞り込む際の、ジオメトリの属性名
+const GEOMETRY: &str = "ジオメトリ";
+
+/// データの取得時に絞り込み条件として使用する属性
+pub const QUERY_ATTRIBUTES: [&str; 4] = ["道路種別", "時間コード", "常時観測点コード", GEOMETRY];
+
+/// 取得対象のデータ
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// 時間コード (YYYYMMDDHHMM)
+    pub time: String,
+    /// 取得間隔
+    pub interval: Interval,
+    /// 道路種別
+    pub road_type: RoadType,
+    /// 観測機器
+    pub counter_type: CounterType,
+    /// 保存に使用するファイル名(拡張子なし)
+    pub name: String,
+    /// 取得先URL
+    pub url: String,
+}
+
+/// 道路種別と時間コード以外の絞り込み条件
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Filter {
+    /// 常時観測点コード。空のときは絞り込まない
+    pub stations: Vec<String>,
+    /// 観測地点が含まれる矩形範囲
+    pub bbox: Option<BBox>,
+    /// 観測地点が含まれる多角形 (WKT)
+    pub within: Option<String>,
+}
+
+impl Filter {
+    /// 絞り込み条件が指定されていないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty() && self.bbox.is_none() && self.within.is_none()
+    }
+}
 
-/// ファイル名と取得先 URL のタプルのリストを生成する
-pub fn create_names_and_urls(option: &ExecutionOption) -> Vec<(String, String)> {
+/// 取得対象のデータのリストを生成する
+pub fn create_targets(option: &ExecutionOption) -> Vec<Target> {
     let mut output = vec![];
 
     // 1時間ごとのデータ取得時
+    // 分まで指定されているときはリストが空になるため、何も追加されない
     if option.interval_h1 {
-        match option.datetime {
-            DT::Ymd { .. } | DT::Ymdh { .. } => {
-                let list = get_datetime_list_1h(&option.datetime);
-                let road_type = option.road_type();
-
-                for t in list {
-                    if option.type_permanent {
-                        output.push(get_target(&t, &Interval::H1, &road_type, &CounterType::Permanent));
-                    }
-                    if option.type_cctv {
-                        output.push(get_target(&t, &Interval::H1, &road_type, &CounterType::Cctv));
-                    }
-                }
+        let list = get_datetime_list_1h(&option.datetime, &option.slot_filter);
+        let road_type = option.road_type();
+
+        for t in list {
+            if option.type_permanent {
+                output.push(get_target(
+                    &t,
+                    &Interval::H1,
+                    &road_type,
+                    &CounterType::Permanent,
+                    &option.filter,
+                    &option.endpoint,
+                ));
+            }
+            if option.type_cctv {
+                output.push(get_target(
+                    &t,
+                    &Interval::H1,
+                    &road_type,
+                    &CounterType::Cctv,
+                    &option.filter,
+                    &option.endpoint,
+                ));
             }
-            _ => {}
         }
     }
 
     // 5分間ごとのデータ取得時
     if option.interval_m5 {
-        let list = get_datetime_list_5m(&option.datetime);
+        let list = get_datetime_list_5m(&option.datetime, &option.slot_filter);
         let road_type = option.road_type();
 
         for t in list {
             if option.type_permanent {
-                output.push(get_target(&t, &Interval::M5, &road_type, &CounterType::Permanent));
+                output.push(get_target(
+                    &t,
+                    &Interval::M5,
+                    &road_type,
+                    &CounterType::Permanent,
+                    &option.filter,
+                    &option.endpoint,
+                ));
             }
             if option.type_cctv {
-                output.push(get_target(&t, &Interval::M5, &road_type, &CounterType::Cctv));
+                output.push(get_target(
+                    &t,
+                    &Interval::M5,
+                    &road_type,
+                    &CounterType::Cctv,
+                    &option.filter,
+                    &option.endpoint,
+                ));
             }
         }
     }
@@ -50,62 +118,75 @@ pub fn create_names_and_urls(option: &ExecutionOption) -> Vec<(String, String)>
 /// 1時間ごとのデータを取得するため、取得対象日時の配列を生成する
 /// - 年月日のみが指定されている場合は、1日分のリストを返す
 /// - 年月日と時が指定されている場合は、1時間分のみを返す
-/// - それ以外の場合は空配列を返す
-pub fn get_datetime_list_1h(dt: &DT) -> Vec<String> {
-    match dt {
-        DT::Ymd { string, .. } => {
-            let mut output = Vec::new();
-            for h in 0..24 {
-                let h_str = format!("{:02}", h);
-                output.push(format!("{}{}00", string, h_str));
-            }
-            output
-        }
-        DT::Ymdh { string, .. } => {
-            vec![format!("{}00", string)]
-        }
-        _ => vec![],
+/// - 範囲指定の場合は、開始から終了までの期間のリストを返す
+/// - それ以外(分まで指定されている場合)は空配列を返す
+///
+/// 絞り込み条件を満たさない日時は含まない。
+pub fn get_datetime_list_1h(dt: &DT, filter: &SlotFilter) -> Vec<String> {
+    if dt.has_minute() {
+        return vec![];
     }
+
+    let (start, end) = dt.span();
+    time_codes(start, end, TimeDelta::hours(1), filter)
 }
 
 /// 5分ごとのデータを取得するため、取得対象日時の配列を生成する
 /// - 年月日が指定されている場合は、1日分のリストを返す
 /// - 年月日と時が指定されている場合は、1時間分のリストを返す
 /// - 年月日と時分が指定されている場合は、当該時分のみのリストを返す
-/// - それ以外の場合は空配列を返す
-pub fn get_datetime_list_5m(dt: &DT) -> Vec<String> {
+/// - 範囲指定の場合は、開始から終了までの期間のリストを返す
+///
+/// 絞り込み条件を満たさない日時は含まない。
+pub fn get_datetime_list_5m(dt: &DT, filter: &SlotFilter) -> Vec<String> {
     match dt {
-        DT::Ymd { string, .. } => {
-            let mut output = Vec::new();
-            for h in 0..24 {
-                for m in (0..60).step_by(5) {
-                    let hm_str = format!("{:02}{:02}", h, m);
-                    output.push(format!("{}{}", string, hm_str));
-                }
-            }
-            output
-        }
-        DT::Ymdh { string, .. } => {
-            let mut output = Vec::new();
-            for m in (0..60).step_by(5) {
-                let m_str = format!("{:02}", m);
-                output.push(format!("{}{}", string, m_str));
-            }
-            output
-        }
         DT::Ymdhm { string, .. } => {
             // 分の指定が 5 の倍数でなくても、サーバー側で切り捨てられるため問題ない
-            vec![format!("{}", string)]
+            if filter.matches(dt.span().0) {
+                vec![string.to_string()]
+            } else {
+                vec![]
+            }
+        }
+        _ => {
+            let (start, end) = dt.span();
+            // 範囲指定の開始が 5 の倍数でない場合は、切り捨てた時分から開始する
+            let start = start - TimeDelta::minutes(start.minute() as i64 % 5);
+            time_codes(start, end, TimeDelta::minutes(5), filter)
         }
     }
 }
 
-/// 保存に使用するファイル名と取得先URLを取得する
-fn get_target(time: &str, interval: &Interval, road_type: &RoadType, counter_type: &CounterType) -> (String, String) {
-    let name = create_filename(time, interval, road_type, counter_type);
-    let url = create_url(time, interval, road_type, counter_type);
+/// 開始日時(含む)から終了日時(含まない)まで、指定間隔ごとの時間コードのうち、絞り込み条件を満たすものの配列を生成する
+fn time_codes(start: NaiveDateTime, end: NaiveDateTime, step: TimeDelta, filter: &SlotFilter) -> Vec<String> {
+    let mut output = Vec::new();
+    let mut t = start;
+    while t < end {
+        if filter.matches(t) {
+            output.push(t.format("%Y%m%d%H%M").to_string());
+        }
+        t += step;
+    }
+    output
+}
 
-    (name, url)
+/// 保存に使用するファイル名と取得先URLを含む、取得対象のデータを生成する
+fn get_target(
+    time: &str,
+    interval: &Interval,
+    road_type: &RoadType,
+    counter_type: &CounterType,
+    filter: &Filter,
+    endpoint: &Endpoint,
+) -> Target {
+    Target {
+        time: time.to_string(),
+        interval: *interval,
+        road_type: *road_type,
+        counter_type: *counter_type,
+        name: create_filename(time, interval, road_type, counter_type),
+        url: create_url(time, interval, road_type, counter_type, filter, endpoint),
+    }
 }
 
 /// 保存に使用するファイル名(拡張子なし)を生成する
@@ -126,36 +207,155 @@ fn create_filename(time: &str, interval: &Interval, _road_type: &RoadType, count
 }
 
 /// 取得対象のURLを生成する
-fn create_url(time: &str, interval: &Interval, road_type: &RoadType, counter_type: &CounterType) -> String {
+pub fn create_url(
+    time: &str,
+    interval: &Interval,
+    road_type: &RoadType,
+    counter_type: &CounterType,
+    filter: &Filter,
+    endpoint: &Endpoint,
+) -> String {
     // 取得対象データの種別。カウンターの種類と間隔に基づく
-    let target = match counter_type {
-        CounterType::Permanent => match interval {
-            Interval::H1 => "t_travospublic_measure_1h",
-            Interval::M5 => "t_travospublic_measure_5m",
-        },
-        CounterType::Cctv => match interval {
-            Interval::H1 => "t_travospublic_measure_1h_img",
-            Interval::M5 => "t_travospublic_measure_5m_img",
-        },
-    };
+    let target = endpoint.layers.get(*interval, *counter_type);
 
+    // 道路種別。両方を対象とするときは OR 条件とする
+    let road = |code: &str| Expr::eq("道路種別", Value::Text(code.into()));
     let road = match road_type {
-        RoadType::Highway => "道路種別='1'",
-        RoadType::Normal => "道路種別='3'",
-        RoadType::Both => "道路種別='1' OR 道路種別='3'",
+        RoadType::Highway => road("1"),
+        RoadType::Normal => road("3"),
+        RoadType::Both => Expr::Or(vec![road("1"), road("3")]),
     };
 
-    // NOTE: デバッグ用に常時観測点コードを絞る場合は、以下のように設定する
-    // "{}{}{}道路種別='{}' AND 時間コード={} AND 常時観測点コード=3310840",
+    // 時間コードは数字のみの文字列として生成済み
+    let time = time.parse().expect("時間コードは数字のみ");
+    let mut conditions = vec![road, Expr::eq("時間コード", Value::Integer(time))];
+
+    // 常時観測点コードによる絞り込み
+    if !filter.stations.is_empty() {
+        // 常時観測点コードの属性は数値型のため、文字列ではなく数値として比較する
+        // コードは数字のみで、先頭の 0 を取り除いた表記であることを検証済み
+        let codes = filter
+            .stations
+            .iter()
+            .map(|code| Value::Integer(code.parse().expect("常時観測点コードは数字のみ")));
+        conditions.push(Expr::is_in("常時観測点コード", codes.collect()));
+    }
 
-    // NOTE: 道路種別は OR 条件で複数指定されることがあるため、 () でくくる
-    format!("{}{}{}({}) AND 時間コード={}", URL_1, target, URL_2, road, time)
+    // 空間条件による絞り込み
+    if let Some(bbox) = &filter.bbox {
+        conditions.push(Expr::BBox {
+            attr: GEOMETRY.into(),
+            bbox: bbox.clone(),
+        });
+    }
+    if let Some(wkt) = &filter.within {
+        conditions.push(Expr::Intersects {
+            attr: GEOMETRY.into(),
+            wkt: wkt.clone(),
+        });
+    }
+
+    format!("{}&cql_filter={}", endpoint.get_feature_url(target), Expr::And(conditions).encode())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[cfg(test)]
+    mod create_url {
+        use super::*;
+
+        /// URL の `cql_filter` パラメーターをデコードして取得する
+        fn cql_filter(url: &str) -> String {
+            let (_, encoded) = url.split_once("&cql_filter=").unwrap();
+            percent_encoding::percent_decode_str(encoded).decode_utf8().unwrap().to_string()
+        }
+
+        #[test]
+        fn no_filter() {
+            let url = create_url(
+                "202501020300",
+                &Interval::H1,
+                &RoadType::Both,
+                &CounterType::Permanent,
+                &Filter::default(),
+                &Endpoint::default(),
+            );
+
+            assert!(
+                url.starts_with("https://api.jartic-open-traffic.org/geoserver?service=WFS&version=2.0.0&request=GetFeature&typeNames=")
+            );
+            assert!(url.contains("t_travospublic_measure_1h&srsName=EPSG:4326&outputFormat=application/json&exceptions=application/json&"));
+            assert_eq!(cql_filter(&url), "(道路種別='1' OR 道路種別='3') AND 時間コード=202501020300");
+        }
+
+        #[test]
+        fn stations() {
+            let filter = Filter {
+                stations: vec!["3310840".into(), "3310850".into()],
+                ..Default::default()
+            };
+            let url = create_url(
+                "202501020305",
+                &Interval::M5,
+                &RoadType::Highway,
+                &CounterType::Cctv,
+                &filter,
+                &Endpoint::default(),
+            );
+
+            assert!(url.contains("typeNames=t_travospublic_measure_5m_img&"));
+            assert_eq!(
+                cql_filter(&url),
+                "道路種別='1' AND 時間コード=202501020305 AND 常時観測点コード IN (3310840,3310850)"
+            );
+        }
+
+        #[test]
+        fn spatial() {
+            let filter = Filter {
+                bbox: Some(BBox {
+                    min_lon: 139.5,
+                    min_lat: 35.5,
+                    max_lon: 140.0,
+                    max_lat: 35.9,
+                }),
+                within: Some("POLYGON((139 35,140 35,140 36,139 35))".into()),
+                ..Default::default()
+            };
+            let url = create_url(
+                "202501020300",
+                &Interval::H1,
+                &RoadType::Normal,
+                &CounterType::Permanent,
+                &filter,
+                &Endpoint::default(),
+            );
+
+            assert_eq!(
+                cql_filter(&url),
+                "道路種別='3' AND 時間コード=202501020300 AND BBOX(ジオメトリ,139.5,35.5,140,35.9,'EPSG:4326') AND INTERSECTS(ジオメトリ,POLYGON((139 35,140 35,140 36,139 35)))"
+            );
+        }
+
+        #[test]
+        fn encoded() {
+            let url = create_url(
+                "202501020300",
+                &Interval::H1,
+                &RoadType::Highway,
+                &CounterType::Permanent,
+                &Filter::default(),
+                &Endpoint::default(),
+            );
+
+            // 日本語の属性名や空白、引用符がパーセントエンコードされていることを確認
+            assert!(url.is_ascii());
+            assert!(url.ends_with("&cql_filter=%E9%81%93%E8%B7%AF%E7%A8%AE%E5%88%A5%3D%271%27%20AND%20%E6%99%82%E9%96%93%E3%82%B3%E3%83%BC%E3%83%89%3D202501020300"));
+        }
+    }
+
     #[cfg(test)]
     mod get_datetime_list_1h {
         use super::*;
@@ -165,7 +365,7 @@ mod tests {
         fn ymd() {
             let dt = datetime::parse("20250102").unwrap();
 
-            let result = get_datetime_list_1h(&dt);
+            let result = get_datetime_list_1h(&dt, &SlotFilter::default());
 
             // 24時間分のデータが返されることを確認
             assert_eq!(result.len(), 24);
@@ -180,13 +380,44 @@ mod tests {
         fn ymdh() {
             let dt = datetime::parse("2025010203").unwrap();
 
-            let result = get_datetime_list_1h(&dt);
+            let result = get_datetime_list_1h(&dt, &SlotFilter::default());
 
             // 1時間分のみが返されることを確認
             assert_eq!(result.len(), 1);
             // 返された要素が正しい形式であること確認
             assert_eq!(result[0], "202501020300");
         }
+
+        #[test]
+        fn ymdhm() {
+            let dt = datetime::parse("202501020304").unwrap();
+
+            // 分まで指定されているときは空配列が返されることを確認
+            assert!(get_datetime_list_1h(&dt, &SlotFilter::default()).is_empty());
+        }
+
+        #[test]
+        fn range_ymd() {
+            let dt = datetime::parse("20250130-20250202").unwrap();
+
+            let result = get_datetime_list_1h(&dt, &SlotFilter::default());
+
+            // 月をまたいだ4日分のデータが返されることを確認
+            assert_eq!(result.len(), 24 * 4);
+            assert_eq!(result[0], "202501300000");
+            assert_eq!(result[24 * 2], "202502010000");
+            assert_eq!(result[result.len() - 1], "202502022300");
+        }
+
+        #[test]
+        fn range_ymdh() {
+            let dt = datetime::parse("2024123122..2025010101").unwrap();
+
+            let result = get_datetime_list_1h(&dt, &SlotFilter::default());
+
+            // 年をまたいだ4時間分のデータが返されることを確認
+            assert_eq!(result, vec!["202412312200", "202412312300", "202501010000", "202501010100"]);
+        }
     }
 
     #[cfg(test)]
@@ -198,7 +429,7 @@ mod tests {
         fn ymd() {
             let dt = datetime::parse("20250102").unwrap();
 
-            let result = get_datetime_list_5m(&dt);
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
 
             // 1日分のデータが返されることを確認
             assert_eq!(result.len(), 288);
@@ -215,7 +446,7 @@ mod tests {
         fn ymdh() {
             let dt = datetime::parse("2025010203").unwrap();
 
-            let result = get_datetime_list_5m(&dt);
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
 
             // 1時間分のデータが返されることを確認
             assert_eq!(result.len(), 12);
@@ -230,12 +461,123 @@ mod tests {
         fn ymdhm() {
             let dt = datetime::parse("202501020304").unwrap();
 
-            let result = get_datetime_list_5m(&dt);
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
 
             // 1時間分のみが返されることを確認
             assert_eq!(result.len(), 1);
             // 返された要素が正しい形式であること確認
             assert_eq!(result[0], "202501020304");
         }
+
+        #[test]
+        fn range_ymd() {
+            let dt = datetime::parse("20250228..20250301").unwrap();
+
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
+
+            // 月をまたいだ2日分のデータが返されることを確認
+            assert_eq!(result.len(), 288 * 2);
+            assert_eq!(result[0], "202502280000");
+            assert_eq!(result[288], "202503010000");
+            assert_eq!(result[result.len() - 1], "202503012355");
+        }
+
+        #[test]
+        fn range_ymdh() {
+            let dt = datetime::parse("2025010223-2025010300").unwrap();
+
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
+
+            // 日をまたいだ2時間分のデータが返されることを確認
+            assert_eq!(result.len(), 24);
+            assert_eq!(result[0], "202501022300");
+            assert_eq!(result[12], "202501030000");
+            assert_eq!(result[23], "202501030055");
+        }
+
+        #[test]
+        fn range_ymdhm() {
+            let dt = datetime::parse("202501022352..202501030005").unwrap();
+
+            let result = get_datetime_list_5m(&dt, &SlotFilter::default());
+
+            // 開始は 5 分単位に切り捨てられ、終了を含むことを確認
+            assert_eq!(result, vec!["202501022350", "202501022355", "202501030000", "202501030005"]);
+        }
+    }
+
+    #[cfg(test)]
+    mod slot_filter {
+        use super::*;
+        use crate::calendar::{self, DayOfWeek, Holidays};
+        use crate::datetime;
+
+        #[test]
+        fn weekdays_and_hours() {
+            // 2025-01-03 (金)〜2025-01-06 (月)
+            let dt = datetime::parse("20250103..20250106").unwrap();
+            let filter = SlotFilter {
+                day_of_week: Some(DayOfWeek::Weekdays),
+                hours: calendar::parse_hours("7-9,17").unwrap(),
+                ..Default::default()
+            };
+
+            let result = get_datetime_list_1h(&dt, &filter);
+
+            // 土日を除いた2日分の、各3時間分が返されることを確認
+            assert_eq!(
+                result,
+                vec![
+                    "202501030700",
+                    "202501030800",
+                    "202501031700",
+                    "202501060700",
+                    "202501060800",
+                    "202501061700"
+                ]
+            );
+
+            // 5分ごとのデータは、各時間帯の12個分が返されることを確認
+            let result = get_datetime_list_5m(&dt, &filter);
+            assert_eq!(result.len(), 2 * 3 * 12);
+            assert_eq!(result[0], "202501030700");
+            assert_eq!(result[result.len() - 1], "202501061755");
+        }
+
+        #[test]
+        fn holidays() {
+            // 2025-01-13 (月) は成人の日
+            let dt = datetime::parse("20250111..20250114").unwrap();
+            let filter = SlotFilter {
+                day_of_week: Some(DayOfWeek::Weekdays),
+                holidays: Some(Holidays::Exclude),
+                ..Default::default()
+            };
+
+            let result = get_datetime_list_1h(&dt, &filter);
+            assert_eq!(result.len(), 24);
+            assert_eq!(result[0], "202501140000");
+
+            let filter = SlotFilter {
+                holidays: Some(Holidays::Only),
+                ..Default::default()
+            };
+            let result = get_datetime_list_1h(&dt, &filter);
+            assert_eq!(result.len(), 24);
+            assert_eq!(result[0], "202501130000");
+        }
+
+        #[test]
+        fn ymdhm() {
+            let dt = datetime::parse("202501040800").unwrap();
+            let filter = SlotFilter {
+                day_of_week: Some(DayOfWeek::Weekdays),
+                ..Default::default()
+            };
+
+            // 分まで指定したときも、条件を満たさなければ空配列が返されることを確認
+            assert!(get_datetime_list_5m(&dt, &filter).is_empty());
+            assert_eq!(get_datetime_list_5m(&dt, &SlotFilter::default()), vec!["202501040800"]);
+        }
     }
 }
@@ -0,0 +1,796 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, ensure};
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::client::Client;
+use crate::datetime::{self, DT, now_jst};
+use crate::execution_option::ExecutionOption;
+use crate::response::{self, ResponseKind};
+use crate::runner::{self, Reporter, Summary};
+use crate::types::{Interval, WatchArgs};
+use crate::{sink, template, url};
+
+/// 時間コードの書式
+const TIME_CODE: &str = "%Y%m%d%H%M";
+
+/// 取得済みの時間帯を記録するファイルの、保存先のディレクトリ内でのファイル名
+const STATE_FILE: &str = ".watch-state.json";
+
+/// `watch` の実行時のオプション
+#[derive(Debug)]
+pub struct WatchOption {
+    /// 取得・保存のオプション。日時指定と取得間隔は、時間帯ごとに変更して使用する
+    pub fetch: ExecutionOption,
+    /// 取得する間隔
+    pub intervals: Vec<Interval>,
+    /// 時間帯の終了から、データの公開の確認を始めるまでの待ち時間
+    pub delay: TimeDelta,
+    /// データが公開されていないときに、再確認するまでの間隔
+    pub poll_interval: Duration,
+    /// 公開の確認を始めてから、データの公開を待つ最大時間
+    pub max_wait: TimeDelta,
+    /// 停止中に取得できなかった時間帯を、遡って取得する最大期間
+    pub max_backfill: TimeDelta,
+    /// 取得済みの時間帯を記録するファイル
+    pub state_path: PathBuf,
+}
+
+impl WatchOption {
+    /// コマンドラインの実行時オプションから生成する
+    pub fn from_args(args: &WatchArgs) -> Result<Self> {
+        ensure!(
+            args.fetch.date.is_none(),
+            "watch では日時を指定できない。公開された最新の時間帯を自動的に取得する"
+        );
+        ensure!(!args.fetch.one, "watch では --one を指定できない");
+
+        // 日時指定は時間帯ごとに変更するため、ここでは最新の時間帯を仮に指定する
+        let slot = latest_slot(now_jst(), Interval::H1, TimeDelta::zero());
+        let fetch = ExecutionOption::with_datetime(&args.fetch, slot_datetime(slot, Interval::H1))?;
+
+        let mut intervals = vec![];
+        if fetch.interval_h1 {
+            intervals.push(Interval::H1);
+        }
+        if fetch.interval_m5 {
+            intervals.push(Interval::M5);
+        }
+
+        let poll_interval = args.poll_interval.unwrap_or(60);
+        ensure!(poll_interval > 0, "再確認の間隔は1秒以上で指定する必要がある");
+        let max_backfill = args.max_backfill.unwrap_or(24);
+        ensure!(max_backfill > 0, "遡って取得する最大期間は1時間以上で指定する必要がある");
+
+        let state_path = match &args.state {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(&fetch.out_dir).join(STATE_FILE),
+        };
+
+        Ok(WatchOption {
+            fetch,
+            intervals,
+            delay: TimeDelta::minutes(args.delay.unwrap_or(10).into()),
+            poll_interval: Duration::from_secs(poll_interval),
+            max_wait: TimeDelta::minutes(args.max_wait.unwrap_or(60).into()),
+            max_backfill: TimeDelta::hours(max_backfill.into()),
+            state_path,
+        })
+    }
+}
+
+/// 公開された最新の時間帯のデータを、終了を要求されるまで継続して取得する
+///
+/// 記録済みの時間帯の次から最新の時間帯まで、停止中に取得できなかった時間帯も含めて古い順に取得し、
+/// 次の時間帯の公開まで待機する。データが未公開(データなし)のときは、公開を待つ最大時間まで再確認を繰り返す。観測地点・範囲で絞り込んでいるときは、データなしも確定とする。
+/// 取得に失敗した時間帯や、公開を待つ最大時間を超えても公開されなかった時間帯は、次回以降に再取得する。
+/// ドライランのときは、現時点で取得対象となる時間帯の URL を表示して終了する。
+pub async fn watch(option: &mut WatchOption, mut shutdown: Shutdown, reporter: Arc<dyn Reporter>) -> Result<Summary> {
+    let client = Client::from_option(&option.fetch).with_reporter(reporter);
+    let mut state = State::load(&option.state_path)?;
+    let mut summary = Summary::default();
+
+    loop {
+        if !watch_once(option, &client, &mut state, &mut shutdown, &mut summary).await? || option.fetch.dry {
+            break;
+        }
+
+        // 次の時間帯の公開まで待機する
+        let now = now_jst();
+        let next = option
+            .intervals
+            .iter()
+            .map(|&interval| publication_time(latest_slot(now, interval, option.delay) + step(interval), interval, option.delay))
+            .min()
+            .context("取得間隔が指定されていない")?;
+        if !shutdown.sleep((next - now).to_std().unwrap_or_default()).await {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// 現時点で取得対象となる時間帯を、取得間隔ごとに古い順に取得し、取得結果を記録する
+///
+/// 再取得を待っている時間帯と、記録済みの最後の時間帯より後の時間帯を対象とする。
+/// 終了を要求されて中断したときは false を返す。
+async fn watch_once(
+    option: &mut WatchOption,
+    client: &Client,
+    state: &mut State,
+    shutdown: &mut Shutdown,
+    summary: &mut Summary,
+) -> Result<bool> {
+    let now = now_jst();
+    for interval in option.intervals.clone() {
+        let latest = latest_slot(now, interval, option.delay);
+        let earliest = earliest_slot(latest, interval, option.max_backfill);
+        let (retries, expired) = state.retries(interval, earliest)?;
+        let (slots, dropped) = pending_slots(state.last(interval)?, latest, interval, option.max_backfill);
+        if dropped + expired > 0 {
+            client
+                .reporter()
+                .error(&format!("遡って取得する最大期間を超えた {} 件の時間帯を省略", dropped + expired));
+        }
+
+        for slot in retries.into_iter().chain(slots) {
+            if shutdown.is_requested() {
+                return Ok(false);
+            }
+            let result = fetch_slot(option, client, slot, interval, shutdown, summary).await?;
+            if result == SlotResult::Interrupted {
+                return Ok(false);
+            }
+            if option.fetch.dry {
+                continue;
+            }
+
+            // 取得できなかった時間帯は、再取得を待つ時間帯として記録する
+            if result == SlotResult::Completed {
+                state.remove_retry(interval, slot);
+            } else {
+                state.add_retry(interval, slot);
+            }
+            if state.last(interval)?.is_none_or(|last| last < slot) {
+                state.set(interval, slot);
+            }
+            state.save(&option.state_path)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// 1つの時間帯の取得結果
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SlotResult {
+    /// すべてのデータを取得・保存した
+    Completed,
+    /// 取得・保存に失敗した、または公開を待つ最大時間を超えても公開されなかったデータがある
+    Incomplete,
+    /// 終了を要求されて中断した
+    Interrupted,
+}
+
+/// 1つの時間帯のデータを取得して保存する
+async fn fetch_slot(
+    option: &mut WatchOption,
+    client: &Client,
+    slot: NaiveDateTime,
+    interval: Interval,
+    shutdown: &mut Shutdown,
+    summary: &mut Summary,
+) -> Result<SlotResult> {
+    let reporter = client.reporter();
+    option.fetch.datetime = slot_datetime(slot, interval);
+    option.fetch.interval_h1 = interval == Interval::H1;
+    option.fetch.interval_m5 = interval == Interval::M5;
+    let published = publication_time(slot, interval, option.delay);
+    let deadline = published + option.max_wait;
+
+    let fetch = &option.fetch;
+    // 観測地点・範囲で絞り込んでいるときは、公開済みの時間帯でも該当する地物がなくデータなしとなりうる。
+    // 未公開と区別できないため、公開時刻を過ぎていればデータなしを確定とする
+    let filtered = !fetch.filter.is_empty();
+    let mut sinks = if fetch.dry { vec![] } else { sink::from_option(fetch)? };
+    let mut result = SlotResult::Completed;
+
+    for target in url::create_targets(fetch) {
+        let path = template::render(&fetch.name_template, &target);
+
+        if fetch.resume && runner::is_saved(&path, fetch).await {
+            reporter.progress(&format!("{}: 保存済みのため省略", target.name));
+            summary.skipped += 1;
+            continue;
+        }
+        if fetch.dry {
+            reporter.progress(&format!("{} - {}", target.name, target.url));
+            continue;
+        }
+
+        // データなしのときは未公開とみなし、公開を待つ最大時間まで再確認する
+        let fetched = loop {
+            let fetched = client.fetch_target(&target).await;
+            let unpublished = matches!(&fetched, Ok(content) if response::classify(content) == ResponseKind::Empty)
+                && !(filtered && now_jst() >= published);
+            if !unpublished {
+                break Some(fetched);
+            }
+            if now_jst() >= deadline {
+                break None;
+            }
+
+            reporter.progress(&format!(
+                "{}: 未公開のため {} 秒後に再確認",
+                target.name,
+                option.poll_interval.as_secs()
+            ));
+            if !shutdown.sleep(option.poll_interval).await {
+                result = SlotResult::Interrupted;
+                break None;
+            }
+        };
+
+        match fetched {
+            Some(Ok(content)) => {
+                let failures = summary.failures;
+                runner::save(&mut sinks, &target, &path, &content, summary, reporter).await;
+                if summary.failures > failures {
+                    result = SlotResult::Incomplete;
+                }
+            }
+            Some(Err(e)) => {
+                reporter.error(&format!("{} の取得に失敗: {:#} ({})", target.name, e, target.url));
+                summary.failures += 1;
+                result = SlotResult::Incomplete;
+            }
+            None if result == SlotResult::Interrupted => break,
+            None => {
+                reporter.progress(&format!("{}: 公開を待つ最大時間を超えたため、次回に再取得", target.name));
+                result = SlotResult::Incomplete;
+            }
+        }
+    }
+
+    for sink in sinks.iter_mut() {
+        sink.finish().await?;
+    }
+
+    Ok(result)
+}
+
+/// 取得間隔ごとの時間帯の長さ
+fn step(interval: Interval) -> TimeDelta {
+    match interval {
+        Interval::H1 => TimeDelta::hours(1),
+        Interval::M5 => TimeDelta::minutes(5),
+    }
+}
+
+/// 指定した日時を含む時間帯の開始日時
+fn floor(time: NaiveDateTime, interval: Interval) -> NaiveDateTime {
+    let step = step(interval).num_seconds();
+    let seconds = time.and_utc().timestamp();
+    DateTime::from_timestamp(seconds - seconds.rem_euclid(step), 0)
+        .expect("有効な日時")
+        .naive_utc()
+}
+
+/// 日本標準時の `now` の時点で公開済みとみなす、最新の時間帯の開始日時
+///
+/// 時間帯の終了から `delay` が経過したものを公開済みとみなす。
+pub fn latest_slot(now: NaiveDateTime, interval: Interval, delay: TimeDelta) -> NaiveDateTime {
+    floor(now - delay, interval) - step(interval)
+}
+
+/// 指定した時間帯のデータが公開されたとみなす日時
+pub fn publication_time(slot: NaiveDateTime, interval: Interval, delay: TimeDelta) -> NaiveDateTime {
+    slot + step(interval) + delay
+}
+
+/// 取得済みの最後の時間帯 `last` の次から、最新の時間帯 `latest` までの時間帯の開始日時
+///
+/// - `last` が None (初回の実行)のときは、最新の時間帯のみ
+/// - 最新の時間帯から `max_backfill` より前の時間帯は対象とせず、その数を返す
+pub fn pending_slots(
+    last: Option<NaiveDateTime>,
+    latest: NaiveDateTime,
+    interval: Interval,
+    max_backfill: TimeDelta,
+) -> (Vec<NaiveDateTime>, usize) {
+    let step = step(interval);
+    let earliest = earliest_slot(latest, interval, max_backfill);
+
+    let mut slots = vec![];
+    let mut dropped = 0;
+    let mut slot = last.map_or(latest, |last| last + step);
+    while slot <= latest {
+        if slot < earliest {
+            dropped += 1;
+        } else {
+            slots.push(slot);
+        }
+        slot += step;
+    }
+
+    (slots, dropped)
+}
+
+/// 最新の時間帯 `latest` から `max_backfill` の期間で、最も古い時間帯の開始日時
+fn earliest_slot(latest: NaiveDateTime, interval: Interval, max_backfill: TimeDelta) -> NaiveDateTime {
+    latest - max_backfill + step(interval)
+}
+
+/// 時間帯を取得する日時指定。1時間ごとは `YYYYMMDDHH`、5分ごとは `YYYYMMDDHHMM`
+fn slot_datetime(slot: NaiveDateTime, interval: Interval) -> DT {
+    let code = match interval {
+        Interval::H1 => slot.format("%Y%m%d%H").to_string(),
+        Interval::M5 => slot.format(TIME_CODE).to_string(),
+    };
+    datetime::parse(&code).expect("有効な日時指定")
+}
+
+/// 取得間隔ごとの、記録済みの最後の時間帯と、再取得を待っている時間帯の時間コード
+///
+/// 記録済みの最後の時間帯までのうち、取得できなかった時間帯は `retry` に記録する。
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(rename = "1h", default, skip_serializing_if = "Option::is_none")]
+    pub h1: Option<String>,
+    #[serde(rename = "5m", default, skip_serializing_if = "Option::is_none")]
+    pub m5: Option<String>,
+    /// 再取得を待っている時間帯
+    #[serde(default, skip_serializing_if = "Retry::is_empty")]
+    pub retry: Retry,
+}
+
+/// 取得間隔ごとの、再取得を待っている時間帯の時間コード。古い順
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Retry {
+    #[serde(rename = "1h", default, skip_serializing_if = "Vec::is_empty")]
+    pub h1: Vec<String>,
+    #[serde(rename = "5m", default, skip_serializing_if = "Vec::is_empty")]
+    pub m5: Vec<String>,
+}
+
+impl Retry {
+    fn is_empty(&self) -> bool {
+        self.h1.is_empty() && self.m5.is_empty()
+    }
+
+    fn codes(&mut self, interval: Interval) -> &mut Vec<String> {
+        match interval {
+            Interval::H1 => &mut self.h1,
+            Interval::M5 => &mut self.m5,
+        }
+    }
+}
+
+impl State {
+    /// ファイルから読み込む。ファイルが存在しないときは、いずれの時間帯も未取得とする
+    pub fn load(path: &Path) -> Result<State> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).with_context(|| format!("{} を解釈不能", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e).with_context(|| format!("{} を読み込めない", path.display())),
+        }
+    }
+
+    /// ファイルへ保存する。書き込みの途中で中断しても壊れないよう、一時ファイルへ書き込んでから置き換える
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_string(self)?).with_context(|| format!("{} へ保存できない", temp_path.display()))?;
+        std::fs::rename(&temp_path, path).with_context(|| format!("{} へ保存できない", path.display()))?;
+        Ok(())
+    }
+
+    /// 指定した取得間隔の、取得済みの最後の時間帯の開始日時
+    pub fn last(&self, interval: Interval) -> Result<Option<NaiveDateTime>> {
+        let code = match interval {
+            Interval::H1 => &self.h1,
+            Interval::M5 => &self.m5,
+        };
+        code.as_deref()
+            .map(|code| NaiveDateTime::parse_from_str(code, TIME_CODE).with_context(|| format!("記録された時間コード {} が不正", code)))
+            .transpose()
+    }
+
+    /// 指定した取得間隔の、取得済みの最後の時間帯を記録する
+    pub fn set(&mut self, interval: Interval, slot: NaiveDateTime) {
+        let code = Some(slot.format(TIME_CODE).to_string());
+        match interval {
+            Interval::H1 => self.h1 = code,
+            Interval::M5 => self.m5 = code,
+        }
+    }
+
+    /// 指定した取得間隔の、再取得を待っている時間帯の開始日時と、`earliest` より前のため省略した数
+    ///
+    /// 省略した時間帯は、再取得を待つ時間帯から除く。
+    pub fn retries(&mut self, interval: Interval, earliest: NaiveDateTime) -> Result<(Vec<NaiveDateTime>, usize)> {
+        let mut slots = vec![];
+        for code in self.retry.codes(interval).iter() {
+            slots.push(NaiveDateTime::parse_from_str(code, TIME_CODE).with_context(|| format!("記録された時間コード {} が不正", code))?);
+        }
+
+        let (slots, expired): (Vec<_>, Vec<_>) = slots.into_iter().partition(|&slot| slot >= earliest);
+        *self.retry.codes(interval) = slots.iter().map(|slot| slot.format(TIME_CODE).to_string()).collect();
+        Ok((slots, expired.len()))
+    }
+
+    /// 指定した取得間隔の、再取得を待つ時間帯として記録する
+    pub fn add_retry(&mut self, interval: Interval, slot: NaiveDateTime) {
+        let code = slot.format(TIME_CODE).to_string();
+        let codes = self.retry.codes(interval);
+        if !codes.contains(&code) {
+            codes.push(code);
+            codes.sort();
+        }
+    }
+
+    /// 指定した取得間隔の、再取得を待つ時間帯から除く
+    pub fn remove_retry(&mut self, interval: Interval, slot: NaiveDateTime) {
+        let code = slot.format(TIME_CODE).to_string();
+        self.retry.codes(interval).retain(|c| *c != code);
+    }
+}
+
+/// 終了の要求を受け取る
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: tokio::sync::watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// SIGINT (Ctrl+C)・SIGTERM を受信したときに終了を要求する。受信したことを `reporter` へ通知する
+    ///
+    /// 処理中の時間帯の取得・保存を完了してから終了する。2回目の受信時は、ただちに終了する。
+    pub fn on_signals(reporter: Arc<dyn Reporter>) -> Result<Shutdown> {
+        let (sender, shutdown) = Shutdown::channel();
+
+        #[cfg(unix)]
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let mut signal = async move || {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            #[cfg(not(unix))]
+            let _ = tokio::signal::ctrl_c().await;
+        };
+
+        tokio::spawn(async move {
+            signal().await;
+            reporter.progress("終了の要求を受信。処理中の時間帯の取得を完了してから終了する");
+            let _ = sender.send(true);
+
+            signal().await;
+            std::process::exit(130);
+        });
+
+        Ok(shutdown)
+    }
+
+    /// 終了を要求する送信側と組で生成する
+    pub fn channel() -> (tokio::sync::watch::Sender<bool>, Shutdown) {
+        let (sender, receiver) = tokio::sync::watch::channel(false);
+        (sender, Shutdown { receiver })
+    }
+
+    /// 終了を要求されたかどうか
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// 指定した時間だけ待機する。待機中に終了を要求されたときは false を返す
+    pub async fn sleep(&mut self, duration: Duration) -> bool {
+        if self.is_requested() {
+            return false;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => true,
+            Ok(_) = self.receiver.wait_for(|requested| *requested) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+    use crate::runner::Recorder;
+    use crate::types::Cli;
+    use clap::Parser;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, TIME_CODE).unwrap()
+    }
+
+    #[test]
+    fn latest() {
+        let delay = TimeDelta::minutes(10);
+
+        // 12:00〜12:05 の時間帯は、12:15 に公開済みとみなす
+        assert_eq!(latest_slot(time("202501021217"), Interval::M5, delay), time("202501021200"));
+        assert_eq!(latest_slot(time("202501021215"), Interval::M5, delay), time("202501021200"));
+        assert_eq!(latest_slot(time("202501021214"), Interval::M5, delay), time("202501021155"));
+
+        // 11時台の時間帯は、12:10 に公開済みとみなす
+        assert_eq!(latest_slot(time("202501021210"), Interval::H1, delay), time("202501021100"));
+        assert_eq!(latest_slot(time("202501021209"), Interval::H1, delay), time("202501021000"));
+        // 日付をまたぐ場合
+        assert_eq!(latest_slot(time("202501010005"), Interval::H1, delay), time("202412312200"));
+
+        assert_eq!(publication_time(time("202501021200"), Interval::M5, delay), time("202501021215"));
+    }
+
+    #[test]
+    fn pending() {
+        let latest = time("202501021200");
+        let backfill = TimeDelta::hours(1);
+
+        // 初回は最新の時間帯のみ
+        assert_eq!(pending_slots(None, latest, Interval::M5, backfill), (vec![latest], 0));
+        // 取得済み
+        assert_eq!(pending_slots(Some(latest), latest, Interval::M5, backfill), (vec![], 0));
+        // 停止中の時間帯を遡って取得する
+        assert_eq!(
+            pending_slots(Some(time("202501021145")), latest, Interval::M5, backfill),
+            (vec![time("202501021150"), time("202501021155"), latest], 0)
+        );
+        // 最大期間より前の時間帯は省略する
+        let (slots, dropped) = pending_slots(Some(time("202501020900")), latest, Interval::M5, backfill);
+        assert_eq!(slots.len(), 12);
+        assert_eq!(slots[0], time("202501021105"));
+        assert_eq!(dropped, 24);
+
+        let (slots, dropped) = pending_slots(Some(time("202501010000")), latest, Interval::H1, TimeDelta::hours(24));
+        assert_eq!((slots.len(), dropped), (24, 12));
+    }
+
+    #[test]
+    fn datetime() {
+        assert_eq!(
+            url::get_datetime_list_1h(&slot_datetime(time("202501021100"), Interval::H1), &Default::default()),
+            vec!["202501021100"]
+        );
+        assert_eq!(
+            url::get_datetime_list_5m(&slot_datetime(time("202501021155"), Interval::M5), &Default::default()),
+            vec!["202501021155"]
+        );
+    }
+
+    #[test]
+    fn state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join(STATE_FILE);
+
+        let mut state = State::load(&path).unwrap();
+        assert_eq!(state, State::default());
+
+        state.set(Interval::M5, time("202501021155"));
+        state.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"5m":"202501021155"}"#);
+
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.last(Interval::M5).unwrap(), Some(time("202501021155")));
+        assert_eq!(state.last(Interval::H1).unwrap(), None);
+
+        std::fs::write(&path, r#"{"1h":"2025010211"}"#).unwrap();
+        assert!(State::load(&path).unwrap().last(Interval::H1).is_err());
+    }
+
+    #[test]
+    fn retry() {
+        let mut state = State::default();
+        state.add_retry(Interval::H1, time("202501021100"));
+        state.add_retry(Interval::H1, time("202501020900"));
+        state.add_retry(Interval::H1, time("202501021100"));
+        assert_eq!(
+            serde_json::to_string(&state).unwrap(),
+            r#"{"retry":{"1h":["202501020900","202501021100"]}}"#
+        );
+
+        // 最大期間より前の時間帯は省略し、記録からも除く
+        let (slots, expired) = state.retries(Interval::H1, time("202501021000")).unwrap();
+        assert_eq!((slots, expired), (vec![time("202501021100")], 1));
+        assert_eq!(state.retry.h1, vec!["202501021100"]);
+
+        state.remove_retry(Interval::H1, time("202501021100"));
+        assert_eq!(serde_json::to_string(&state).unwrap(), "{}");
+    }
+
+    #[test]
+    fn from_args() {
+        let parse = |args: &[&str]| match Cli::try_parse_from(args.iter().copied().chain(["--no-config"])).unwrap().command {
+            Some(crate::types::Command::Watch(args)) => WatchOption::from_args(&args),
+            _ => unreachable!(),
+        };
+
+        let option = parse(&["traffic-dl", "watch", "--1h", "--5m", "--out-dir", "out"]).unwrap();
+        assert_eq!(option.intervals, vec![Interval::H1, Interval::M5]);
+        assert_eq!(option.delay, TimeDelta::minutes(10));
+        assert_eq!(option.state_path, Path::new("out").join(STATE_FILE));
+
+        assert!(parse(&["traffic-dl", "watch", "20250102"]).is_err());
+        assert!(parse(&["traffic-dl", "watch", "--poll-interval", "0"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn poll_until_published() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"type":"FeatureCollection","features":[]}"#))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{}}]}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().to_string_lossy().to_string();
+        let args = ["traffic-dl", "watch", "--5m", "--permanent", "--rate", "1000"];
+        let Some(crate::types::Command::Watch(args)) = Cli::try_parse_from(args.into_iter().chain(["--out-dir", &out_dir, "--no-config"]))
+            .unwrap()
+            .command
+        else {
+            unreachable!()
+        };
+        let mut option = WatchOption::from_args(&args).unwrap();
+        option.fetch.endpoint.url = server.uri();
+        option.poll_interval = Duration::ZERO;
+
+        let slot = latest_slot(now_jst(), Interval::M5, option.delay);
+        let client = Client::from_option(&option.fetch);
+        let (_sender, mut shutdown) = Shutdown::channel();
+        let mut summary = Summary::default();
+
+        let result = fetch_slot(&mut option, &client, slot, Interval::M5, &mut shutdown, &mut summary)
+            .await
+            .unwrap();
+
+        // 未公開の間は再確認し、公開されたデータのみを保存する
+        assert_eq!(result, SlotResult::Completed);
+        assert_eq!(
+            summary,
+            Summary {
+                fetched: 1,
+                skipped: 0,
+                failures: 0
+            }
+        );
+        let path = dir.path().join(format!("M{}P.json", slot.format(TIME_CODE)));
+        assert!(std::fs::read_to_string(path).unwrap().contains("Feature"));
+    }
+
+    #[tokio::test]
+    async fn empty_with_filter() {
+        // 観測地点で絞り込んでいるときは、公開時刻を過ぎたデータなしを再確認しない
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"type":"FeatureCollection","features":[]}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().to_string_lossy().to_string();
+        let args = [
+            "traffic-dl",
+            "watch",
+            "--5m",
+            "--permanent",
+            "--rate",
+            "1000",
+            "--station",
+            "3310840",
+        ];
+        let Some(crate::types::Command::Watch(args)) = Cli::try_parse_from(args.into_iter().chain(["--out-dir", &out_dir, "--no-config"]))
+            .unwrap()
+            .command
+        else {
+            unreachable!()
+        };
+        let mut option = WatchOption::from_args(&args).unwrap();
+        option.fetch.endpoint.url = server.uri();
+        option.poll_interval = Duration::ZERO;
+
+        let slot = latest_slot(now_jst(), Interval::M5, option.delay);
+        let client = Client::from_option(&option.fetch);
+        let (_sender, mut shutdown) = Shutdown::channel();
+        let mut summary = Summary::default();
+
+        let result = fetch_slot(&mut option, &client, slot, Interval::M5, &mut shutdown, &mut summary)
+            .await
+            .unwrap();
+
+        assert_eq!(result, SlotResult::Completed);
+        assert_eq!(summary.fetched, 1);
+    }
+
+    #[tokio::test]
+    async fn retry_failed_slot() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{}}]}"#),
+            )
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().to_string_lossy().to_string();
+        let args = ["traffic-dl", "watch", "--1h", "--permanent", "--rate", "1000", "--retries", "0"];
+        let Some(crate::types::Command::Watch(args)) = Cli::try_parse_from(args.into_iter().chain(["--out-dir", &out_dir, "--no-config"]))
+            .unwrap()
+            .command
+        else {
+            unreachable!()
+        };
+        let mut option = WatchOption::from_args(&args).unwrap();
+        option.fetch.endpoint.url = server.uri();
+
+        let reporter = Arc::new(Recorder::default());
+        let client = Client::from_option(&option.fetch).with_reporter(reporter.clone());
+        let (_sender, mut shutdown) = Shutdown::channel();
+        let mut summary = Summary::default();
+        let mut state = State::default();
+
+        // 取得に失敗した時間帯は、再取得を待つ時間帯として記録する
+        assert!(
+            watch_once(&mut option, &client, &mut state, &mut shutdown, &mut summary)
+                .await
+                .unwrap()
+        );
+        assert_eq!(summary.failures, 1);
+        assert!(reporter.errors.lock().unwrap()[0].contains("の取得に失敗"));
+        let slot = state.last(Interval::H1).unwrap().unwrap();
+        let saved = State::load(&option.state_path).unwrap();
+        assert_eq!(saved.retry.h1, vec![slot.format(TIME_CODE).to_string()]);
+        let path = dir.path().join(format!("H{}P.json", slot.format(TIME_CODE)));
+        assert!(!path.exists());
+
+        // 次回に再取得し、取得できたときは記録から除く
+        assert!(
+            watch_once(&mut option, &client, &mut state, &mut shutdown, &mut summary)
+                .await
+                .unwrap()
+        );
+        assert!(path.exists());
+        assert!(State::load(&option.state_path).unwrap().retry.h1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let (sender, mut shutdown) = Shutdown::channel();
+        assert!(shutdown.sleep(Duration::ZERO).await);
+
+        sender.send(true).unwrap();
+        assert!(shutdown.is_requested());
+        assert!(!shutdown.sleep(Duration::from_secs(3600)).await);
+    }
+}